@@ -446,6 +446,7 @@ async function minimalTest() {
             isSigner: false,
             isWritable: true,
           }, // Staker PDA,
+          { pubkey: mintAccount, isSigner: false, isWritable: false }, // HAPR mint
         ],
         data: Buffer.from(Uint8Array.of(5, ...new BN(amount).toArray("le", 8))), // Identifier `5` for `stake_tokens`
       });
@@ -524,6 +525,7 @@ async function minimalTest() {
             isSigner: false,
             isWritable: false,
           }, // Token Program
+          { pubkey: mintAccount, isSigner: false, isWritable: false }, // HAPR mint
         ],
         data: Buffer.from(Uint8Array.of(6, ...new BN(amount).toArray("le", 8))), // Identifier `6` for `unstake_tokens`
       });
@@ -601,7 +603,6 @@ async function minimalTest() {
             isSigner: false,
             isWritable: true,
           }, // Treasury token account
-          // { pubkey: treasuryPDA, isSigner: false, isWritable: false }, // Treasury PDA
           { pubkey: ticketPDA, isSigner: false, isWritable: true }, // Ticket PDA
           {
             pubkey: splToken.TOKEN_PROGRAM_ID,
@@ -613,6 +614,8 @@ async function minimalTest() {
             isSigner: false,
             isWritable: false,
           }, // Add System Program account here
+          { pubkey: treasuryPDA, isSigner: false, isWritable: false }, // Treasury PDA, read by the pause check
+          { pubkey: mintAccount, isSigner: false, isWritable: false }, // HAPR mint
         ],
         data: instructionData, // Encoded arguments
       });
@@ -650,6 +653,16 @@ async function minimalTest() {
         pg.wallet.publicKey // Owner
       );
 
+      // Derive the Treasury PDA's associated token account (principal is paid from here)
+      const treasuryTokenAccount =
+        await splToken.getOrCreateAssociatedTokenAccount(
+          pg.connection,
+          pg.wallet.keypair, // Payer
+          mintAccount, // Mint
+          treasuryPDA, // Owner
+          true
+        );
+
       console.log("Ticket PDA:", ticketPDA.toBase58());
       console.log("User Token Account:", userTokenAccount.address.toBase58());
 
@@ -665,12 +678,22 @@ async function minimalTest() {
             isSigner: false,
             isWritable: true,
           }, // Owner's token account
+          {
+            pubkey: treasuryTokenAccount.address,
+            isSigner: false,
+            isWritable: true,
+          }, // Treasury's token account
           { pubkey: treasuryPDA, isSigner: false, isWritable: false }, // Treasury PDA
           {
             pubkey: splToken.TOKEN_PROGRAM_ID,
             isSigner: false,
             isWritable: false,
           }, // Token program
+          {
+            pubkey: web3.SystemProgram.programId,
+            isSigner: false,
+            isWritable: false,
+          }, // System program (receipt history growth)
         ],
         data: Buffer.from(
           Uint8Array.of(8, ...new BN(amount).toArray("le", 8)) // Instruction identifier and arguments
@@ -0,0 +1,265 @@
+// Typed helpers for programs that stake and buy tickets through CPI.
+// Depend on the crate with the `cpi` feature (which implies `no-entrypoint`) to use them
+// from another program.
+
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::invoke_signed,
+    pubkey::Pubkey,
+    system_program,
+};
+
+const SEED: &[u8] = b"mintthissuperhyperAPRtoken"; // Seed for the HAPR mint PDA
+const TREASURY_SEED: &[u8] = b"treasurythissuperhyperAPRtoken";
+const TICKET_SEED: &[u8] = b"ticket_seed";
+const STAKER_SEED: &[u8] = b"staker"; // [user, seed]
+const WALLET_OUTFLOW_SEED: &[u8] = b"wallet_outflow"; // [seed, wallet]
+
+const STAKE: u8 = 5;
+const PURCHASE_TICKETS: u8 = 7;
+const REDEEM_TICKETS: u8 = 8;
+
+// Accounts for `stake`. `user` signs (with the caller's seeds when it is a PDA) and pays
+// for the staker account on the first stake.
+pub struct Stake<'a> {
+    pub user_token_account: AccountInfo<'a>,
+    pub treasury_token_account: AccountInfo<'a>,
+    pub treasury_pda: AccountInfo<'a>,
+    pub user: AccountInfo<'a>,
+    pub token_program: AccountInfo<'a>, // SPL Token or Token-2022, whichever owns the mint
+    pub system_program: AccountInfo<'a>,
+    pub staker_account: AccountInfo<'a>,
+    pub mint: AccountInfo<'a>,
+}
+
+// Accounts for `purchase_tickets`. `buyer` signs and pays for the ticket account.
+pub struct PurchaseTickets<'a> {
+    pub buyer: AccountInfo<'a>,
+    pub buyer_token_account: AccountInfo<'a>,
+    pub treasury_token_account: AccountInfo<'a>,
+    pub ticket_account: AccountInfo<'a>,
+    pub token_program: AccountInfo<'a>, // SPL Token or Token-2022, whichever owns the mint
+    pub system_program: AccountInfo<'a>,
+    pub treasury_pda: AccountInfo<'a>, // Read by the pause check
+    pub mint: AccountInfo<'a>,
+}
+
+// Accounts for `redeem_tickets`. `owner` signs and receives any refunded rent.
+pub struct RedeemTickets<'a> {
+    pub ticket_account: AccountInfo<'a>,
+    pub owner: AccountInfo<'a>,
+    pub mint: AccountInfo<'a>,
+    pub owner_token_account: AccountInfo<'a>,
+    pub treasury_token_account: AccountInfo<'a>,
+    pub treasury_pda: AccountInfo<'a>, // Writable, counts treasury outflows
+    pub token_program: AccountInfo<'a>, // SPL Token or Token-2022, whichever owns the mint
+    pub system_program: AccountInfo<'a>,
+    pub wallet_outflow: AccountInfo<'a>, // Owner's wallet outflow PDA, used with a daily limit
+}
+
+// Stake `amount` tokens from the user's token account
+pub fn stake<'a>(
+    program: &AccountInfo<'a>,
+    accounts: Stake<'a>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let instruction = stake_instruction(
+        program.key,
+        accounts.user.key,
+        accounts.user_token_account.key,
+        accounts.treasury_token_account.key,
+        accounts.token_program.key,
+        amount,
+    );
+    invoke_signed(
+        &instruction,
+        &[
+            accounts.user_token_account,
+            accounts.treasury_token_account,
+            accounts.treasury_pda,
+            accounts.user,
+            accounts.token_program,
+            accounts.system_program,
+            accounts.staker_account,
+            accounts.mint,
+            program.clone(),
+        ],
+        signer_seeds,
+    )
+}
+
+// Buy as many tickets as `amount` pays for, vesting for `vesting_period` seconds
+pub fn purchase_tickets<'a>(
+    program: &AccountInfo<'a>,
+    accounts: PurchaseTickets<'a>,
+    amount: u64,
+    vesting_period: i64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let instruction = purchase_tickets_instruction(
+        program.key,
+        accounts.buyer.key,
+        accounts.buyer_token_account.key,
+        accounts.treasury_token_account.key,
+        accounts.token_program.key,
+        amount,
+        vesting_period,
+    );
+    invoke_signed(
+        &instruction,
+        &[
+            accounts.buyer,
+            accounts.buyer_token_account,
+            accounts.treasury_token_account,
+            accounts.ticket_account,
+            accounts.token_program,
+            accounts.system_program,
+            accounts.treasury_pda,
+            accounts.mint,
+            program.clone(),
+        ],
+        signer_seeds,
+    )
+}
+
+// Redeem `amount` vested tickets for principal and yield
+pub fn redeem_tickets<'a>(
+    program: &AccountInfo<'a>,
+    accounts: RedeemTickets<'a>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let instruction = redeem_tickets_instruction(
+        program.key,
+        accounts.owner.key,
+        accounts.owner_token_account.key,
+        accounts.treasury_token_account.key,
+        accounts.token_program.key,
+        amount,
+    );
+    invoke_signed(
+        &instruction,
+        &[
+            accounts.ticket_account,
+            accounts.owner,
+            accounts.mint,
+            accounts.owner_token_account,
+            accounts.treasury_token_account,
+            accounts.treasury_pda,
+            accounts.token_program,
+            accounts.system_program,
+            accounts.wallet_outflow,
+            program.clone(),
+        ],
+        signer_seeds,
+    )
+}
+
+// Build a Stake instruction; the treasury, staker and mint PDAs are derived from `program_id`.
+// `token_program` is the program that owns the HAPR mint.
+pub fn stake_instruction(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    user_token_account: &Pubkey,
+    treasury_token_account: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let (treasury_pda, _treasury_bump) = Pubkey::find_program_address(&[TREASURY_SEED], program_id);
+    let (staker_pda, _staker_bump) =
+        Pubkey::find_program_address(&[user.as_ref(), STAKER_SEED], program_id);
+    let (mint_pda, _mint_bump) = Pubkey::find_program_address(&[SEED], program_id);
+
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*user_token_account, false),
+            AccountMeta::new(*treasury_token_account, false),
+            AccountMeta::new_readonly(treasury_pda, false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new_readonly(*token_program, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(staker_pda, false),
+            AccountMeta::new_readonly(mint_pda, false),
+        ],
+        data: instruction_data(STAKE, amount, &[]),
+    }
+}
+
+// Build a PurchaseTickets instruction; the ticket, treasury and mint PDAs are derived from
+// `program_id`. `token_program` is the program that owns the HAPR mint.
+pub fn purchase_tickets_instruction(
+    program_id: &Pubkey,
+    buyer: &Pubkey,
+    buyer_token_account: &Pubkey,
+    treasury_token_account: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+    vesting_period: i64,
+) -> Instruction {
+    let (ticket_pda, _ticket_bump) =
+        Pubkey::find_program_address(&[TICKET_SEED, buyer.as_ref()], program_id);
+    let (treasury_pda, _treasury_bump) = Pubkey::find_program_address(&[TREASURY_SEED], program_id);
+    let (mint_pda, _mint_bump) = Pubkey::find_program_address(&[SEED], program_id);
+
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*buyer, true),
+            AccountMeta::new(*buyer_token_account, false),
+            AccountMeta::new(*treasury_token_account, false),
+            AccountMeta::new(ticket_pda, false),
+            AccountMeta::new_readonly(*token_program, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(treasury_pda, false),
+            AccountMeta::new_readonly(mint_pda, false),
+        ],
+        data: instruction_data(PURCHASE_TICKETS, amount, &vesting_period.to_le_bytes()),
+    }
+}
+
+// Build a RedeemTickets instruction; the ticket, mint, treasury and wallet outflow PDAs are
+// derived from `program_id`. `token_program` is the program that owns the HAPR mint.
+pub fn redeem_tickets_instruction(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    owner_token_account: &Pubkey,
+    treasury_token_account: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let (ticket_pda, _ticket_bump) =
+        Pubkey::find_program_address(&[TICKET_SEED, owner.as_ref()], program_id);
+    let (mint_pda, _mint_bump) = Pubkey::find_program_address(&[SEED], program_id);
+    let (treasury_pda, _treasury_bump) = Pubkey::find_program_address(&[TREASURY_SEED], program_id);
+    let (wallet_outflow_pda, _wallet_outflow_bump) =
+        Pubkey::find_program_address(&[WALLET_OUTFLOW_SEED, owner.as_ref()], program_id);
+
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(ticket_pda, false),
+            AccountMeta::new(*owner, true),
+            AccountMeta::new(mint_pda, false),
+            AccountMeta::new(*owner_token_account, false),
+            AccountMeta::new(*treasury_token_account, false),
+            AccountMeta::new(treasury_pda, false),
+            AccountMeta::new_readonly(*token_program, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(wallet_outflow_pda, false),
+        ],
+        data: instruction_data(REDEEM_TICKETS, amount, &[]),
+    }
+}
+
+// [tag, amount, extra args]
+fn instruction_data(tag: u8, amount: u64, args: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(9 + args.len());
+    data.push(tag);
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.extend_from_slice(args);
+    data
+}
@@ -0,0 +1,545 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::rent::Rent,
+    sysvar::{slot_hashes, Sysvar},
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::haprerror::HaprError;
+use crate::haprlimits;
+use crate::haprspl;
+use crate::haprstate::{self, AccountKind, ACCOUNT_HEADER_SIZE};
+use crate::haprtoken::TicketAccount;
+use crate::haprtreasury;
+use crate::haprvalidate;
+
+const SEED: &[u8] = b"mintthissuperhyperAPRtoken"; // Seed for the HAPR mint PDA
+const TREASURY_SEED: &[u8] = b"treasurythissuperhyperAPRtoken";
+const TICKET_SEED: &[u8] = b"ticket_seed";
+const DRAW_SEED: &[u8] = b"draw"; // [seed, draw_id]
+const DRAW_ENTRY_SEED: &[u8] = b"draw_entry"; // [seed, draw state, owner]
+
+const DRAW_STATE_SIZE: usize =
+    ACCOUNT_HEADER_SIZE + 8 + 8 + 8 + 8 + 8 + 8 + 32 + 1; // Total: 90 bytes
+const DRAW_ENTRY_SIZE: usize = ACCOUNT_HEADER_SIZE + 32 + 32 + 8 + 8; // Total: 89 bytes
+
+// Slots between committing to a randomness slot and reading its hash. The hash of a
+// future slot is unknown when the commitment is made, so the settler can't grind it.
+// If the leader skips the committed slot, the next produced slot's hash decides. If the
+// committed slot ages out of SlotHashes before anyone settles, a later hash could be
+// picked at will, so the draw commits to a new future slot and settling is retried.
+const DRAW_RANDOMNESS_SLOT_DELAY: u64 = 10;
+
+// A prize-linked savings round. Ticket holders enter with their ticket count as
+// weight; the prize is set aside from the treasury's accrued yield when the draw opens
+// and paid from the treasury token account, so the principal held there stays
+// redeemable as usual.
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct DrawState {
+    pub draw_id: u64,
+    pub prize_amount: u64,    // Reserved from accrued yield, paid to the winner
+    pub entry_end_time: i64,  // Entries close (and ticket locks expire) at this time
+    pub total_weight: u64,    // Sum of entered tickets
+    pub randomness_slot: u64, // Slot whose hash picks the winner (0 = not committed)
+    pub winning_ticket: u64,  // Index into [0, total_weight)
+    pub winner: Pubkey,       // Default pubkey when the draw had no entries
+    pub settled: bool,
+}
+
+// A holder's entry, covering tickets [range_start, range_start + weight) of the draw
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct DrawEntry {
+    pub draw: Pubkey,
+    pub owner: Pubkey,
+    pub range_start: u64,
+    pub weight: u64,
+}
+
+// Open a new draw round (admin only). With the admin timelock enabled draws are
+// started through the action queue instead.
+pub fn start_draw(
+    accounts: &[AccountInfo],
+    prize_amount: u64,
+    draw_id: u64,
+    entry_duration: i64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let admin_account = next_account_info(accounts_iter)?; // Treasury admin (pays rent)
+    let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA
+    let draw_state_account = next_account_info(accounts_iter)?; // Draw PDA
+    let system_program = next_account_info(accounts_iter)?;
+    let co_signers = accounts_iter.as_slice(); // Other treasury signers, with a signer set
+
+    haprtreasury::assert_admin(treasury_account, admin_account, co_signers, program_id)?;
+    if haprtreasury::load_config(treasury_account, program_id)?.admin_timelock_delay > 0 {
+        msg!("Error: Admin timelock is enabled; queue the draw with QueueAction.");
+        return Err(HaprError::TimelockRequired.into());
+    }
+
+    open_draw(
+        admin_account,
+        treasury_account,
+        draw_state_account,
+        system_program,
+        prize_amount,
+        draw_id,
+        entry_duration,
+        program_id,
+    )
+}
+
+// Create the draw account for a draw the admin is allowed to start, reserving the prize
+// from the treasury's accrued yield
+#[allow(clippy::too_many_arguments)]
+pub fn open_draw<'a>(
+    admin_account: &AccountInfo<'a>,
+    treasury_account: &AccountInfo<'a>,
+    draw_state_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    prize_amount: u64,
+    draw_id: u64,
+    entry_duration: i64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let draw_id_bytes = draw_id.to_le_bytes();
+    let draw_bump = haprvalidate::assert_pda(
+        draw_state_account,
+        &[DRAW_SEED, &draw_id_bytes],
+        program_id,
+        HaprError::InvalidDrawPda,
+        "Draw account",
+    )?;
+    haprvalidate::assert_writable(draw_state_account, "Draw account")?;
+    if draw_state_account.data_len() != 0 {
+        msg!("Error: Draw {} already exists.", draw_id);
+        return Err(HaprError::AccountAlreadyExists.into());
+    }
+    if prize_amount == 0 || entry_duration <= 0 {
+        msg!("Error: Draw needs a prize and a positive entry duration.");
+        return Err(HaprError::InvalidDrawParameters.into());
+    }
+    haprvalidate::assert_writable(treasury_account, "Treasury account")?;
+    haprtreasury::reserve_accrued_yield(treasury_account, prize_amount)?;
+
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            admin_account.key,
+            draw_state_account.key,
+            rent.minimum_balance(DRAW_STATE_SIZE),
+            DRAW_STATE_SIZE as u64,
+            program_id,
+        ),
+        &[
+            admin_account.clone(),
+            draw_state_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[DRAW_SEED, &draw_id_bytes, &[draw_bump]]],
+    )?;
+
+    let draw_state = DrawState {
+        draw_id,
+        prize_amount,
+        entry_end_time: Clock::get()?.unix_timestamp + entry_duration,
+        total_weight: 0,
+        randomness_slot: 0,
+        winning_ticket: 0,
+        winner: Pubkey::default(),
+        settled: false,
+    };
+    haprstate::store(draw_state_account, AccountKind::DrawState, &draw_state)?;
+
+    debug_msg!("Draw started: {:?}", draw_state);
+    Ok(())
+}
+
+// Enter a draw with every ticket currently held. The tickets are locked (no
+// transfer or redemption) until the entry window closes.
+pub fn enter_draw(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let owner_account = next_account_info(accounts_iter)?; // Ticket owner (pays rent)
+    let ticket_account = next_account_info(accounts_iter)?; // Ticket PDA
+    let draw_state_account = next_account_info(accounts_iter)?; // Draw PDA
+    let draw_entry_account = next_account_info(accounts_iter)?; // Entry PDA
+    let system_program = next_account_info(accounts_iter)?;
+
+    haprvalidate::assert_signer(owner_account, "Ticket owner")?;
+    haprvalidate::assert_program_account(
+        ticket_account,
+        &[TICKET_SEED, owner_account.key.as_ref()],
+        program_id,
+        HaprError::InvalidTicketPda,
+        "Ticket account",
+    )?;
+    haprvalidate::assert_writable(ticket_account, "Ticket account")?;
+    haprvalidate::assert_writable(draw_state_account, "Draw account")?;
+    let entry_bump = haprvalidate::assert_pda(
+        draw_entry_account,
+        &[
+            DRAW_ENTRY_SEED,
+            draw_state_account.key.as_ref(),
+            owner_account.key.as_ref(),
+        ],
+        program_id,
+        HaprError::InvalidDrawPda,
+        "Draw entry account",
+    )?;
+    haprvalidate::assert_writable(draw_entry_account, "Draw entry account")?;
+    if draw_entry_account.data_len() != 0 {
+        msg!("Error: Already entered in this draw.");
+        return Err(HaprError::AccountAlreadyExists.into());
+    }
+
+    let mut draw_state = load_draw_state(draw_state_account, program_id)?;
+    let current_time = Clock::get()?.unix_timestamp;
+    if draw_state.settled || current_time >= draw_state.entry_end_time {
+        msg!("Error: Draw {} is closed for entries.", draw_state.draw_id);
+        return Err(HaprError::DrawNotOpen.into());
+    }
+
+    let weight = {
+        let ticket_account_bytes = ticket_account.try_borrow_data()?;
+        let ticket_account_data = TicketAccount::load(&ticket_account_bytes)?;
+        if ticket_account_data.owner != *owner_account.key {
+            msg!("Unauthorized: Only the owner can enter tickets.");
+            return Err(HaprError::UnauthorizedAccess.into());
+        }
+        u64::from(ticket_account_data.ticket_total)
+    };
+    if weight == 0 {
+        msg!("Error: You don't have any tickets.");
+        return Err(HaprError::InsufficientTickets.into());
+    }
+
+    // Record the entry's ticket range
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            owner_account.key,
+            draw_entry_account.key,
+            rent.minimum_balance(DRAW_ENTRY_SIZE),
+            DRAW_ENTRY_SIZE as u64,
+            program_id,
+        ),
+        &[
+            owner_account.clone(),
+            draw_entry_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[
+            DRAW_ENTRY_SEED,
+            draw_state_account.key.as_ref(),
+            owner_account.key.as_ref(),
+            &[entry_bump],
+        ]],
+    )?;
+    let draw_entry = DrawEntry {
+        draw: *draw_state_account.key,
+        owner: *owner_account.key,
+        range_start: draw_state.total_weight,
+        weight,
+    };
+    haprstate::store(draw_entry_account, AccountKind::DrawEntry, &draw_entry)?;
+
+    draw_state.total_weight += weight;
+    haprstate::store(draw_state_account, AccountKind::DrawState, &draw_state)?;
+
+    // Lock the entered tickets for the rest of the entry window
+    let mut ticket_account_bytes = ticket_account.try_borrow_mut_data()?;
+    let ticket_account_data = TicketAccount::load_mut(&mut ticket_account_bytes)?;
+    ticket_account_data.draw_locked_until = std::cmp::max(
+        i64::from(ticket_account_data.draw_locked_until),
+        draw_state.entry_end_time,
+    )
+    .into();
+
+    debug_msg!(
+        "Entered draw {} with {} tickets (range start {}).",
+        draw_state.draw_id,
+        weight,
+        draw_entry.range_start
+    );
+    Ok(())
+}
+
+// Settle a draw in two steps. After entries close, the first call commits to a
+// future slot. Once that slot has passed, its hash picks the winning ticket and
+// the caller supplies the matching entry so the prize is paid to the winner.
+pub fn settle_draw(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let draw_state_account = next_account_info(accounts_iter)?; // Draw PDA
+    let slot_hashes_account = next_account_info(accounts_iter)?; // SlotHashes sysvar
+
+    haprvalidate::assert_writable(draw_state_account, "Draw account")?;
+    if *slot_hashes_account.key != slot_hashes::id() {
+        msg!("Error: SlotHashes sysvar account missing or incorrect");
+        return Err(HaprError::InvalidSysvar.into());
+    }
+
+    let mut draw_state = load_draw_state(draw_state_account, program_id)?;
+    if draw_state.settled {
+        msg!("Error: Draw {} is already settled.", draw_state.draw_id);
+        return Err(HaprError::DrawAlreadySettled.into());
+    }
+
+    let clock = Clock::get()?;
+    if clock.unix_timestamp < draw_state.entry_end_time {
+        msg!(
+            "Error: Draw entries are open until {}.",
+            draw_state.entry_end_time
+        );
+        return Err(HaprError::DrawNotReady.into());
+    }
+
+    if draw_state.total_weight == 0 {
+        // Return the prize to the accrued yield. The treasury PDA is the trailing account
+        // passed for the pause check.
+        let treasury_account = haprtreasury::find_treasury(accounts_iter.as_slice(), program_id)?;
+        haprvalidate::assert_writable(treasury_account, "Treasury account")?;
+        haprtreasury::release_accrued_yield(treasury_account, draw_state.prize_amount)?;
+
+        draw_state.settled = true;
+        haprstate::store(draw_state_account, AccountKind::DrawState, &draw_state)?;
+        debug_msg!(
+            "Draw {} had no entries. The prize returns to the accrued yield.",
+            draw_state.draw_id
+        );
+        return Ok(());
+    }
+
+    // Step 1: commit to a future slot
+    if draw_state.randomness_slot == 0 {
+        draw_state.randomness_slot = clock.slot + DRAW_RANDOMNESS_SLOT_DELAY;
+        haprstate::store(draw_state_account, AccountKind::DrawState, &draw_state)?;
+        debug_msg!(
+            "Draw {} will be decided by the hash of slot {}.",
+            draw_state.draw_id,
+            draw_state.randomness_slot
+        );
+        return Ok(());
+    }
+
+    if clock.slot <= draw_state.randomness_slot {
+        msg!(
+            "Error: Randomness slot {} has not passed yet (current slot {}).",
+            draw_state.randomness_slot,
+            clock.slot
+        );
+        return Err(HaprError::DrawNotReady.into());
+    }
+
+    // Step 2: read the hash of the committed slot, or of the first slot after it
+    let slot_hash = match find_slot_hash(
+        &slot_hashes_account.try_borrow_data()?,
+        draw_state.randomness_slot,
+    ) {
+        SlotHashLookup::Found(hash) => hash,
+        SlotHashLookup::NotYet => {
+            msg!(
+                "Error: No slot hash at or after slot {} yet.",
+                draw_state.randomness_slot
+            );
+            return Err(HaprError::DrawNotReady.into());
+        }
+        SlotHashLookup::Expired => {
+            let expired_slot = draw_state.randomness_slot;
+            draw_state.randomness_slot = clock.slot + DRAW_RANDOMNESS_SLOT_DELAY;
+            haprstate::store(draw_state_account, AccountKind::DrawState, &draw_state)?;
+            msg!(
+                "Randomness slot {} left the SlotHashes window. Draw {} now waits for slot {}; settle again after it.",
+                expired_slot,
+                draw_state.draw_id,
+                draw_state.randomness_slot
+            );
+            return Ok(());
+        }
+    };
+    let winning_ticket =
+        u64::from_le_bytes(slot_hash[0..8].try_into().unwrap()) % draw_state.total_weight;
+
+    let draw_entry_account = next_account_info(accounts_iter)?; // Winning entry PDA
+    let winner_token_account = next_account_info(accounts_iter)?; // Winner's token account
+    let treasury_token_account = next_account_info(accounts_iter)?; // Treasury's token account
+    let mint_account = next_account_info(accounts_iter)?; // Token mint account
+    let treasury_pda_account = next_account_info(accounts_iter)?; // Treasury PDA, token owner
+    let token_program = next_account_info(accounts_iter)?;
+
+    // Verify the supplied entry holds the winning ticket
+    haprvalidate::assert_token_program(token_program)?;
+    haprvalidate::assert_owned_by(draw_entry_account, program_id, "Draw entry account")?;
+    let draw_entry: DrawEntry = haprstate::load(draw_entry_account, AccountKind::DrawEntry)?;
+    haprvalidate::assert_pda(
+        draw_entry_account,
+        &[
+            DRAW_ENTRY_SEED,
+            draw_state_account.key.as_ref(),
+            draw_entry.owner.as_ref(),
+        ],
+        program_id,
+        HaprError::InvalidDrawPda,
+        "Draw entry account",
+    )?;
+    if draw_entry.draw != *draw_state_account.key
+        || winning_ticket < draw_entry.range_start
+        || winning_ticket >= draw_entry.range_start + draw_entry.weight
+    {
+        msg!(
+            "Error: Entry does not hold winning ticket {}.",
+            winning_ticket
+        );
+        return Err(HaprError::InvalidDrawWinner.into());
+    }
+
+    haprvalidate::assert_hapr_token_account(
+        winner_token_account,
+        &draw_entry.owner,
+        program_id,
+        "Winner token account",
+    )?;
+    haprvalidate::assert_pda(
+        mint_account,
+        &[SEED],
+        program_id,
+        HaprError::InvalidMintPda,
+        "Mint account",
+    )?;
+    haprvalidate::assert_treasury_token_account(treasury_token_account, program_id)?;
+
+    // Pay the prize reserved from accrued yield out of the treasury token account
+    let (treasury_pda, bump_seed) = Pubkey::find_program_address(&[TREASURY_SEED], program_id);
+    if *treasury_pda_account.key != treasury_pda {
+        msg!("Error: Invalid PDA authority");
+        return Err(HaprError::InvalidTreasuryPda.into());
+    }
+    haprlimits::record_outflow(
+        accounts,
+        treasury_pda_account,
+        None,
+        draw_state.prize_amount,
+        program_id,
+    )?;
+    let seeds = &[TREASURY_SEED, &[bump_seed]];
+    haprspl::transfer(
+        token_program,
+        treasury_token_account,
+        mint_account,
+        winner_token_account,
+        treasury_pda_account,
+        draw_state.prize_amount,
+        &[&seeds[..]],
+    )?;
+
+    draw_state.winning_ticket = winning_ticket;
+    draw_state.winner = draw_entry.owner;
+    draw_state.settled = true;
+    haprstate::store(draw_state_account, AccountKind::DrawState, &draw_state)?;
+
+    debug_msg!(
+        "Draw {} settled. Winning ticket {} -> {} won {} tokens.",
+        draw_state.draw_id,
+        winning_ticket,
+        draw_state.winner,
+        draw_state.prize_amount
+    );
+    Ok(())
+}
+
+fn load_draw_state(
+    draw_state_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<DrawState, ProgramError> {
+    haprvalidate::assert_owned_by(draw_state_account, program_id, "Draw account")?;
+    let draw_state: DrawState = haprstate::load(draw_state_account, AccountKind::DrawState)?;
+    haprvalidate::assert_pda(
+        draw_state_account,
+        &[DRAW_SEED, &draw_state.draw_id.to_le_bytes()],
+        program_id,
+        HaprError::InvalidDrawPda,
+        "Draw account",
+    )?;
+    Ok(draw_state)
+}
+
+// What SlotHashes holds for a committed randomness slot
+#[derive(Debug, PartialEq)]
+enum SlotHashLookup {
+    Found([u8; 32]), // Hash of the slot, or of the first produced slot after it
+    NotYet,          // No slot at or after it has been recorded yet
+    Expired,         // The slot is older than every recorded slot
+}
+
+// Look up the hash of the first slot at or after `slot` in the raw SlotHashes sysvar data
+// (u64 entry count followed by (slot: u64, hash: [u8; 32]) entries, newest first)
+fn find_slot_hash(data: &[u8], slot: u64) -> SlotHashLookup {
+    const ENTRY_SIZE: usize = 8 + 32;
+    let count = data.get(0..8).map_or(0, |count| {
+        u64::from_le_bytes(count.try_into().unwrap()) as usize
+    });
+    let entries = data
+        .get(8..)
+        .unwrap_or_default()
+        .chunks_exact(ENTRY_SIZE)
+        .take(count)
+        .map(|entry| (u64::from_le_bytes(entry[0..8].try_into().unwrap()), entry));
+
+    // Walk back to the oldest entry at or after `slot`. It only stands in for `slot` if
+    // the window still reaches back that far; otherwise earlier candidates have aged out.
+    let mut first_at_or_after = None;
+    let mut reaches_back = false;
+    for (entry_slot, entry) in entries {
+        if entry_slot < slot {
+            reaches_back = true;
+            break;
+        }
+        first_at_or_after = Some((entry_slot, entry));
+    }
+    match first_at_or_after {
+        Some((entry_slot, entry)) if reaches_back || entry_slot == slot => {
+            SlotHashLookup::Found(entry[8..ENTRY_SIZE].try_into().unwrap())
+        }
+        Some(_) => SlotHashLookup::Expired,
+        None => SlotHashLookup::NotYet,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // SlotHashes data for `slots` (newest first), each hashed to its own slot number
+    fn slot_hashes(slots: &[u64]) -> Vec<u8> {
+        let mut data = (slots.len() as u64).to_le_bytes().to_vec();
+        for slot in slots {
+            data.extend_from_slice(&slot.to_le_bytes());
+            data.extend_from_slice(&[*slot as u8; 32]);
+        }
+        data
+    }
+
+    #[test]
+    fn skipped_randomness_slot_uses_the_next_produced_slot() {
+        let data = slot_hashes(&[105, 104, 102, 101, 99]);
+        assert_eq!(find_slot_hash(&data, 102), SlotHashLookup::Found([102; 32]));
+        assert_eq!(find_slot_hash(&data, 100), SlotHashLookup::Found([101; 32]));
+        assert_eq!(find_slot_hash(&data, 103), SlotHashLookup::Found([104; 32]));
+        assert_eq!(find_slot_hash(&data, 106), SlotHashLookup::NotYet);
+    }
+
+    #[test]
+    fn randomness_slot_older_than_the_window_has_expired() {
+        let data = slot_hashes(&[105, 104, 102]);
+        assert_eq!(find_slot_hash(&data, 102), SlotHashLookup::Found([102; 32]));
+        assert_eq!(find_slot_hash(&data, 101), SlotHashLookup::Expired);
+        assert_eq!(find_slot_hash(&[], 101), SlotHashLookup::NotYet);
+    }
+}
@@ -0,0 +1,264 @@
+use num_traits::FromPrimitive;
+use solana_program::{
+    decode_error::DecodeError,
+    msg,
+    program_error::{PrintProgramError, ProgramError},
+};
+
+// Errors returned by the program as `ProgramError::Custom(code)`.
+// Codes are stable: never reorder or reuse a variant, only append new ones.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HaprError {
+    AlreadyStaking = 0,
+    InsufficientFunds = 1,
+    VestingNotComplete = 2,
+    TicketAlreadyClaimed = 3,
+    StakingPeriodNotComplete = 4,
+    UnauthorizedAccess = 5,
+    InsufficientTickets = 6,
+    InsufficientVestedTickets = 7,
+    TooManyTicketLots = 8,
+    TicketsLockedInDraw = 9,
+    DrawNotOpen = 10,
+    DrawNotReady = 11,
+    DrawAlreadySettled = 12,
+    InvalidDrawWinner = 13,
+    ListingPriceExceeded = 14,
+    MaxSupplyExceeded = 15,
+    InsufficientTicketAmount = 16,
+    InvalidTreasuryPda = 17,
+    InvalidMintPda = 18,
+    InvalidTicketPda = 19,
+    InvalidStakerPda = 20,
+    InvalidTicketLotPda = 21,
+    InvalidListingPda = 22,
+    InvalidDrawPda = 23,
+    IncorrectTokenProgram = 24,
+    InvalidTreasuryTokenAccount = 25,
+    InvalidAccountOwner = 26,
+    InvalidSysvar = 27,
+    InvalidTicketIndex = 28,
+    SelfTransfer = 29,
+    InvalidListingPrice = 30,
+    InvalidDrawParameters = 31,
+    InvalidTokenAccount = 32,
+    TreasuryNotInitialized = 33,
+    ListingSellerMismatch = 34,
+    AccountAlreadyExists = 35,
+    VotingClosed = 36,
+    VotingNotEnded = 37,
+    ProposalAlreadyExecuted = 38,
+    QuorumNotReached = 39,
+    ProposalRejected = 40,
+    TimelockNotElapsed = 41,
+    InvalidProposal = 42,
+    NoVotingPower = 43,
+    InvalidProposalPda = 44,
+    InvalidVeLockPda = 45,
+    InvalidLockDuration = 46,
+    LockNotExpired = 47,
+    TimelockRequired = 48,
+    ActionNotReady = 49,
+    ActionExpired = 50,
+    InvalidAdminAction = 51,
+    InvalidActionPda = 52,
+    ThresholdNotReached = 53,
+    InvalidMultisig = 54,
+    InstructionPaused = 55,
+    MissingTreasuryAccount = 56,
+    OutflowLimitExceeded = 57,
+    WalletOutflowLimitExceeded = 58,
+    InvalidWalletOutflowPda = 59,
+    AccountNotWritable = 60,
+    AccountNotEmpty = 61,
+    AccountClosed = 62,
+    InvalidAccountDiscriminator = 63,
+    UnsupportedAccountVersion = 64,
+    ProposalExpired = 65,
+    AlreadyApproved = 66,
+}
+
+// Every variant, indexed by its code
+const ALL_ERRORS: [HaprError; 67] = [
+    HaprError::AlreadyStaking,
+    HaprError::InsufficientFunds,
+    HaprError::VestingNotComplete,
+    HaprError::TicketAlreadyClaimed,
+    HaprError::StakingPeriodNotComplete,
+    HaprError::UnauthorizedAccess,
+    HaprError::InsufficientTickets,
+    HaprError::InsufficientVestedTickets,
+    HaprError::TooManyTicketLots,
+    HaprError::TicketsLockedInDraw,
+    HaprError::DrawNotOpen,
+    HaprError::DrawNotReady,
+    HaprError::DrawAlreadySettled,
+    HaprError::InvalidDrawWinner,
+    HaprError::ListingPriceExceeded,
+    HaprError::MaxSupplyExceeded,
+    HaprError::InsufficientTicketAmount,
+    HaprError::InvalidTreasuryPda,
+    HaprError::InvalidMintPda,
+    HaprError::InvalidTicketPda,
+    HaprError::InvalidStakerPda,
+    HaprError::InvalidTicketLotPda,
+    HaprError::InvalidListingPda,
+    HaprError::InvalidDrawPda,
+    HaprError::IncorrectTokenProgram,
+    HaprError::InvalidTreasuryTokenAccount,
+    HaprError::InvalidAccountOwner,
+    HaprError::InvalidSysvar,
+    HaprError::InvalidTicketIndex,
+    HaprError::SelfTransfer,
+    HaprError::InvalidListingPrice,
+    HaprError::InvalidDrawParameters,
+    HaprError::InvalidTokenAccount,
+    HaprError::TreasuryNotInitialized,
+    HaprError::ListingSellerMismatch,
+    HaprError::AccountAlreadyExists,
+    HaprError::VotingClosed,
+    HaprError::VotingNotEnded,
+    HaprError::ProposalAlreadyExecuted,
+    HaprError::QuorumNotReached,
+    HaprError::ProposalRejected,
+    HaprError::TimelockNotElapsed,
+    HaprError::InvalidProposal,
+    HaprError::NoVotingPower,
+    HaprError::InvalidProposalPda,
+    HaprError::InvalidVeLockPda,
+    HaprError::InvalidLockDuration,
+    HaprError::LockNotExpired,
+    HaprError::TimelockRequired,
+    HaprError::ActionNotReady,
+    HaprError::ActionExpired,
+    HaprError::InvalidAdminAction,
+    HaprError::InvalidActionPda,
+    HaprError::ThresholdNotReached,
+    HaprError::InvalidMultisig,
+    HaprError::InstructionPaused,
+    HaprError::MissingTreasuryAccount,
+    HaprError::OutflowLimitExceeded,
+    HaprError::WalletOutflowLimitExceeded,
+    HaprError::InvalidWalletOutflowPda,
+    HaprError::AccountNotWritable,
+    HaprError::AccountNotEmpty,
+    HaprError::AccountClosed,
+    HaprError::InvalidAccountDiscriminator,
+    HaprError::UnsupportedAccountVersion,
+    HaprError::ProposalExpired,
+    HaprError::AlreadyApproved,
+];
+
+impl HaprError {
+    // User-facing message for each error code
+    pub fn message(&self) -> &'static str {
+        match self {
+            HaprError::AlreadyStaking => "Account is already staking",
+            HaprError::InsufficientFunds => "Treasury has insufficient funds",
+            HaprError::VestingNotComplete => "Tickets have not finished vesting",
+            HaprError::TicketAlreadyClaimed => "Ticket lot has already been claimed",
+            HaprError::StakingPeriodNotComplete => "Staking period is not complete",
+            HaprError::UnauthorizedAccess => "Signer is not authorized for this account",
+            HaprError::InsufficientTickets => "Not enough tickets",
+            HaprError::InsufficientVestedTickets => "Not enough vested tickets to redeem",
+            HaprError::TooManyTicketLots => "Ticket account holds the maximum number of lots",
+            HaprError::TicketsLockedInDraw => "Tickets are locked in a running draw",
+            HaprError::DrawNotOpen => "Draw is closed for entries",
+            HaprError::DrawNotReady => "Draw cannot be settled yet",
+            HaprError::DrawAlreadySettled => "Draw is already settled",
+            HaprError::InvalidDrawWinner => "Entry does not hold the winning ticket",
+            HaprError::ListingPriceExceeded => "Listing price exceeds the maximum accepted",
+            HaprError::MaxSupplyExceeded => "Minting would exceed the max supply",
+            HaprError::InsufficientTicketAmount => "Amount is below the price of one ticket",
+            HaprError::InvalidTreasuryPda => "Treasury account does not match the treasury PDA",
+            HaprError::InvalidMintPda => "Mint account does not match the mint PDA",
+            HaprError::InvalidTicketPda => "Ticket account does not match the ticket PDA",
+            HaprError::InvalidStakerPda => "Staker account does not match the staker PDA",
+            HaprError::InvalidTicketLotPda => "Ticket lot account does not match its PDA",
+            HaprError::InvalidListingPda => "Listing account does not match its PDA",
+            HaprError::InvalidDrawPda => "Draw account does not match its PDA",
+            HaprError::IncorrectTokenProgram => "Account is not owned by the SPL Token program",
+            HaprError::InvalidTreasuryTokenAccount => "Token account is not the treasury ATA",
+            HaprError::InvalidAccountOwner => "Account is not owned by this program",
+            HaprError::InvalidSysvar => "Sysvar account missing or incorrect",
+            HaprError::InvalidTicketIndex => "Ticket lot index out of range",
+            HaprError::SelfTransfer => "Source and destination owner are the same",
+            HaprError::InvalidListingPrice => "Listing price must be greater than zero",
+            HaprError::InvalidDrawParameters => "Draw needs a prize and a positive entry window",
+            HaprError::InvalidTokenAccount => "Token account has the wrong owner or mint",
+            HaprError::TreasuryNotInitialized => "Treasury is not initialized",
+            HaprError::ListingSellerMismatch => "Seller account does not match the listing",
+            HaprError::AccountAlreadyExists => "Account already exists",
+            HaprError::VotingClosed => "Proposal is closed for voting",
+            HaprError::VotingNotEnded => "Proposal voting period has not ended",
+            HaprError::ProposalAlreadyExecuted => "Proposal has already been executed",
+            HaprError::QuorumNotReached => "Proposal did not reach quorum",
+            HaprError::ProposalRejected => "Proposal did not pass the approval threshold",
+            HaprError::TimelockNotElapsed => "Proposal timelock has not elapsed",
+            HaprError::InvalidProposal => "Proposal parameter or value is invalid",
+            HaprError::NoVotingPower => "Voter has no eligible stake",
+            HaprError::InvalidProposalPda => "Proposal account does not match its PDA",
+            HaprError::InvalidVeLockPda => "Lock account does not match the owner's lock PDA",
+            HaprError::InvalidLockDuration => "Unlock time must be later and within the max lock",
+            HaprError::LockNotExpired => "Tokens are still locked",
+            HaprError::TimelockRequired => "Admin timelock is enabled; queue this action",
+            HaprError::ActionNotReady => "Queued admin action is not ready to execute",
+            HaprError::ActionExpired => "Queued admin action has expired",
+            HaprError::InvalidAdminAction => "Admin action parameters are invalid",
+            HaprError::InvalidActionPda => "Admin action account does not match its PDA",
+            HaprError::ThresholdNotReached => "Not enough treasury signers approved",
+            HaprError::InvalidMultisig => "Signer set needs up to 10 distinct signers and a threshold they can reach",
+            HaprError::InstructionPaused => "Instruction is paused",
+            HaprError::MissingTreasuryAccount => "Treasury PDA account is required",
+            HaprError::OutflowLimitExceeded => "Treasury outflow limit for this window reached",
+            HaprError::WalletOutflowLimitExceeded => "Daily outflow limit for this wallet reached",
+            HaprError::InvalidWalletOutflowPda => "Wallet outflow account is missing or does not match its PDA",
+            HaprError::AccountNotWritable => "Account must be writable",
+            HaprError::AccountNotEmpty => "Account still holds staked tokens or tickets",
+            HaprError::AccountClosed => "Account has been closed",
+            HaprError::InvalidAccountDiscriminator => "Account has the wrong type or needs to be migrated",
+            HaprError::UnsupportedAccountVersion => "Account layout version is not supported",
+            HaprError::ProposalExpired => "Proposal execution window has passed",
+            HaprError::AlreadyApproved => "Signer already approved this admin action",
+        }
+    }
+}
+
+impl From<HaprError> for ProgramError {
+    fn from(e: HaprError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl std::fmt::Display for HaprError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for HaprError {}
+
+impl<T> DecodeError<T> for HaprError {
+    fn type_of() -> &'static str {
+        "HaprError"
+    }
+}
+
+impl FromPrimitive for HaprError {
+    fn from_i64(n: i64) -> Option<Self> {
+        u64::try_from(n).ok().and_then(Self::from_u64)
+    }
+
+    fn from_u64(n: u64) -> Option<Self> {
+        ALL_ERRORS.get(usize::try_from(n).ok()?).copied()
+    }
+}
+
+impl PrintProgramError for HaprError {
+    fn print<E>(&self)
+    where
+        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
+    {
+        msg!("Error: {}", self.message());
+    }
+}
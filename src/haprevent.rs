@@ -0,0 +1,143 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{log::sol_log_data, pubkey::Pubkey};
+
+use crate::haprtimelock::AdminAction;
+
+// First field of every event record, so indexers can pick our events out of
+// the `Program data:` log lines of a transaction
+pub const EVENT_DISCRIMINATOR: [u8; 8] = *b"haprevnt";
+
+// Structured events emitted via `sol_log_data` as [EVENT_DISCRIMINATOR, borsh(event)].
+// The Borsh variant index identifies the event type, so variants must only be appended.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub enum HaprEvent {
+    Staked {
+        staker: Pubkey,
+        amount: u64,
+        total_staked: u64,
+        timestamp: i64,
+    },
+    Unstaked {
+        staker: Pubkey,
+        amount: u64,
+        remaining_staked: u64,
+        timestamp: i64,
+    },
+    RewardsPaid {
+        recipient: Pubkey,
+        amount: u64,
+    },
+    TicketsPurchased {
+        owner: Pubkey,
+        number_of_tickets: u64,
+        cost: u64,
+        vesting_period: i64,
+        timestamp: i64,
+    },
+    TicketsRedeemed {
+        owner: Pubkey,
+        number_of_tickets: u64,
+        principal: u64,
+        yield_amount: u64,
+        timestamp: i64,
+    },
+    Minted {
+        destination: Pubkey, // Token account credited
+        amount: u64,
+    },
+    Burned {
+        source: Pubkey, // Token account debited
+        amount: u64,
+    },
+    AdminChanged {
+        old_admin: Pubkey, // Default pubkey when the treasury is first initialized
+        new_admin: Pubkey,
+    },
+    ProposalCreated {
+        proposal_id: u64,
+        proposer: Pubkey,
+        param: u8,
+        new_value: u64,
+        voting_end_time: i64,
+    },
+    VoteCast {
+        proposal_id: u64,
+        voter: Pubkey,
+        support: bool,
+        weight: u64,
+    },
+    ProposalExecuted {
+        proposal_id: u64,
+        param: u8,
+        new_value: u64,
+    },
+    Locked {
+        owner: Pubkey,
+        amount: u64, // Tokens added, 0 when only the unlock time moved
+        total_locked: u64,
+        unlock_time: i64,
+    },
+    LockWithdrawn {
+        owner: Pubkey,
+        amount: u64,
+    },
+    ActionQueued {
+        action_id: u64,
+        action: AdminAction,
+        eta: i64,
+    },
+    ActionExecuted {
+        action_id: u64,
+    },
+    ActionCanceled {
+        action_id: u64,
+    },
+    MultisigChanged {
+        threshold: u8,
+        signers: Vec<Pubkey>,
+    },
+    ActionApproved {
+        action_id: u64,
+        signer: Pubkey,
+    },
+    PauseChanged {
+        paused: u8, // PAUSE_* bits now set
+        by: Pubkey,
+    },
+    PauseGuardianChanged {
+        old_guardian: Pubkey,
+        new_guardian: Pubkey,
+    },
+    OutflowLimitsChanged {
+        window_length: i64,
+        window_limit: u64,
+        wallet_daily_limit: u64,
+    },
+    AccountMigrated {
+        account: Pubkey,
+        kind: u8, // AccountKind code
+        version: u8,
+    },
+}
+
+impl HaprEvent {
+    // Log the event as a `Program data:` line
+    pub fn emit(&self) {
+        let mut data = Vec::new();
+        // Writing into a Vec cannot fail
+        if self.serialize(&mut data).is_ok() {
+            sol_log_data(&[&EVENT_DISCRIMINATOR, &data]);
+        }
+    }
+
+    // Decode the base64-decoded fields of a `Program data:` log line.
+    // Returns None for records that are not HAPR events.
+    pub fn decode(fields: &[&[u8]]) -> Option<HaprEvent> {
+        match fields {
+            [discriminator, data] if *discriminator == EVENT_DISCRIMINATOR => {
+                HaprEvent::try_from_slice(data).ok()
+            }
+            _ => None,
+        }
+    }
+}
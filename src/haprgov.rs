@@ -0,0 +1,468 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::rent::Rent,
+    sysvar::Sysvar,
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::haprerror::HaprError;
+use crate::haprevent::HaprEvent;
+use crate::haprlock;
+use crate::haprspl;
+use crate::haprstate::{self, AccountKind, ACCOUNT_HEADER_SIZE};
+use crate::haprtoken::Staker;
+use crate::haprtreasury::{self, TreasuryConfig};
+use crate::haprvalidate;
+
+const STAKER_SEED: &[u8] = b"staker"; // [user, seed]
+const PROPOSAL_SEED: &[u8] = b"proposal"; // [seed, proposal_id]
+const VOTE_SEED: &[u8] = b"vote"; // [seed, proposal, voter]
+
+const PROPOSAL_SIZE: usize =
+    ACCOUNT_HEADER_SIZE + 8 + 32 + 1 + 8 + 8 + 8 + 8 + 8 + 8 + 1; // Total: 99 bytes
+const VOTE_RECORD_SIZE: usize = ACCOUNT_HEADER_SIZE + 32 + 32 + 8 + 1; // Total: 82 bytes
+
+// Staked tokens can be withdrawn at any time and vote 1x. Locked tokens vote with up to
+// MAX_LOCK_MULTIPLIER_BPS of their amount when haprlock::MAX_LOCK_DURATION or more is left
+// on the lock, scaling linearly down to 1x at unlock.
+const MAX_LOCK_MULTIPLIER_BPS: u64 = 20_000;
+// A passed proposal must be executed within this long after `execute_after`
+const EXECUTION_PERIOD: i64 = 14 * 24 * 60 * 60;
+const MAX_APR_BPS: u64 = 10_000;
+
+// Protocol parameters a proposal can change
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigParam {
+    StakingAprBps,
+    TicketYieldBps,
+    MaxSupply,
+    QuorumVotes,
+    ThresholdBps,
+    VotingPeriod,
+    TimelockDelay,
+    RetireAdmin, // Clears the admin key so only governance remains (value ignored)
+    AdminTimelockDelay,
+}
+
+impl ConfigParam {
+    pub fn from_u8(value: u8) -> Option<ConfigParam> {
+        ConfigParam::try_from_slice(&[value]).ok()
+    }
+
+    pub fn is_valid_value(self, value: u64) -> bool {
+        match self {
+            ConfigParam::StakingAprBps | ConfigParam::TicketYieldBps => value <= MAX_APR_BPS,
+            ConfigParam::MaxSupply | ConfigParam::QuorumVotes => value > 0,
+            ConfigParam::ThresholdBps => value > 0 && value <= 10_000,
+            ConfigParam::VotingPeriod | ConfigParam::TimelockDelay => {
+                value > 0 && value <= i64::MAX as u64
+            }
+            ConfigParam::RetireAdmin => true,
+            ConfigParam::AdminTimelockDelay => value <= i64::MAX as u64,
+        }
+    }
+
+    pub fn apply(self, config: &mut TreasuryConfig, value: u64) {
+        match self {
+            ConfigParam::StakingAprBps => config.staking_apr_bps = value,
+            ConfigParam::TicketYieldBps => config.ticket_yield_bps = value,
+            ConfigParam::MaxSupply => config.max_supply = value,
+            ConfigParam::QuorumVotes => config.quorum_votes = value,
+            ConfigParam::ThresholdBps => config.threshold_bps = value,
+            ConfigParam::VotingPeriod => config.voting_period = value as i64,
+            ConfigParam::TimelockDelay => config.timelock_delay = value as i64,
+            ConfigParam::RetireAdmin => {}
+            ConfigParam::AdminTimelockDelay => config.admin_timelock_delay = value as i64,
+        }
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct Proposal {
+    pub proposal_id: u64,
+    pub proposer: Pubkey,
+    pub param: ConfigParam,
+    pub new_value: u64,
+    pub created_time: i64, // Only stake and locks older than the proposal can vote on it
+    pub voting_end_time: i64,
+    pub execute_after: i64, // End of voting plus the timelock delay; expires EXECUTION_PERIOD later
+    pub votes_for: u64,
+    pub votes_against: u64,
+    pub executed: bool,
+}
+
+// One per voter and proposal, so nobody votes twice
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct VoteRecord {
+    pub proposal: Pubkey,
+    pub voter: Pubkey,
+    pub weight: u64,
+    pub support: bool,
+}
+
+// Propose a change to the protocol config. The proposer must have staked or locked tokens.
+pub fn create_proposal(
+    accounts: &[AccountInfo],
+    new_value: u64,
+    proposal_id: u64,
+    param: ConfigParam,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let proposer_account = next_account_info(accounts_iter)?; // Proposer (pays proposal rent)
+    let proposer_staker_account = next_account_info(accounts_iter)?; // Proposer's staker PDA
+    let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA (protocol config)
+    let proposal_account = next_account_info(accounts_iter)?; // Proposal PDA
+    let system_program = next_account_info(accounts_iter)?;
+    let proposer_ve_lock_account = accounts_iter.next(); // Optional: proposer's VeLock PDA
+
+    haprvalidate::assert_signer(proposer_account, "Proposer")?;
+
+    let config = haprtreasury::load_config(treasury_account, program_id)?;
+    if !haprtreasury::is_initialized(treasury_account)? {
+        msg!("Error: Treasury is not initialized.");
+        return Err(HaprError::TreasuryNotInitialized.into());
+    }
+
+    let current_time = Clock::get()?.unix_timestamp;
+    voting_weight(
+        proposer_staker_account,
+        proposer_ve_lock_account,
+        proposer_account.key,
+        current_time,
+        program_id,
+    )?;
+
+    if !param.is_valid_value(new_value) {
+        msg!("Error: {} is not a valid value for {:?}.", new_value, param);
+        return Err(HaprError::InvalidProposal.into());
+    }
+
+    let proposal_id_bytes = proposal_id.to_le_bytes();
+    let proposal_bump = haprvalidate::assert_pda(
+        proposal_account,
+        &[PROPOSAL_SEED, &proposal_id_bytes],
+        program_id,
+        HaprError::InvalidProposalPda,
+        "Proposal account",
+    )?;
+    haprvalidate::assert_writable(proposal_account, "Proposal account")?;
+    if proposal_account.data_len() != 0 {
+        msg!("Error: Proposal {} already exists.", proposal_id);
+        return Err(HaprError::AccountAlreadyExists.into());
+    }
+
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            proposer_account.key,
+            proposal_account.key,
+            rent.minimum_balance(PROPOSAL_SIZE),
+            PROPOSAL_SIZE as u64,
+            program_id,
+        ),
+        &[
+            proposer_account.clone(),
+            proposal_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[PROPOSAL_SEED, &proposal_id_bytes, &[proposal_bump]]],
+    )?;
+
+    let voting_end_time = current_time + config.voting_period;
+    let proposal = Proposal {
+        proposal_id,
+        proposer: *proposer_account.key,
+        param,
+        new_value,
+        created_time: current_time,
+        voting_end_time,
+        execute_after: voting_end_time + config.timelock_delay,
+        votes_for: 0,
+        votes_against: 0,
+        executed: false,
+    };
+    haprstate::store(proposal_account, AccountKind::Proposal, &proposal)?;
+
+    debug_msg!("Proposal created: {:?}", proposal);
+    HaprEvent::ProposalCreated {
+        proposal_id,
+        proposer: *proposer_account.key,
+        param: param as u8,
+        new_value,
+        voting_end_time,
+    }
+    .emit();
+    Ok(())
+}
+
+// Vote for or against a proposal with the voter's stake and veHAPR lock
+pub fn cast_vote(accounts: &[AccountInfo], support: bool, program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let voter_account = next_account_info(accounts_iter)?; // Voter (pays vote record rent)
+    let voter_staker_account = next_account_info(accounts_iter)?; // Voter's staker PDA
+    let proposal_account = next_account_info(accounts_iter)?; // Proposal PDA
+    let vote_record_account = next_account_info(accounts_iter)?; // Vote record PDA
+    let system_program = next_account_info(accounts_iter)?;
+    let voter_ve_lock_account = accounts_iter.next(); // Optional: voter's VeLock PDA
+
+    haprvalidate::assert_signer(voter_account, "Voter")?;
+    haprvalidate::assert_writable(proposal_account, "Proposal account")?;
+
+    let mut proposal = load_proposal(proposal_account, program_id)?;
+    let current_time = Clock::get()?.unix_timestamp;
+    if current_time >= proposal.voting_end_time {
+        msg!(
+            "Error: Voting on proposal {} ended at {}.",
+            proposal.proposal_id,
+            proposal.voting_end_time
+        );
+        return Err(HaprError::VotingClosed.into());
+    }
+
+    let weight = voting_weight(
+        voter_staker_account,
+        voter_ve_lock_account,
+        voter_account.key,
+        proposal.created_time,
+        program_id,
+    )?;
+
+    let vote_record_bump = haprvalidate::assert_pda(
+        vote_record_account,
+        &[
+            VOTE_SEED,
+            proposal_account.key.as_ref(),
+            voter_account.key.as_ref(),
+        ],
+        program_id,
+        HaprError::InvalidProposalPda,
+        "Vote record account",
+    )?;
+    haprvalidate::assert_writable(vote_record_account, "Vote record account")?;
+    if vote_record_account.data_len() != 0 {
+        msg!("Error: Already voted on this proposal.");
+        return Err(HaprError::AccountAlreadyExists.into());
+    }
+
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            voter_account.key,
+            vote_record_account.key,
+            rent.minimum_balance(VOTE_RECORD_SIZE),
+            VOTE_RECORD_SIZE as u64,
+            program_id,
+        ),
+        &[
+            voter_account.clone(),
+            vote_record_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[
+            VOTE_SEED,
+            proposal_account.key.as_ref(),
+            voter_account.key.as_ref(),
+            &[vote_record_bump],
+        ]],
+    )?;
+    let vote_record = VoteRecord {
+        proposal: *proposal_account.key,
+        voter: *voter_account.key,
+        weight,
+        support,
+    };
+    haprstate::store(vote_record_account, AccountKind::VoteRecord, &vote_record)?;
+
+    if support {
+        proposal.votes_for += weight;
+    } else {
+        proposal.votes_against += weight;
+    }
+    haprstate::store(proposal_account, AccountKind::Proposal, &proposal)?;
+
+    debug_msg!(
+        "Vote cast on proposal {}: {} with weight {}",
+        proposal.proposal_id,
+        support,
+        weight
+    );
+    HaprEvent::VoteCast {
+        proposal_id: proposal.proposal_id,
+        voter: *voter_account.key,
+        support,
+        weight,
+    }
+    .emit();
+    Ok(())
+}
+
+// Apply a passed proposal to the treasury config once its timelock has elapsed and before
+// the execution period ends. Anyone may execute; the executor pays for growing legacy
+// treasury accounts.
+pub fn execute_proposal(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let executor_account = next_account_info(accounts_iter)?; // Executor
+    let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA (protocol config)
+    let proposal_account = next_account_info(accounts_iter)?; // Proposal PDA
+    let system_program = next_account_info(accounts_iter)?;
+
+    haprvalidate::assert_signer(executor_account, "Executor")?;
+    haprvalidate::assert_writable(treasury_account, "Treasury account")?;
+    haprvalidate::assert_writable(proposal_account, "Proposal account")?;
+
+    let mut proposal = load_proposal(proposal_account, program_id)?;
+    if proposal.executed {
+        msg!("Error: Proposal {} was already executed.", proposal.proposal_id);
+        return Err(HaprError::ProposalAlreadyExecuted.into());
+    }
+
+    let current_time = Clock::get()?.unix_timestamp;
+    if current_time < proposal.voting_end_time {
+        msg!(
+            "Error: Voting on proposal {} ends at {}.",
+            proposal.proposal_id,
+            proposal.voting_end_time
+        );
+        return Err(HaprError::VotingNotEnded.into());
+    }
+    if current_time < proposal.execute_after {
+        msg!(
+            "Error: Proposal {} can be executed after {}.",
+            proposal.proposal_id,
+            proposal.execute_after
+        );
+        return Err(HaprError::TimelockNotElapsed.into());
+    }
+    if current_time > proposal.execute_after + EXECUTION_PERIOD {
+        msg!(
+            "Error: Proposal {} expired at {}.",
+            proposal.proposal_id,
+            proposal.execute_after + EXECUTION_PERIOD
+        );
+        return Err(HaprError::ProposalExpired.into());
+    }
+
+    let mut config = haprtreasury::load_config(treasury_account, program_id)?;
+    if *treasury_account.owner != *program_id || !haprtreasury::is_initialized(treasury_account)? {
+        msg!("Error: Treasury is not initialized.");
+        return Err(HaprError::TreasuryNotInitialized.into());
+    }
+
+    let total_votes = proposal.votes_for + proposal.votes_against;
+    if total_votes < config.quorum_votes {
+        msg!(
+            "Error: {} votes cast, quorum is {}.",
+            total_votes,
+            config.quorum_votes
+        );
+        return Err(HaprError::QuorumNotReached.into());
+    }
+    if proposal.votes_for as u128 * 10_000 <= total_votes as u128 * config.threshold_bps as u128 {
+        msg!(
+            "Error: {} of {} votes in favour does not pass the {} bps threshold.",
+            proposal.votes_for,
+            total_votes,
+            config.threshold_bps
+        );
+        return Err(HaprError::ProposalRejected.into());
+    }
+
+    if proposal.param == ConfigParam::RetireAdmin {
+        haprtreasury::set_admin(treasury_account, &Pubkey::default())?;
+    } else {
+        proposal.param.apply(&mut config, proposal.new_value);
+        haprtreasury::store_config(treasury_account, executor_account, system_program, &config)?;
+        if proposal.param == ConfigParam::StakingAprBps {
+            // The HAPR mint follows, and the Token-2022 program when the mint uses it
+            haprspl::sync_interest_rate(
+                accounts_iter.as_slice(),
+                treasury_account,
+                config.staking_apr_bps,
+                program_id,
+            )?;
+        }
+    }
+
+    proposal.executed = true;
+    haprstate::store(proposal_account, AccountKind::Proposal, &proposal)?;
+
+    debug_msg!("Proposal executed. New config: {:?}", config);
+    HaprEvent::ProposalExecuted {
+        proposal_id: proposal.proposal_id,
+        param: proposal.param as u8,
+        new_value: proposal.new_value,
+    }
+    .emit();
+    Ok(())
+}
+
+// Vote weight of `voter`: the staked amount plus the locked amount scaled by the time left
+// on their veHAPR lock, both taken at `snapshot_time`. Stake or locks changed after the
+// snapshot do not count, so tokens cannot be moved to a new account to vote twice.
+fn voting_weight(
+    staker_account: &AccountInfo,
+    ve_lock_account: Option<&AccountInfo>,
+    voter: &Pubkey,
+    snapshot_time: i64,
+    program_id: &Pubkey,
+) -> Result<u64, ProgramError> {
+    haprvalidate::assert_pda(
+        staker_account,
+        &[voter.as_ref(), STAKER_SEED],
+        program_id,
+        HaprError::InvalidStakerPda,
+        "Staker account",
+    )?;
+
+    let mut weight: u64 = 0;
+    if *staker_account.owner == *program_id {
+        let staker_account_data = staker_account.data.borrow();
+        let staker_data = Staker::load(&staker_account_data)?;
+        if i64::from(staker_data.last_staked_time) <= snapshot_time {
+            weight = weight.saturating_add(staker_data.amount_staked.into());
+        }
+    }
+    if let Some(ve_lock) = haprlock::load_owner_ve_lock(ve_lock_account, voter, program_id)? {
+        if ve_lock.locked_time <= snapshot_time {
+            // Voting power is amount × remaining / MAX_LOCK_DURATION, so this adds up to
+            // (MAX_LOCK_MULTIPLIER_BPS - 1x) of the amount for a full-length lock
+            let lock_bonus = ve_lock.voting_power(snapshot_time) as u128
+                * (MAX_LOCK_MULTIPLIER_BPS - 10_000) as u128
+                / 10_000;
+            weight = weight
+                .saturating_add(ve_lock.amount_locked)
+                .saturating_add(lock_bonus as u64);
+        }
+    }
+
+    if weight == 0 {
+        msg!("Error: {} has no stake or lock eligible to vote.", voter);
+        return Err(HaprError::NoVotingPower.into());
+    }
+    Ok(weight)
+}
+
+fn load_proposal(
+    proposal_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<Proposal, ProgramError> {
+    haprvalidate::assert_owned_by(proposal_account, program_id, "Proposal account")?;
+    let proposal: Proposal = haprstate::load(proposal_account, AccountKind::Proposal)?;
+    haprvalidate::assert_pda(
+        proposal_account,
+        &[PROPOSAL_SEED, &proposal.proposal_id.to_le_bytes()],
+        program_id,
+        HaprError::InvalidProposalPda,
+        "Proposal account",
+    )?;
+    Ok(proposal)
+}
@@ -0,0 +1,201 @@
+use solana_program::{
+    account_info::AccountInfo,
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_instruction, system_program,
+    sysvar::rent::Rent,
+    sysvar::Sysvar,
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::haprerror::HaprError;
+use crate::haprevent::HaprEvent;
+use crate::haprstate::{self, AccountKind, ACCOUNT_HEADER_SIZE};
+use crate::haprtreasury;
+use crate::haprvalidate;
+
+const WALLET_OUTFLOW_SEED: &[u8] = b"wallet_outflow"; // [seed, wallet]
+const WALLET_OUTFLOW_SIZE: usize = ACCOUNT_HEADER_SIZE + 32 + 8 + 8; // Total: 57 bytes
+const DAY: i64 = 24 * 60 * 60;
+
+// Tokens paid out of the treasury to a wallet during the current day
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct WalletOutflow {
+    pub wallet: Pubkey,
+    pub day_start: i64,
+    pub outflow: u64,
+}
+
+// Count `amount` leaving the treasury token account against the outflow limits, and
+// against `wallet`'s daily limit when the tokens go to a wallet. The treasury must be
+// writable once limits are set, and with a wallet limit the wallet's outflow PDA and the
+// system program are looked up among `accounts`; the wallet signs and pays its rent.
+pub fn record_outflow<'a>(
+    accounts: &[AccountInfo<'a>],
+    treasury_account: &AccountInfo<'a>,
+    wallet: Option<&AccountInfo<'a>>,
+    amount: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let mut limits = haprtreasury::load_outflow_limits(treasury_account)?;
+    if limits.window_limit == 0 && limits.wallet_daily_limit == 0 {
+        return Ok(());
+    }
+    let current_time = Clock::get()?.unix_timestamp;
+
+    if limits.window_limit > 0 {
+        if current_time >= limits.window_start + limits.window_length {
+            limits.window_start = current_time - current_time.rem_euclid(limits.window_length);
+            limits.window_outflow = 0;
+        }
+        let window_outflow = limits.window_outflow.saturating_add(amount);
+        if window_outflow > limits.window_limit {
+            msg!(
+                "Error: {} of {} tokens already left the treasury this window; {} requested.",
+                limits.window_outflow,
+                limits.window_limit,
+                amount
+            );
+            return Err(HaprError::OutflowLimitExceeded.into());
+        }
+        limits.window_outflow = window_outflow;
+        haprvalidate::assert_writable(treasury_account, "Treasury account")?;
+        haprtreasury::update_outflow_limits(treasury_account, &limits)?;
+    }
+
+    match wallet {
+        Some(wallet) if limits.wallet_daily_limit > 0 => record_wallet_outflow(
+            accounts,
+            wallet,
+            amount,
+            limits.wallet_daily_limit,
+            current_time,
+            program_id,
+        ),
+        _ => Ok(()),
+    }
+}
+
+fn record_wallet_outflow<'a>(
+    accounts: &[AccountInfo<'a>],
+    wallet: &AccountInfo<'a>,
+    amount: u64,
+    daily_limit: u64,
+    current_time: i64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let (wallet_outflow_pda, wallet_outflow_bump) = Pubkey::find_program_address(
+        &[WALLET_OUTFLOW_SEED, wallet.key.as_ref()],
+        program_id,
+    );
+    let wallet_outflow_account = accounts
+        .iter()
+        .find(|account| *account.key == wallet_outflow_pda)
+        .ok_or_else(|| {
+            msg!("Error: Wallet outflow PDA {} is required.", wallet_outflow_pda);
+            HaprError::InvalidWalletOutflowPda
+        })?;
+    haprvalidate::assert_writable(wallet_outflow_account, "Wallet outflow account")?;
+
+    let mut wallet_outflow = if wallet_outflow_account.data_len() == 0 {
+        let system_program = accounts
+            .iter()
+            .find(|account| system_program::check_id(account.key))
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let rent = Rent::get()?;
+        invoke_signed(
+            &system_instruction::create_account(
+                wallet.key,
+                wallet_outflow_account.key,
+                rent.minimum_balance(WALLET_OUTFLOW_SIZE),
+                WALLET_OUTFLOW_SIZE as u64,
+                program_id,
+            ),
+            &[
+                wallet.clone(),
+                wallet_outflow_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[
+                WALLET_OUTFLOW_SEED,
+                wallet.key.as_ref(),
+                &[wallet_outflow_bump],
+            ]],
+        )?;
+        debug_msg!("Wallet outflow account created.");
+
+        WalletOutflow {
+            wallet: *wallet.key,
+            day_start: 0,
+            outflow: 0,
+        }
+    } else {
+        haprvalidate::assert_owned_by(
+            wallet_outflow_account,
+            program_id,
+            "Wallet outflow account",
+        )?;
+        haprstate::load(wallet_outflow_account, AccountKind::WalletOutflow)?
+    };
+
+    if current_time >= wallet_outflow.day_start + DAY {
+        wallet_outflow.day_start = current_time - current_time.rem_euclid(DAY);
+        wallet_outflow.outflow = 0;
+    }
+    let outflow = wallet_outflow.outflow.saturating_add(amount);
+    if outflow > daily_limit {
+        msg!(
+            "Error: {} already paid {} of {} tokens today; {} more requested.",
+            wallet.key,
+            wallet_outflow.outflow,
+            daily_limit,
+            amount
+        );
+        return Err(HaprError::WalletOutflowLimitExceeded.into());
+    }
+    wallet_outflow.outflow = outflow;
+    haprstate::store(wallet_outflow_account, AccountKind::WalletOutflow, &wallet_outflow)?;
+
+    debug_msg!("Wallet outflow today: {} of {}", outflow, daily_limit);
+    Ok(())
+}
+
+// Replace the outflow limits, keeping the current window's count when the window length
+// is unchanged
+pub fn set_outflow_limits<'a>(
+    treasury_account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    window_length: i64,
+    window_limit: u64,
+    wallet_daily_limit: u64,
+) -> ProgramResult {
+    let mut limits = haprtreasury::load_outflow_limits(treasury_account)?;
+    if limits.window_length != window_length {
+        limits.window_start = 0;
+        limits.window_outflow = 0;
+    }
+    limits.window_length = window_length;
+    limits.window_limit = window_limit;
+    limits.wallet_daily_limit = wallet_daily_limit;
+    haprtreasury::store_outflow_limits(treasury_account, payer, system_program, &limits)?;
+
+    debug_msg!("Outflow limits now {:?}", limits);
+    HaprEvent::OutflowLimitsChanged {
+        window_length,
+        window_limit,
+        wallet_daily_limit,
+    }
+    .emit();
+    Ok(())
+}
+
+// Whether a set of limits can be stored: a window limit needs a positive window length
+pub fn is_valid_limits(window_length: i64, window_limit: u64) -> bool {
+    window_length > 0 || (window_length == 0 && window_limit == 0)
+}
@@ -0,0 +1,336 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::rent::Rent,
+    sysvar::Sysvar,
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::haprerror::HaprError;
+use crate::haprevent::HaprEvent;
+use crate::haprlimits;
+use crate::haprspl;
+use crate::haprstate::{self, AccountKind, ACCOUNT_HEADER_SIZE};
+use crate::haprvalidate;
+
+const TREASURY_SEED: &[u8] = b"treasurythissuperhyperAPRtoken";
+const VE_LOCK_SEED: &[u8] = b"ve_lock"; // [seed, owner]
+
+const VE_LOCK_SIZE: usize = ACCOUNT_HEADER_SIZE + 32 + 8 + 8 + 8; // Total: 65 bytes
+
+// A lock of MAX_LOCK_DURATION starts with one unit of voting power per locked token
+pub const MAX_LOCK_DURATION: i64 = 4 * 365 * 24 * 60 * 60;
+// Staking rewards are boosted by up to this much once the staker's voting power
+// matches their staked amount
+const MAX_REWARD_BOOST_BPS: u64 = 15_000;
+
+// Tokens locked in the treasury ATA until `unlock_time` in exchange for voting power
+// that decays linearly to zero at unlock
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct VeLock {
+    pub owner: Pubkey,
+    pub amount_locked: u64,
+    pub locked_time: i64, // Last time tokens were added or the lock extended
+    pub unlock_time: i64,
+}
+
+impl VeLock {
+    // Voting power at `at_time`: amount × remaining lock / MAX_LOCK_DURATION
+    pub fn voting_power(&self, at_time: i64) -> u64 {
+        if at_time >= self.unlock_time {
+            return 0;
+        }
+        let remaining = (self.unlock_time - at_time).min(MAX_LOCK_DURATION) as u128;
+        (self.amount_locked as u128 * remaining / MAX_LOCK_DURATION as u128) as u64
+    }
+
+    // Voting power averaged over [from, to]. Time before `locked_time` counts as zero since
+    // the lock may have been smaller or shorter then.
+    pub fn average_voting_power(&self, from: i64, to: i64) -> u64 {
+        let start = from.max(self.locked_time);
+        let end = to.min(self.unlock_time);
+        if to <= from || end <= start {
+            return 0;
+        }
+        // Power decays linearly between `start` and `end`
+        let area = (end - start) as u128
+            * (self.voting_power(start) as u128 + self.voting_power(end) as u128)
+            / 2;
+        (area / (to - from) as u128) as u64
+    }
+}
+
+// Staking `reward` accrued from `accrual_start` to `at_time`, boosted by the staker's lock
+// voting power averaged over that period and scaled by how much of `amount_staked` it
+// covers. A lock created just before claiming adds next to nothing.
+pub fn boosted_reward(
+    reward: u64,
+    amount_staked: u64,
+    ve_lock: Option<&VeLock>,
+    accrual_start: i64,
+    at_time: i64,
+) -> u64 {
+    let voting_power = match ve_lock {
+        Some(ve_lock) if amount_staked > 0 => ve_lock.average_voting_power(accrual_start, at_time),
+        _ => return reward,
+    };
+    let boost_bps = MAX_REWARD_BOOST_BPS as u128 * voting_power.min(amount_staked) as u128
+        / amount_staked as u128;
+    (reward as u128 * (10_000 + boost_bps) / 10_000) as u64
+}
+
+// Lock `amount` more tokens and/or push the unlock time out to `unlock_time`.
+// Pass an `unlock_time` of 0 to keep the current one when adding tokens.
+pub fn lock_tokens(
+    accounts: &[AccountInfo],
+    amount: u64,
+    unlock_time: i64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let owner_account = next_account_info(accounts_iter)?; // Owner (pays lock rent)
+    let owner_token_account = next_account_info(accounts_iter)?; // Owner's token account
+    let treasury_token_account = next_account_info(accounts_iter)?; // Treasury's token account
+    let ve_lock_account = next_account_info(accounts_iter)?; // VeLock PDA
+    let token_program = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    // The HAPR mint follows, after the optional treasury PDA read by the pause check
+    let mint_account = haprspl::find_hapr_mint(accounts_iter.as_slice(), program_id)?;
+
+    haprvalidate::assert_signer(owner_account, "Lock owner")?;
+    haprvalidate::assert_token_program(token_program)?;
+    haprvalidate::assert_hapr_token_account(
+        owner_token_account,
+        owner_account.key,
+        program_id,
+        "Owner token account",
+    )?;
+    haprvalidate::assert_treasury_token_account(treasury_token_account, program_id)?;
+
+    let ve_lock_bump = haprvalidate::assert_pda(
+        ve_lock_account,
+        &[VE_LOCK_SEED, owner_account.key.as_ref()],
+        program_id,
+        HaprError::InvalidVeLockPda,
+        "VeLock account",
+    )?;
+    haprvalidate::assert_writable(ve_lock_account, "VeLock account")?;
+
+    let current_time = Clock::get()?.unix_timestamp;
+    let mut ve_lock = if ve_lock_account.data_len() == 0 {
+        let rent = Rent::get()?;
+        invoke_signed(
+            &system_instruction::create_account(
+                owner_account.key,
+                ve_lock_account.key,
+                rent.minimum_balance(VE_LOCK_SIZE),
+                VE_LOCK_SIZE as u64,
+                program_id,
+            ),
+            &[
+                owner_account.clone(),
+                ve_lock_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[VE_LOCK_SEED, owner_account.key.as_ref(), &[ve_lock_bump]]],
+        )?;
+        debug_msg!("VeLock account created.");
+
+        VeLock {
+            owner: *owner_account.key,
+            amount_locked: 0,
+            locked_time: 0,
+            unlock_time: 0,
+        }
+    } else {
+        load_ve_lock(ve_lock_account, program_id)?
+    };
+
+    // Expired locks must be extended before more tokens go in
+    let new_unlock_time = if unlock_time == 0 {
+        ve_lock.unlock_time
+    } else {
+        unlock_time
+    };
+    if new_unlock_time <= current_time
+        || new_unlock_time < ve_lock.unlock_time
+        || new_unlock_time > current_time + MAX_LOCK_DURATION
+        || (amount == 0 && new_unlock_time == ve_lock.unlock_time)
+    {
+        msg!(
+            "Error: Cannot lock {} tokens until {} (current unlock time {}, max {}).",
+            amount,
+            new_unlock_time,
+            ve_lock.unlock_time,
+            current_time + MAX_LOCK_DURATION
+        );
+        return Err(HaprError::InvalidLockDuration.into());
+    }
+
+    if amount > 0 {
+        // The owner covers any transfer fee so the treasury holds the full amount it owes
+        // back. Withdrawing pays out that amount, so a fee is charged again on the way out.
+        let paid = haprspl::transfer_in(
+            token_program,
+            owner_token_account,
+            mint_account,
+            treasury_token_account,
+            owner_account,
+            amount,
+        )?;
+        debug_msg!("Locked {} tokens in the treasury ({} paid).", amount, paid);
+    }
+
+    ve_lock.amount_locked += amount;
+    ve_lock.locked_time = current_time;
+    ve_lock.unlock_time = new_unlock_time;
+    haprstate::store(ve_lock_account, AccountKind::VeLock, &ve_lock)?;
+
+    debug_msg!("Updated lock: {:?}", ve_lock);
+    HaprEvent::Locked {
+        owner: ve_lock.owner,
+        amount,
+        total_locked: ve_lock.amount_locked,
+        unlock_time: ve_lock.unlock_time,
+    }
+    .emit();
+    Ok(())
+}
+
+// Withdraw every locked token once the lock has expired
+pub fn withdraw_lock(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let owner_account = next_account_info(accounts_iter)?; // Owner
+    let ve_lock_account = next_account_info(accounts_iter)?; // VeLock PDA
+    let owner_token_account = next_account_info(accounts_iter)?; // Owner's token account
+    let treasury_token_account = next_account_info(accounts_iter)?; // Treasury's token account
+    let treasury_pda_account = next_account_info(accounts_iter)?; // Treasury PDA (ATA owner)
+    let token_program = next_account_info(accounts_iter)?;
+    let mint_account = haprspl::find_hapr_mint(accounts_iter.as_slice(), program_id)?;
+
+    haprvalidate::assert_signer(owner_account, "Lock owner")?;
+    haprvalidate::assert_writable(ve_lock_account, "VeLock account")?;
+    haprvalidate::assert_token_program(token_program)?;
+
+    let mut ve_lock = load_ve_lock(ve_lock_account, program_id)?;
+    if ve_lock.owner != *owner_account.key {
+        msg!(
+            "Error: Withdrawal attempted by unauthorized user. Expected: {}, Found: {}",
+            ve_lock.owner,
+            owner_account.key
+        );
+        return Err(HaprError::UnauthorizedAccess.into());
+    }
+
+    let current_time = Clock::get()?.unix_timestamp;
+    if current_time < ve_lock.unlock_time {
+        msg!(
+            "Error: Tokens are locked until {}. Current time: {}",
+            ve_lock.unlock_time,
+            current_time
+        );
+        return Err(HaprError::LockNotExpired.into());
+    }
+
+    let (treasury_pda, treasury_bump) =
+        Pubkey::find_program_address(&[TREASURY_SEED], program_id);
+    if *treasury_pda_account.key != treasury_pda {
+        msg!("Error: Invalid Treasury PDA authority");
+        return Err(HaprError::InvalidTreasuryPda.into());
+    }
+    haprvalidate::assert_hapr_token_account(
+        owner_token_account,
+        owner_account.key,
+        program_id,
+        "Owner token account",
+    )?;
+    haprvalidate::assert_treasury_token_account(treasury_token_account, program_id)?;
+
+    // The HAPR mint follows the token program, and with a wallet outflow limit so do the
+    // owner's wallet outflow PDA and the system program. Any transfer fee comes out of the
+    // withdrawn amount, as on every payout.
+    let amount = ve_lock.amount_locked;
+    haprlimits::record_outflow(
+        accounts,
+        treasury_pda_account,
+        Some(owner_account),
+        amount,
+        program_id,
+    )?;
+    haprspl::transfer(
+        token_program,
+        treasury_token_account,
+        mint_account,
+        owner_token_account,
+        treasury_pda_account,
+        amount,
+        &[&[TREASURY_SEED, &[treasury_bump]]],
+    )?;
+
+    ve_lock.amount_locked = 0;
+    haprstate::store(ve_lock_account, AccountKind::VeLock, &ve_lock)?;
+
+    debug_msg!("Withdrew {} unlocked tokens.", amount);
+    HaprEvent::LockWithdrawn {
+        owner: ve_lock.owner,
+        amount,
+    }
+    .emit();
+    Ok(())
+}
+
+pub fn ve_lock_address(owner: &Pubkey, program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[VE_LOCK_SEED, owner.as_ref()], program_id).0
+}
+
+// Load `owner`'s lock from an optional account. Returns None when no account was passed
+// or the lock was never created.
+pub fn load_owner_ve_lock(
+    ve_lock_account: Option<&AccountInfo>,
+    owner: &Pubkey,
+    program_id: &Pubkey,
+) -> Result<Option<VeLock>, ProgramError> {
+    let ve_lock_account = match ve_lock_account {
+        Some(ve_lock_account) => ve_lock_account,
+        None => return Ok(None),
+    };
+    let (ve_lock_pda, _ve_lock_bump) =
+        Pubkey::find_program_address(&[VE_LOCK_SEED, owner.as_ref()], program_id);
+    if *ve_lock_account.key != ve_lock_pda {
+        msg!("Error: VeLock account does not belong to {}", owner);
+        return Err(HaprError::InvalidVeLockPda.into());
+    }
+    if ve_lock_account.data_len() == 0 {
+        return Ok(None);
+    }
+    load_ve_lock(ve_lock_account, program_id).map(Some)
+}
+
+pub fn load_ve_lock(
+    ve_lock_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<VeLock, ProgramError> {
+    haprvalidate::assert_owned_by(ve_lock_account, program_id, "VeLock account")?;
+    haprstate::account_body(&ve_lock_account.data.borrow(), AccountKind::VeLock)?;
+    ve_lock_at(ve_lock_account, program_id).ok_or_else(|| {
+        msg!("Error: Invalid VeLock PDA");
+        HaprError::InvalidVeLockPda.into()
+    })
+}
+
+// The lock held by an account, if it is a VeLock PDA
+pub fn ve_lock_at(account: &AccountInfo, program_id: &Pubkey) -> Option<VeLock> {
+    let data = account.data.borrow();
+    if data.len() != VE_LOCK_SIZE || !haprstate::has_header(&data, AccountKind::VeLock) {
+        return None;
+    }
+    let ve_lock = VeLock::try_from_slice(&data[ACCOUNT_HEADER_SIZE..]).ok()?;
+    (*account.key == ve_lock_address(&ve_lock.owner, program_id)).then_some(ve_lock)
+}
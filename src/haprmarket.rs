@@ -0,0 +1,324 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::rent::Rent,
+    sysvar::Sysvar,
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::haprerror::HaprError;
+use crate::haprspl;
+use crate::haprstate::{self, AccountKind, ACCOUNT_HEADER_SIZE};
+use crate::haprtoken::{ensure_ticket_account, Ticket, TicketAccount};
+use crate::haprtreasury;
+use crate::haprvalidate;
+
+const TICKET_SEED: &[u8] = b"ticket_seed";
+const LISTING_SEED: &[u8] = b"listing"; // [seed, seller, listing_id]
+
+const LISTING_SIZE: usize = ACCOUNT_HEADER_SIZE + 32 + 8 + 8 + (8 + 8 + 8 + 1); // Total: 82 bytes
+const MARKET_FEE_BPS: u64 = 100; // 1% protocol fee sent to the treasury (0 disables it)
+
+// A ticket lot escrowed for sale. The tickets leave the seller's TicketAccount
+// while listed and move to the buyer's TicketAccount on purchase.
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct Listing {
+    pub seller: Pubkey,
+    pub listing_id: u64,
+    pub price: u64, // Total HAPR price for the lot
+    pub ticket: Ticket,
+}
+
+// List a ticket lot (or part of it) for sale at a fixed HAPR price
+pub fn list_ticket_lot(
+    accounts: &[AccountInfo],
+    amount: u64,
+    ticket_index: usize,
+    price: u64,
+    listing_id: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let seller_account = next_account_info(accounts_iter)?; // Seller (pays listing rent)
+    let seller_ticket_account = next_account_info(accounts_iter)?; // Seller's Ticket PDA
+    let listing_account = next_account_info(accounts_iter)?; // Listing PDA
+    let system_program = next_account_info(accounts_iter)?;
+
+    haprvalidate::assert_signer(seller_account, "Seller")?;
+    if price == 0 {
+        msg!("Error: Listing price must be greater than zero.");
+        return Err(HaprError::InvalidListingPrice.into());
+    }
+
+    haprvalidate::assert_program_account(
+        seller_ticket_account,
+        &[TICKET_SEED, seller_account.key.as_ref()],
+        program_id,
+        HaprError::InvalidTicketPda,
+        "Seller ticket account",
+    )?;
+    haprvalidate::assert_writable(seller_ticket_account, "Seller ticket account")?;
+
+    let listing_id_bytes = listing_id.to_le_bytes();
+    let listing_bump = haprvalidate::assert_pda(
+        listing_account,
+        &[LISTING_SEED, seller_account.key.as_ref(), &listing_id_bytes],
+        program_id,
+        HaprError::InvalidListingPda,
+        "Listing account",
+    )?;
+    haprvalidate::assert_writable(listing_account, "Listing account")?;
+    if listing_account.data_len() != 0 {
+        msg!("Error: Listing {} already exists.", listing_id);
+        return Err(HaprError::AccountAlreadyExists.into());
+    }
+
+    // Move the lot out of the seller's TicketAccount
+    let ticket = {
+        let mut ticket_account_bytes = seller_ticket_account.try_borrow_mut_data()?;
+        let ticket_account_data = TicketAccount::load_mut(&mut ticket_account_bytes)?;
+        if ticket_account_data.owner != *seller_account.key {
+            msg!("Unauthorized: Only the owner can list tickets.");
+            return Err(HaprError::UnauthorizedAccess.into());
+        }
+        ticket_account_data.check_draw_lock(Clock::get()?.unix_timestamp)?;
+        ticket_account_data.take_tickets(ticket_index, amount)?
+    };
+
+    // Escrow it in the listing PDA
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            seller_account.key,
+            listing_account.key,
+            rent.minimum_balance(LISTING_SIZE),
+            LISTING_SIZE as u64,
+            program_id,
+        ),
+        &[
+            seller_account.clone(),
+            listing_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[
+            LISTING_SEED,
+            seller_account.key.as_ref(),
+            &listing_id_bytes,
+            &[listing_bump],
+        ]],
+    )?;
+    let listing = Listing {
+        seller: *seller_account.key,
+        listing_id,
+        price,
+        ticket,
+    };
+    haprstate::store(listing_account, AccountKind::Listing, &listing)?;
+
+    debug_msg!("Listed ticket lot: {:?}", listing);
+    Ok(())
+}
+
+// Cancel a listing and return the escrowed lot to the seller
+pub fn cancel_listing(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let seller_account = next_account_info(accounts_iter)?; // Seller (receives listing rent)
+    let seller_ticket_account = next_account_info(accounts_iter)?; // Seller's Ticket PDA
+    let listing_account = next_account_info(accounts_iter)?; // Listing PDA
+    let system_program = next_account_info(accounts_iter)?;
+
+    haprvalidate::assert_signer(seller_account, "Seller")?;
+    haprvalidate::assert_writable(listing_account, "Listing account")?;
+
+    let listing = load_listing(listing_account, program_id)?;
+    if listing.seller != *seller_account.key {
+        msg!("Unauthorized: Only the seller can cancel this listing.");
+        return Err(HaprError::UnauthorizedAccess.into());
+    }
+
+    // Return the lot to the seller
+    ensure_ticket_account(
+        seller_ticket_account,
+        seller_account.key,
+        seller_account,
+        system_program,
+        program_id,
+    )?;
+    TicketAccount::load_mut(&mut seller_ticket_account.try_borrow_mut_data()?)?
+        .add_ticket_lot(listing.ticket)?;
+
+    close_listing(listing_account, seller_account)?;
+
+    debug_msg!("Listing {} cancelled.", listing.listing_id);
+    Ok(())
+}
+
+// Buy a listed lot. The buyer pays the seller, minus the protocol fee which goes
+// to the treasury token account as accrued yield.
+pub fn buy_listed_lot(
+    accounts: &[AccountInfo],
+    max_price: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let buyer_account = next_account_info(accounts_iter)?; // Buyer
+    let buyer_token_account = next_account_info(accounts_iter)?; // Buyer's token account
+    let seller_account = next_account_info(accounts_iter)?; // Seller (receives listing rent)
+    let seller_token_account = next_account_info(accounts_iter)?; // Seller's token account
+    let treasury_token_account = next_account_info(accounts_iter)?; // Treasury's token account
+    let buyer_ticket_account = next_account_info(accounts_iter)?; // Buyer's Ticket PDA
+    let listing_account = next_account_info(accounts_iter)?; // Listing PDA
+    let token_program = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    // The HAPR mint follows, after the optional treasury PDA read by the pause check
+    let mint_account = haprspl::find_hapr_mint(accounts_iter.as_slice(), program_id)?;
+
+    haprvalidate::assert_signer(buyer_account, "Buyer")?;
+    haprvalidate::assert_writable(seller_account, "Seller")?;
+    haprvalidate::assert_writable(listing_account, "Listing account")?;
+    haprvalidate::assert_token_program(token_program)?;
+
+    let listing = load_listing(listing_account, program_id)?;
+    if listing.seller != *seller_account.key {
+        msg!("Error: Seller account does not match the listing.");
+        return Err(HaprError::ListingSellerMismatch.into());
+    }
+    if listing.seller == *buyer_account.key {
+        msg!("Error: Sellers cannot buy their own listing.");
+        return Err(HaprError::SelfTransfer.into());
+    }
+    if listing.price > max_price {
+        msg!(
+            "Error: Listing price {} exceeds the maximum of {}.",
+            listing.price,
+            max_price
+        );
+        return Err(HaprError::ListingPriceExceeded.into());
+    }
+
+    // Proceeds must go to the seller's HAPR account and fees to the canonical treasury ATA
+    haprvalidate::assert_hapr_token_account(
+        buyer_token_account,
+        buyer_account.key,
+        program_id,
+        "Buyer token account",
+    )?;
+    haprvalidate::assert_hapr_token_account(
+        seller_token_account,
+        &listing.seller,
+        program_id,
+        "Seller token account",
+    )?;
+    haprvalidate::assert_treasury_token_account(treasury_token_account, program_id)?;
+
+    let fee =
+        u64::try_from(listing.price as u128 * MARKET_FEE_BPS as u128 / 10_000).map_err(|_| {
+            msg!("Error: Fee on a price of {} overflows.", listing.price);
+            ProgramError::ArithmeticOverflow
+        })?;
+    let seller_proceeds = listing.price - fee;
+
+    haprspl::transfer(
+        token_program,
+        buyer_token_account,
+        mint_account,
+        seller_token_account,
+        buyer_account,
+        seller_proceeds,
+        &[],
+    )?;
+    if fee > 0 {
+        // The fee is treasury income, counted as accrued yield once any transfer fee is
+        // withheld. The treasury PDA is the trailing account passed for the pause check.
+        let treasury_account = haprtreasury::find_treasury(accounts_iter.as_slice(), program_id)?;
+        haprvalidate::assert_writable(treasury_account, "Treasury account")?;
+        let balance = haprspl::unpack_token_account(treasury_token_account)?.amount;
+        haprspl::transfer(
+            token_program,
+            buyer_token_account,
+            mint_account,
+            treasury_token_account,
+            buyer_account,
+            fee,
+            &[],
+        )?;
+        let received = haprspl::unpack_token_account(treasury_token_account)?.amount - balance;
+        haprtreasury::credit_accrued_yield(
+            treasury_account,
+            buyer_account,
+            system_program,
+            received,
+        )?;
+    }
+    debug_msg!(
+        "Paid {} tokens to the seller and {} tokens of fees to the treasury.",
+        seller_proceeds,
+        fee
+    );
+
+    // Deliver the lot to the buyer
+    ensure_ticket_account(
+        buyer_ticket_account,
+        buyer_account.key,
+        buyer_account,
+        system_program,
+        program_id,
+    )?;
+    let number_of_tickets = listing.ticket.number_of_tickets;
+    TicketAccount::load_mut(&mut buyer_ticket_account.try_borrow_mut_data()?)?
+        .add_ticket_lot(listing.ticket)?;
+
+    close_listing(listing_account, seller_account)?;
+
+    debug_msg!(
+        "Bought listing {}: {} tickets for {} tokens.",
+        listing.listing_id,
+        number_of_tickets,
+        listing.price
+    );
+    Ok(())
+}
+
+fn load_listing(listing_account: &AccountInfo, program_id: &Pubkey) -> Result<Listing, ProgramError> {
+    haprvalidate::assert_owned_by(listing_account, program_id, "Listing account")?;
+    let listing: Listing = haprstate::load(listing_account, AccountKind::Listing)?;
+    if *listing_account.key != listing_address(&listing, program_id) {
+        msg!("Error: Invalid Listing PDA");
+        return Err(HaprError::InvalidListingPda.into());
+    }
+    Ok(listing)
+}
+
+// The listing held by an account, if it is a Listing PDA
+pub fn listing_at(account: &AccountInfo, program_id: &Pubkey) -> Option<Listing> {
+    let data = account.data.borrow();
+    if data.len() != LISTING_SIZE || !haprstate::has_header(&data, AccountKind::Listing) {
+        return None;
+    }
+    let listing = Listing::try_from_slice(&data[ACCOUNT_HEADER_SIZE..]).ok()?;
+    (*account.key == listing_address(&listing, program_id)).then_some(listing)
+}
+
+fn listing_address(listing: &Listing, program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            LISTING_SEED,
+            listing.seller.as_ref(),
+            &listing.listing_id.to_le_bytes(),
+        ],
+        program_id,
+    )
+    .0
+}
+
+// Close the listing account, returning its rent to the seller
+fn close_listing(listing_account: &AccountInfo, seller_account: &AccountInfo) -> ProgramResult {
+    haprstate::close_account(listing_account, seller_account)
+}
@@ -0,0 +1,144 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::haprerror::HaprError;
+use crate::haprevent::HaprEvent;
+use crate::haprtreasury;
+use crate::haprvalidate;
+
+const TREASURY_SEED: &[u8] = b"treasurythissuperhyperAPRtoken";
+
+// Circuit breakers, one bit per group of instructions
+pub const PAUSE_MINT: u8 = 1 << 0;
+pub const PAUSE_STAKE: u8 = 1 << 1;
+pub const PAUSE_UNSTAKE: u8 = 1 << 2;
+pub const PAUSE_TICKET_PURCHASE: u8 = 1 << 3;
+pub const PAUSE_TICKET_REDEMPTION: u8 = 1 << 4;
+pub const PAUSE_TICKET_TRANSFER: u8 = 1 << 5; // Ticket transfers and listed lot purchases
+pub const PAUSE_DRAW: u8 = 1 << 6; // Draw settlement and prize payout
+pub const PAUSE_TREASURY_WITHDRAWAL: u8 = 1 << 7; // Queued WithdrawTreasury actions
+pub const PAUSE_ALL: u8 = PAUSE_MINT
+    | PAUSE_STAKE
+    | PAUSE_UNSTAKE
+    | PAUSE_TICKET_PURCHASE
+    | PAUSE_TICKET_REDEMPTION
+    | PAUSE_TICKET_TRANSFER
+    | PAUSE_DRAW
+    | PAUSE_TREASURY_WITHDRAWAL;
+
+// Refuse an instruction whose circuit breaker is tripped. Pausable instructions must
+// pass the treasury PDA, as a trailing account if they don't use it otherwise.
+pub fn assert_not_paused(
+    accounts: &[AccountInfo],
+    flag: u8,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let (treasury_pda, _treasury_bump) =
+        Pubkey::find_program_address(&[TREASURY_SEED], program_id);
+    let treasury_account = accounts
+        .iter()
+        .find(|account| *account.key == treasury_pda)
+        .ok_or_else(|| {
+            msg!("Error: Treasury PDA is required to check the circuit breakers.");
+            HaprError::MissingTreasuryAccount
+        })?;
+    if treasury_account.data_len() == 0 {
+        return Ok(()); // No treasury yet, so nothing can be paused
+    }
+    if *treasury_account.owner != *program_id {
+        msg!("Error: Treasury account is not owned by the program.");
+        return Err(HaprError::InvalidAccountOwner.into());
+    }
+
+    let pause_state = haprtreasury::load_pause_state(treasury_account)?;
+    if pause_state.paused & flag != 0 {
+        msg!(
+            "Error: Instruction is paused (paused flags {:#04x}).",
+            pause_state.paused
+        );
+        return Err(HaprError::InstructionPaused.into());
+    }
+    Ok(())
+}
+
+// Trip the circuit breakers in `flags`. The pause guardian or the treasury authority may
+// pause; the signer pays for growing legacy treasuries.
+pub fn pause(accounts: &[AccountInfo], flags: u8, program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let signer_account = next_account_info(accounts_iter)?; // Pause guardian or treasury admin
+    let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA
+    let system_program = next_account_info(accounts_iter)?;
+    let co_signers = accounts_iter.as_slice(); // Other treasury signers, with a signer set
+
+    check_flags(flags)?;
+    haprtreasury::assert_treasury(treasury_account, program_id)?;
+    haprvalidate::assert_writable(treasury_account, "Treasury account")?;
+    let mut pause_state = haprtreasury::load_pause_state(treasury_account)?;
+    let is_guardian = signer_account.is_signer
+        && pause_state.guardian != Pubkey::default()
+        && pause_state.guardian == *signer_account.key;
+    if !is_guardian {
+        haprtreasury::assert_admin(treasury_account, signer_account, co_signers, program_id)?;
+    }
+
+    pause_state.paused |= flags;
+    haprtreasury::store_pause_state(
+        treasury_account,
+        signer_account,
+        system_program,
+        &pause_state,
+    )?;
+
+    debug_msg!("Paused flags now {:#04x}", pause_state.paused);
+    HaprEvent::PauseChanged {
+        paused: pause_state.paused,
+        by: *signer_account.key,
+    }
+    .emit();
+    Ok(())
+}
+
+// Reset the circuit breakers in `flags` (treasury authority only). Not routed through the
+// admin timelock: unpausing only restores normal operation.
+pub fn unpause(accounts: &[AccountInfo], flags: u8, program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let admin_account = next_account_info(accounts_iter)?; // Treasury admin
+    let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA
+    let system_program = next_account_info(accounts_iter)?;
+    let co_signers = accounts_iter.as_slice(); // Other treasury signers, with a signer set
+
+    check_flags(flags)?;
+    haprtreasury::assert_admin(treasury_account, admin_account, co_signers, program_id)?;
+    haprvalidate::assert_writable(treasury_account, "Treasury account")?;
+
+    let mut pause_state = haprtreasury::load_pause_state(treasury_account)?;
+    pause_state.paused &= !flags;
+    haprtreasury::store_pause_state(
+        treasury_account,
+        admin_account,
+        system_program,
+        &pause_state,
+    )?;
+
+    debug_msg!("Paused flags now {:#04x}", pause_state.paused);
+    HaprEvent::PauseChanged {
+        paused: pause_state.paused,
+        by: *admin_account.key,
+    }
+    .emit();
+    Ok(())
+}
+
+// Every bit of the flags byte names a breaker, so only an empty set is invalid
+fn check_flags(flags: u8) -> ProgramResult {
+    if flags == 0 {
+        msg!("Error: Invalid pause flags {:#04x}", flags);
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    Ok(())
+}
@@ -0,0 +1,486 @@
+use solana_program::{
+    account_info::AccountInfo,
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use spl_token_2022::extension::{
+    interest_bearing_mint::{instruction::update_rate, InterestBearingConfig},
+    transfer_fee::{
+        instruction::{set_transfer_fee, withdraw_withheld_tokens_from_mint},
+        TransferFeeConfig,
+    },
+    BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+};
+use spl_token_2022::instruction::{burn_checked, mint_to_checked, transfer_checked};
+use spl_token_2022::state::{Account, Mint};
+use spl_token_metadata_interface::state::{Field, TokenMetadata};
+
+use crate::haprerror::HaprError;
+
+const SEED: &[u8] = b"mintthissuperhyperAPRtoken"; // Seed for the HAPR mint PDA
+const TREASURY_SEED: &[u8] = b"treasurythissuperhyperAPRtoken";
+
+// Longest token metadata value an admin action may set; keeps queued actions within their
+// fixed account size
+pub const MAX_METADATA_VALUE_LEN: usize = 200;
+
+// Token metadata fields of the HAPR mint the admin can change
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataField {
+    Name,
+    Symbol,
+    Uri,
+}
+
+impl MetadataField {
+    fn field(&self) -> Field {
+        match self {
+            MetadataField::Name => Field::Name,
+            MetadataField::Symbol => Field::Symbol,
+            MetadataField::Uri => Field::Uri,
+        }
+    }
+}
+
+// Token program plumbing for the HAPR mint, which is owned by either SPL Token or Token-2022.
+// Every CPI goes through the *_checked instructions so the mint is always passed along, and
+// the token program is the one that owns the mint. Both programs share the base account and
+// mint layouts, so the state readers here accept either.
+
+pub fn is_token_program(program_id: &Pubkey) -> bool {
+    *program_id == spl_token::id() || *program_id == spl_token_2022::id()
+}
+
+pub fn unpack_mint(mint: &AccountInfo) -> Result<Mint, ProgramError> {
+    let data = mint.try_borrow_data()?;
+    Ok(StateWithExtensions::<Mint>::unpack(&data)?.base)
+}
+
+pub fn unpack_token_account(account: &AccountInfo) -> Result<Account, ProgramError> {
+    let data = account.try_borrow_data()?;
+    Ok(StateWithExtensions::<Account>::unpack(&data)?.base)
+}
+
+// The HAPR mint among `accounts`. Handlers that take it after their optional accounts
+// find it by key, so it can come anywhere after the fixed ones.
+pub fn find_hapr_mint<'a, 'b>(
+    accounts: &'b [AccountInfo<'a>],
+    program_id: &Pubkey,
+) -> Result<&'b AccountInfo<'a>, ProgramError> {
+    let (mint_pda, _mint_bump) = Pubkey::find_program_address(&[SEED], program_id);
+    let Some(mint_account) = accounts.iter().find(|account| *account.key == mint_pda) else {
+        msg!(
+            "Error: HAPR mint {} is missing from the accounts.",
+            mint_pda
+        );
+        return Err(HaprError::InvalidMintPda.into());
+    };
+    if !is_token_program(mint_account.owner) {
+        msg!(
+            "Error: HAPR mint is owned by {}, not a token program.",
+            mint_account.owner
+        );
+        return Err(HaprError::IncorrectTokenProgram.into());
+    }
+    Ok(mint_account)
+}
+
+fn assert_mint_program(token_program: &AccountInfo, mint: &AccountInfo) -> ProgramResult {
+    if *token_program.key != *mint.owner {
+        msg!(
+            "Error: Mint {} is owned by {}, not token program {}.",
+            mint.key,
+            mint.owner,
+            token_program.key
+        );
+        return Err(HaprError::IncorrectTokenProgram.into());
+    }
+    Ok(())
+}
+
+// Move `amount` from `source`; the recipient gets less if the mint charges a transfer fee
+pub fn transfer<'a>(
+    token_program: &AccountInfo<'a>,
+    source: &AccountInfo<'a>,
+    mint: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    authority: &AccountInfo<'a>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    assert_mint_program(token_program, mint)?;
+    let decimals = unpack_mint(mint)?.decimals;
+    invoke_signed(
+        &transfer_checked(
+            token_program.key,
+            source.key,
+            mint.key,
+            destination.key,
+            authority.key,
+            &[],
+            amount,
+            decimals,
+        )?,
+        &[
+            source.clone(),
+            mint.clone(),
+            destination.clone(),
+            authority.clone(),
+            token_program.clone(),
+        ],
+        signer_seeds,
+    )
+}
+
+// Deposit `amount` into the treasury: the sender pays any transfer fee on top, so the
+// treasury is credited the full amount it now owes back. Returns what left the sender.
+pub fn transfer_in<'a>(
+    token_program: &AccountInfo<'a>,
+    source: &AccountInfo<'a>,
+    mint: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    authority: &AccountInfo<'a>,
+    amount: u64,
+) -> Result<u64, ProgramError> {
+    let gross_amount = amount_with_fee(mint, amount)?;
+    transfer(
+        token_program,
+        source,
+        mint,
+        destination,
+        authority,
+        gross_amount,
+        &[],
+    )?;
+    Ok(gross_amount)
+}
+
+// What to send so that `amount` arrives after the mint's current transfer fee
+pub fn amount_with_fee(mint: &AccountInfo, amount: u64) -> Result<u64, ProgramError> {
+    if *mint.owner != spl_token_2022::id() {
+        return Ok(amount);
+    }
+    let data = mint.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<Mint>::unpack(&data)?;
+    match mint_state.get_extension::<TransferFeeConfig>() {
+        Ok(fee_config) => gross_up(fee_config, Clock::get()?.epoch, amount),
+        Err(_) => Ok(amount),
+    }
+}
+
+fn gross_up(fee_config: &TransferFeeConfig, epoch: u64, amount: u64) -> Result<u64, ProgramError> {
+    fee_config
+        .calculate_inverse_epoch_fee(epoch, amount)
+        .and_then(|fee| amount.checked_add(fee))
+        .ok_or(ProgramError::ArithmeticOverflow)
+}
+
+pub fn mint_to<'a>(
+    token_program: &AccountInfo<'a>,
+    mint: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    authority: &AccountInfo<'a>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    assert_mint_program(token_program, mint)?;
+    let decimals = unpack_mint(mint)?.decimals;
+    invoke_signed(
+        &mint_to_checked(
+            token_program.key,
+            mint.key,
+            destination.key,
+            authority.key,
+            &[],
+            amount,
+            decimals,
+        )?,
+        &[
+            mint.clone(),
+            destination.clone(),
+            authority.clone(),
+            token_program.clone(),
+        ],
+        signer_seeds,
+    )
+}
+
+pub fn burn<'a>(
+    token_program: &AccountInfo<'a>,
+    source: &AccountInfo<'a>,
+    mint: &AccountInfo<'a>,
+    authority: &AccountInfo<'a>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    assert_mint_program(token_program, mint)?;
+    let decimals = unpack_mint(mint)?.decimals;
+    invoke_signed(
+        &burn_checked(
+            token_program.key,
+            source.key,
+            mint.key,
+            authority.key,
+            &[],
+            amount,
+            decimals,
+        )?,
+        &[
+            source.clone(),
+            mint.clone(),
+            authority.clone(),
+            token_program.clone(),
+        ],
+        signer_seeds,
+    )
+}
+
+// Size of a token account for `mint`, including the extensions its mint requires
+pub fn token_account_len(mint: &AccountInfo) -> Result<usize, ProgramError> {
+    let data = mint.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<Mint>::unpack(&data)?;
+    let account_extensions =
+        ExtensionType::get_required_init_account_extensions(&mint_state.get_extension_types()?);
+    ExtensionType::try_calculate_account_len::<Account>(&account_extensions)
+}
+
+// Keep a Token-2022 mint's interest-bearing rate equal to the staking APR. SPL Token mints,
+// and mints whose rate the treasury PDA does not control, are left as they are.
+pub fn sync_interest_rate<'a>(
+    accounts: &[AccountInfo<'a>],
+    treasury_account: &AccountInfo<'a>,
+    staking_apr_bps: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let mint_account = find_hapr_mint(accounts, program_id)?;
+    if *mint_account.owner != spl_token_2022::id() {
+        return Ok(());
+    }
+    let rate_authority = {
+        let data = mint_account.try_borrow_data()?;
+        let mint_state = StateWithExtensions::<Mint>::unpack(&data)?;
+        match mint_state.get_extension::<InterestBearingConfig>() {
+            Ok(interest_config) => Option::<Pubkey>::from(interest_config.rate_authority),
+            Err(_) => None,
+        }
+    };
+    let (treasury_pda, treasury_bump) = Pubkey::find_program_address(&[TREASURY_SEED], program_id);
+    if rate_authority != Some(treasury_pda) {
+        debug_msg!("HAPR mint rate is not controlled by the treasury; leaving it.");
+        return Ok(());
+    }
+
+    let Some(token_program) = accounts
+        .iter()
+        .find(|account| *account.key == spl_token_2022::id())
+    else {
+        msg!("Error: Token-2022 program is missing from the accounts.");
+        return Err(HaprError::IncorrectTokenProgram.into());
+    };
+    let rate = i16::try_from(staking_apr_bps).map_err(|_| ProgramError::InvalidArgument)?;
+    invoke_signed(
+        &update_rate(
+            token_program.key,
+            mint_account.key,
+            &treasury_pda,
+            &[],
+            rate,
+        )?,
+        &[
+            mint_account.clone(),
+            treasury_account.clone(),
+            token_program.clone(),
+        ],
+        &[&[TREASURY_SEED, &[treasury_bump]]],
+    )?;
+    debug_msg!("HAPR mint interest rate set to {} bps.", rate);
+    Ok(())
+}
+
+// The Token-2022 extensions of the HAPR mint are controlled by the treasury PDA
+fn assert_token_2022_mint(token_program: &AccountInfo, mint: &AccountInfo) -> ProgramResult {
+    assert_mint_program(token_program, mint)?;
+    if *token_program.key != spl_token_2022::id() {
+        msg!("Error: HAPR mint is not a Token-2022 mint.");
+        return Err(HaprError::IncorrectTokenProgram.into());
+    }
+    Ok(())
+}
+
+// Set the HAPR mint's transfer fee. Token-2022 applies it from the epoch after next.
+pub fn update_transfer_fee<'a>(
+    token_program: &AccountInfo<'a>,
+    mint: &AccountInfo<'a>,
+    treasury_account: &AccountInfo<'a>,
+    transfer_fee_basis_points: u16,
+    maximum_fee: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    assert_token_2022_mint(token_program, mint)?;
+    let (treasury_pda, treasury_bump) = Pubkey::find_program_address(&[TREASURY_SEED], program_id);
+    invoke_signed(
+        &set_transfer_fee(
+            token_program.key,
+            mint.key,
+            &treasury_pda,
+            &[],
+            transfer_fee_basis_points,
+            maximum_fee,
+        )?,
+        &[
+            mint.clone(),
+            treasury_account.clone(),
+            token_program.clone(),
+        ],
+        &[&[TREASURY_SEED, &[treasury_bump]]],
+    )?;
+    debug_msg!(
+        "HAPR transfer fee set to {} bps, at most {}.",
+        transfer_fee_basis_points,
+        maximum_fee
+    );
+    Ok(())
+}
+
+// Move the transfer fees withheld in the HAPR mint to the treasury token account. Fees
+// withheld in holders' accounts must be harvested to the mint first, which anyone can do.
+pub fn withdraw_withheld_fees<'a>(
+    token_program: &AccountInfo<'a>,
+    mint: &AccountInfo<'a>,
+    treasury_token_account: &AccountInfo<'a>,
+    treasury_account: &AccountInfo<'a>,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    assert_token_2022_mint(token_program, mint)?;
+    let (treasury_pda, treasury_bump) = Pubkey::find_program_address(&[TREASURY_SEED], program_id);
+    invoke_signed(
+        &withdraw_withheld_tokens_from_mint(
+            token_program.key,
+            mint.key,
+            treasury_token_account.key,
+            &treasury_pda,
+            &[],
+        )?,
+        &[
+            mint.clone(),
+            treasury_token_account.clone(),
+            treasury_account.clone(),
+            token_program.clone(),
+        ],
+        &[&[TREASURY_SEED, &[treasury_bump]]],
+    )
+}
+
+// Update a field of the HAPR mint's token metadata. Token-2022 resizes the mint for the new
+// value but does not fund it, so the payer first tops up its rent.
+#[allow(clippy::too_many_arguments)]
+pub fn update_token_metadata<'a>(
+    token_program: &AccountInfo<'a>,
+    mint: &AccountInfo<'a>,
+    treasury_account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    field: MetadataField,
+    value: String,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    assert_token_2022_mint(token_program, mint)?;
+    let new_len = {
+        let data = mint.try_borrow_data()?;
+        let mint_state = StateWithExtensions::<Mint>::unpack(&data)?;
+        let mut metadata = mint_state.get_variable_len_extension::<TokenMetadata>()?;
+        let old_size = metadata.tlv_size_of()?;
+        metadata.update(field.field(), value.clone());
+        (data.len() + metadata.tlv_size_of()?)
+            .checked_sub(old_size)
+            .ok_or(ProgramError::ArithmeticOverflow)?
+    };
+    let shortfall = Rent::get()?
+        .minimum_balance(new_len)
+        .saturating_sub(mint.lamports());
+    if shortfall > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, mint.key, shortfall),
+            &[payer.clone(), mint.clone(), system_program.clone()],
+        )?;
+    }
+
+    let (treasury_pda, treasury_bump) = Pubkey::find_program_address(&[TREASURY_SEED], program_id);
+    invoke_signed(
+        &spl_token_metadata_interface::instruction::update_field(
+            token_program.key,
+            mint.key,
+            &treasury_pda,
+            field.field(),
+            value,
+        ),
+        &[
+            mint.clone(),
+            treasury_account.clone(),
+            token_program.clone(),
+        ],
+        &[&[TREASURY_SEED, &[treasury_bump]]],
+    )?;
+    debug_msg!("HAPR token metadata {:?} updated.", field);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spl_token_2022::extension::transfer_fee::TransferFee;
+
+    fn fee_config(basis_points: u16, maximum_fee: u64) -> TransferFeeConfig {
+        let fee = TransferFee {
+            epoch: 0.into(),
+            maximum_fee: maximum_fee.into(),
+            transfer_fee_basis_points: basis_points.into(),
+        };
+        TransferFeeConfig {
+            older_transfer_fee: fee,
+            newer_transfer_fee: fee,
+            ..TransferFeeConfig::default()
+        }
+    }
+
+    #[test]
+    fn grossed_up_transfer_delivers_the_full_amount() {
+        for (basis_points, maximum_fee) in [(0, 0), (100, u64::MAX), (250, 1_000), (9_999, 5)] {
+            let config = fee_config(basis_points, maximum_fee);
+            for amount in [1, 99, 1_000_000, 123_456_789_000] {
+                let gross = gross_up(&config, 0, amount).unwrap();
+                let fee = config.calculate_epoch_fee(0, gross).unwrap();
+                assert_eq!(
+                    gross - fee,
+                    amount,
+                    "{} bps, max {}",
+                    basis_points,
+                    maximum_fee
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn gross_up_that_overflows_is_an_error() {
+        assert_eq!(
+            gross_up(&fee_config(100, u64::MAX), 0, u64::MAX),
+            Err(ProgramError::ArithmeticOverflow)
+        );
+    }
+
+    #[test]
+    fn both_token_programs_are_accepted() {
+        assert!(is_token_program(&spl_token::id()));
+        assert!(is_token_program(&spl_token_2022::id()));
+        assert!(!is_token_program(&Pubkey::new_unique()));
+    }
+}
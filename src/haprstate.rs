@@ -0,0 +1,304 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+
+use crate::haprerror::HaprError;
+use crate::haprevent::HaprEvent;
+use crate::haprtoken::{self, grow_account};
+use crate::haprtreasury;
+use crate::haprvalidate;
+
+// Every program-owned state account starts with an 8-byte discriminator naming its type
+// and the version of the layout that follows. The treasury keeps its fixed-offset regions,
+// counted from the end of its header.
+pub const ACCOUNT_HEADER_SIZE: usize = 8 + 1;
+pub const ACCOUNT_VERSION: u8 = 1; // Layout written by this program, see AccountKind::version
+const TICKET_ACCOUNT_VERSION: u8 = 2; // Fixed lot slots and a receipt ring
+
+// Written over the start of a closed account so it can't be loaded again if lamports are
+// sent back to it before the end of the transaction
+pub const CLOSED_ACCOUNT_DISCRIMINATOR: [u8; 8] = [255; 8];
+
+// Program-owned account types. The code is the `amount` passed to MigrateAccount and
+// must never change.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AccountKind {
+    Staker = 0,
+    TicketAccount = 1,
+    VeLock = 2,
+    DrawState = 3,
+    DrawEntry = 4,
+    Proposal = 5,
+    VoteRecord = 6,
+    Listing = 7,
+    TicketLot = 8,
+    AdminAction = 9,
+    WalletOutflow = 10,
+    Treasury = 11,
+}
+
+// Every kind, indexed by its code
+const ALL_KINDS: [AccountKind; 12] = [
+    AccountKind::Staker,
+    AccountKind::TicketAccount,
+    AccountKind::VeLock,
+    AccountKind::DrawState,
+    AccountKind::DrawEntry,
+    AccountKind::Proposal,
+    AccountKind::VoteRecord,
+    AccountKind::Listing,
+    AccountKind::TicketLot,
+    AccountKind::AdminAction,
+    AccountKind::WalletOutflow,
+    AccountKind::Treasury,
+];
+
+impl AccountKind {
+    pub fn from_code(code: u64) -> Option<Self> {
+        ALL_KINDS.get(usize::try_from(code).ok()?).copied()
+    }
+
+    pub fn discriminator(&self) -> [u8; 8] {
+        match self {
+            AccountKind::Staker => *b"haprstkr",
+            AccountKind::TicketAccount => *b"haprtckt",
+            AccountKind::VeLock => *b"haprvlck",
+            AccountKind::DrawState => *b"haprdraw",
+            AccountKind::DrawEntry => *b"haprdent",
+            AccountKind::Proposal => *b"haprprop",
+            AccountKind::VoteRecord => *b"haprvote",
+            AccountKind::Listing => *b"haprlist",
+            AccountKind::TicketLot => *b"haprtlot",
+            AccountKind::AdminAction => *b"hapractn",
+            AccountKind::WalletOutflow => *b"haprwout",
+            AccountKind::Treasury => *b"haprtrsy",
+        }
+    }
+
+    // Layout version written for this kind
+    pub fn version(&self) -> u8 {
+        match self {
+            AccountKind::TicketAccount => TICKET_ACCOUNT_VERSION,
+            _ => ACCOUNT_VERSION,
+        }
+    }
+}
+
+// Write the header for `kind` at the start of an account's data
+pub fn write_header(data: &mut [u8], kind: AccountKind) {
+    data[..8].copy_from_slice(&kind.discriminator());
+    data[8] = kind.version();
+}
+
+// Whether `data` starts with the current header for `kind`
+pub fn has_header(data: &[u8], kind: AccountKind) -> bool {
+    data.starts_with(&kind.discriminator()) && data.get(8) == Some(&kind.version())
+}
+
+// The data of a `kind` account after its header. Closed accounts, accounts of another type
+// and accounts in an older layout (see `migrate_account`) are rejected.
+pub fn account_body(data: &[u8], kind: AccountKind) -> Result<&[u8], ProgramError> {
+    check_header(data, kind)?;
+    Ok(&data[ACCOUNT_HEADER_SIZE..])
+}
+
+// Mutable `account_body`
+pub fn account_body_mut(data: &mut [u8], kind: AccountKind) -> Result<&mut [u8], ProgramError> {
+    check_header(data, kind)?;
+    Ok(&mut data[ACCOUNT_HEADER_SIZE..])
+}
+
+fn check_header(data: &[u8], kind: AccountKind) -> ProgramResult {
+    if data.starts_with(&CLOSED_ACCOUNT_DISCRIMINATOR) {
+        msg!("Error: {:?} account has been closed.", kind);
+        return Err(HaprError::AccountClosed.into());
+    }
+    if data.len() < ACCOUNT_HEADER_SIZE || data[..8] != kind.discriminator() {
+        msg!(
+            "Error: Not a {:?} account. Legacy stakers, ticket accounts and treasuries must be migrated.",
+            kind
+        );
+        return Err(HaprError::InvalidAccountDiscriminator.into());
+    }
+    if data[8] != kind.version() {
+        msg!(
+            "Error: {:?} account has layout version {}, expected {}.",
+            kind,
+            data[8],
+            kind.version()
+        );
+        return Err(HaprError::UnsupportedAccountVersion.into());
+    }
+    Ok(())
+}
+
+// Serialize `value` behind the header for `kind`
+pub fn pack<T: BorshSerialize>(value: &T, kind: AccountKind, data: &mut [u8]) -> ProgramResult {
+    write_header(data, kind);
+    value.serialize(&mut &mut data[ACCOUNT_HEADER_SIZE..])?;
+    Ok(())
+}
+
+// Deserialize a fixed-size `kind` account
+pub fn load<T: BorshDeserialize>(
+    account: &AccountInfo,
+    kind: AccountKind,
+) -> Result<T, ProgramError> {
+    Ok(T::try_from_slice(account_body(&account.try_borrow_data()?, kind)?)?)
+}
+
+// Serialize `value` into a `kind` account
+pub fn store<T: BorshSerialize>(
+    account: &AccountInfo,
+    kind: AccountKind,
+    value: &T,
+) -> ProgramResult {
+    pack(value, kind, &mut account.try_borrow_mut_data()?)
+}
+
+// View a fixed-size `kind` account in place. `T` must only hold byte-aligned fields since
+// the body starts right after the 9-byte header.
+pub fn load_pod<T: Pod>(data: &[u8], kind: AccountKind) -> Result<&T, ProgramError> {
+    pod_body(account_body(data, kind)?, kind)
+}
+
+// Mutable `load_pod`
+pub fn load_pod_mut<T: Pod>(data: &mut [u8], kind: AccountKind) -> Result<&mut T, ProgramError> {
+    pod_body_mut(account_body_mut(data, kind)?, kind)
+}
+
+// Write the header for `kind` over a new account and return its zeroed body
+pub fn init_pod<T: Pod>(data: &mut [u8], kind: AccountKind) -> Result<&mut T, ProgramError> {
+    data.fill(0);
+    write_header(data, kind);
+    pod_body_mut(&mut data[ACCOUNT_HEADER_SIZE..], kind)
+}
+
+fn pod_body<T: Pod>(body: &[u8], kind: AccountKind) -> Result<&T, ProgramError> {
+    body.get(..std::mem::size_of::<T>())
+        .and_then(|body| bytemuck::try_from_bytes(body).ok())
+        .ok_or_else(|| {
+            msg!("Error: {:?} account is too short.", kind);
+            ProgramError::InvalidAccountData
+        })
+}
+
+fn pod_body_mut<T: Pod>(body: &mut [u8], kind: AccountKind) -> Result<&mut T, ProgramError> {
+    body.get_mut(..std::mem::size_of::<T>())
+        .and_then(|body| bytemuck::try_from_bytes_mut(body).ok())
+        .ok_or_else(|| {
+            msg!("Error: {:?} account is too short.", kind);
+            ProgramError::InvalidAccountData
+        })
+}
+
+// Close a program account: move its lamports to `destination`, zero its data and mark it
+// with CLOSED_ACCOUNT_DISCRIMINATOR
+pub fn close_account(account: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+    let lamports = account.lamports();
+    **account.try_borrow_mut_lamports()? = 0;
+    **destination.try_borrow_mut_lamports()? += lamports;
+    let mut data = account.try_borrow_mut_data()?;
+    data.fill(0);
+    data[..CLOSED_ACCOUNT_DISCRIMINATOR.len()].copy_from_slice(&CLOSED_ACCOUNT_DISCRIMINATOR);
+    debug_msg!("Closed {}, returning {} lamports.", account.key, lamports);
+    Ok(())
+}
+
+// Upgrade a staker, ticket account or treasury created before account headers, or a ticket
+// account in the version 1 layout, in place: the legacy data is read in its old layout, checked
+// against the PDA it must live at and rewritten behind a current header. Anyone may
+// migrate an account; the payer covers the extra rent but never receives the account's
+// lamports. Accounts that already carry the current header are left alone.
+pub fn migrate_account(
+    accounts: &[AccountInfo],
+    kind: AccountKind,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer_account = next_account_info(accounts_iter)?; // Pays for the larger account
+    let account = next_account_info(accounts_iter)?; // Account to migrate
+    let system_program = next_account_info(accounts_iter)?;
+
+    haprvalidate::assert_signer(payer_account, "Payer")?;
+    haprvalidate::assert_writable(account, "Migrated account")?;
+    haprvalidate::assert_owned_by(account, program_id, "Migrated account")?;
+    haprvalidate::assert_system_program(system_program)?;
+
+    let (address, body) = {
+        let data = account.try_borrow_data()?;
+        if has_header(&data, kind) {
+            debug_msg!("{:?} account {} is already migrated.", kind, account.key);
+            return Ok(());
+        }
+        let legacy_data = if data.starts_with(&kind.discriminator())
+            && data.get(8).is_some_and(|version| *version < kind.version())
+        {
+            &data[ACCOUNT_HEADER_SIZE..] // Body of an older layout
+        } else if data.starts_with(&CLOSED_ACCOUNT_DISCRIMINATOR)
+            || ALL_KINDS
+                .iter()
+                .any(|other| data.starts_with(&other.discriminator()))
+        {
+            msg!("Error: {} is not a legacy {:?} account.", account.key, kind);
+            return Err(HaprError::InvalidAccountDiscriminator.into());
+        } else {
+            &data[..] // Headerless
+        };
+        legacy_layout(kind, legacy_data, program_id).map_err(|_| {
+            msg!("Error: {} does not hold a legacy {:?} layout.", account.key, kind);
+            HaprError::InvalidAccountDiscriminator
+        })?
+    };
+    if address != *account.key {
+        msg!(
+            "Error: Legacy {:?} data belongs at {}, not {}.",
+            kind,
+            address,
+            account.key
+        );
+        return Err(HaprError::InvalidAccountDiscriminator.into());
+    }
+
+    grow_account(
+        account,
+        payer_account,
+        system_program,
+        ACCOUNT_HEADER_SIZE + body.len(),
+    )?;
+    let mut data = account.try_borrow_mut_data()?;
+    write_header(&mut data, kind);
+    data[ACCOUNT_HEADER_SIZE..].copy_from_slice(&body);
+
+    debug_msg!("Migrated {:?} account {}.", kind, account.key);
+    HaprEvent::AccountMigrated {
+        account: *account.key,
+        kind: kind as u8,
+        version: kind.version(),
+    }
+    .emit();
+    Ok(())
+}
+
+// The PDA a legacy `kind` account must live at and its data in the current layout.
+// Only stakers, ticket accounts and the treasury predate account headers, and only ticket
+// accounts have changed layout since.
+fn legacy_layout(
+    kind: AccountKind,
+    data: &[u8],
+    program_id: &Pubkey,
+) -> Result<(Pubkey, Vec<u8>), ProgramError> {
+    match kind {
+        AccountKind::Staker => haprtoken::migrate_staker(data, program_id),
+        AccountKind::TicketAccount => haprtoken::migrate_ticket_account(data, program_id),
+        AccountKind::Treasury => haprtreasury::migrate_treasury(data, program_id),
+        _ => Err(HaprError::InvalidAccountDiscriminator.into()),
+    }
+}
@@ -0,0 +1,421 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::rent::Rent,
+    sysvar::Sysvar,
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use spl_associated_token_account::{
+    get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
+};
+use spl_token_2022::instruction::{initialize_mint, set_authority, AuthorityType};
+use spl_token_2022::state::Mint;
+
+use crate::haprerror::HaprError;
+use crate::haprevent::HaprEvent;
+use crate::haprlimits;
+use crate::haprspl;
+use crate::haprstate::{self, AccountKind, ACCOUNT_HEADER_SIZE};
+use crate::haprtoken::{calculate_ticket_yield, pay_ticket_redemption, FIXED_TICKET_PRICE};
+use crate::haprtreasury;
+use crate::haprvalidate;
+
+const SEED: &[u8] = b"mintthissuperhyperAPRtoken"; // Seed for the HAPR mint PDA
+const TREASURY_SEED: &[u8] = b"treasurythissuperhyperAPRtoken";
+const TICKET_LOT_MINT_SEED: &[u8] = b"ticket_lot_mint"; // [seed, buyer, nonce]
+const TICKET_LOT_SEED: &[u8] = b"ticket_lot"; // [seed, lot mint]
+
+const TICKET_LOT_SIZE: usize = ACCOUNT_HEADER_SIZE + 32 + 8 + 8 + 8 + 1; // Total: 66 bytes
+
+// A ticket lot tokenized as its own SPL mint, under the same token program as the HAPR
+// mint. Each token unit is one ticket (decimals 0), so a single-ticket lot is an NFT and
+// larger lots are SFTs sharing the same deposit time and vesting period.
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct TicketLot {
+    pub mint: Pubkey,           // Lot mint held by the ticket owner(s)
+    pub number_of_tickets: u64, // Outstanding tickets (equals the lot mint supply)
+    pub deposit_time: i64,
+    pub vesting_period: i64,
+    pub claimed: bool, // Set once every ticket in the lot has been redeemed
+}
+
+//Purchase tickets as a tokenized lot held in the buyer's wallet
+pub fn purchase_ticket_lot(
+    accounts: &[AccountInfo],
+    amount: u64,
+    vesting_period: i64,
+    nonce: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let buyer_account = next_account_info(accounts_iter)?; // Buyer
+    let buyer_token_account = next_account_info(accounts_iter)?; // Buyer's HAPR token account
+    let treasury_token_account = next_account_info(accounts_iter)?; // Treasury's token account
+    let treasury_pda_account = next_account_info(accounts_iter)?; // Treasury PDA (lot mint authority)
+    let lot_mint_account = next_account_info(accounts_iter)?; // Lot mint PDA
+    let ticket_lot_account = next_account_info(accounts_iter)?; // Lot record PDA
+    let buyer_lot_token_account = next_account_info(accounts_iter)?; // Buyer's ATA for the lot mint
+    let token_program = next_account_info(accounts_iter)?;
+    let associated_token_program = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let sysvar_rent = next_account_info(accounts_iter)?;
+    // The HAPR mint follows, after the optional treasury PDA read by the pause check
+    let mint_account = haprspl::find_hapr_mint(accounts_iter.as_slice(), program_id)?;
+
+    haprvalidate::assert_signer(buyer_account, "Buyer")?;
+    haprvalidate::assert_writable(lot_mint_account, "Ticket lot mint")?;
+    haprvalidate::assert_writable(ticket_lot_account, "Ticket lot account")?;
+    haprvalidate::assert_token_program(token_program)?;
+    haprvalidate::assert_system_program(system_program)?;
+    haprvalidate::assert_hapr_token_account(
+        buyer_token_account,
+        buyer_account.key,
+        program_id,
+        "Buyer token account",
+    )?;
+    haprvalidate::assert_treasury_token_account(treasury_token_account, program_id)?;
+
+    // Verify PDA authority
+    let (treasury_pda, treasury_bump) = Pubkey::find_program_address(&[TREASURY_SEED], program_id);
+    if *treasury_pda_account.key != treasury_pda {
+        msg!("Error: Invalid PDA authority");
+        return Err(HaprError::InvalidTreasuryPda.into());
+    }
+
+    // Validate the lot mint and lot record PDAs
+    let nonce_bytes = nonce.to_le_bytes();
+    let (lot_mint_pda, lot_mint_bump) = Pubkey::find_program_address(
+        &[TICKET_LOT_MINT_SEED, buyer_account.key.as_ref(), &nonce_bytes],
+        program_id,
+    );
+    if *lot_mint_account.key != lot_mint_pda {
+        msg!("Error: Invalid Ticket Lot Mint PDA");
+        return Err(HaprError::InvalidTicketLotPda.into());
+    }
+    let (ticket_lot_pda, ticket_lot_bump) =
+        Pubkey::find_program_address(&[TICKET_LOT_SEED, lot_mint_pda.as_ref()], program_id);
+    if *ticket_lot_account.key != ticket_lot_pda {
+        msg!("Error: Invalid Ticket Lot PDA");
+        return Err(HaprError::InvalidTicketLotPda.into());
+    }
+    if *buyer_lot_token_account.key
+        != get_associated_token_address_with_program_id(
+            buyer_account.key,
+            &lot_mint_pda,
+            token_program.key,
+        )
+    {
+        msg!("Error: Buyer lot token account is not the associated token account.");
+        return Err(HaprError::InvalidTokenAccount.into());
+    }
+    debug_msg!("Ticket lot PDAs verified successfully.");
+
+    if lot_mint_account.data_len() != 0 || ticket_lot_account.data_len() != 0 {
+        msg!("Error: Ticket lot already exists for this nonce.");
+        return Err(HaprError::AccountAlreadyExists.into());
+    }
+
+    // Calculate the total cost for tickets
+    let number_of_tickets = amount / FIXED_TICKET_PRICE;
+    let total_cost = FIXED_TICKET_PRICE * number_of_tickets;
+
+    if number_of_tickets == 0 {
+        msg!("Error: Amount is insufficient to purchase tickets.");
+        return Err(HaprError::InsufficientTicketAmount.into());
+    }
+
+    // Pay for the tickets; the buyer covers any transfer fee so the treasury holds the full
+    // principal owed back at redemption
+    let paid = haprspl::transfer_in(
+        token_program,
+        buyer_token_account,
+        mint_account,
+        treasury_token_account,
+        buyer_account,
+        total_cost,
+    )?;
+    debug_msg!("Token transfer successful ({} paid).", paid);
+
+    let rent = Rent::get()?;
+    let lot_mint_seeds: &[&[u8]] = &[
+        TICKET_LOT_MINT_SEED,
+        buyer_account.key.as_ref(),
+        &nonce_bytes,
+        &[lot_mint_bump],
+    ];
+    let treasury_seeds: &[&[u8]] = &[TREASURY_SEED, &[treasury_bump]];
+
+    // Create and initialize the lot mint (0 decimals, Treasury PDA as mint authority)
+    invoke_signed(
+        &system_instruction::create_account(
+            buyer_account.key,
+            lot_mint_account.key,
+            rent.minimum_balance(Mint::LEN),
+            Mint::LEN as u64,
+            token_program.key,
+        ),
+        &[
+            buyer_account.clone(),
+            lot_mint_account.clone(),
+            system_program.clone(),
+        ],
+        &[lot_mint_seeds],
+    )?;
+    invoke(
+        &initialize_mint(
+            token_program.key,
+            lot_mint_account.key,
+            treasury_pda_account.key,
+            None,
+            0,
+        )?,
+        &[
+            lot_mint_account.clone(),
+            sysvar_rent.clone(),
+            token_program.clone(),
+        ],
+    )?;
+    debug_msg!("Ticket lot mint created: {}", lot_mint_account.key);
+
+    // Create the buyer's token account for the lot and mint one token per ticket
+    invoke(
+        &create_associated_token_account_idempotent(
+            buyer_account.key,
+            buyer_account.key,
+            lot_mint_account.key,
+            token_program.key,
+        ),
+        &[
+            buyer_account.clone(),
+            buyer_lot_token_account.clone(),
+            buyer_account.clone(),
+            lot_mint_account.clone(),
+            system_program.clone(),
+            token_program.clone(),
+            associated_token_program.clone(),
+        ],
+    )?;
+    haprspl::mint_to(
+        token_program,
+        lot_mint_account,
+        buyer_lot_token_account,
+        treasury_pda_account,
+        number_of_tickets,
+        &[treasury_seeds],
+    )?;
+
+    // Revoke the mint authority so the lot supply can never grow
+    invoke_signed(
+        &set_authority(
+            token_program.key,
+            lot_mint_account.key,
+            None,
+            AuthorityType::MintTokens,
+            treasury_pda_account.key,
+            &[],
+        )?,
+        &[
+            lot_mint_account.clone(),
+            treasury_pda_account.clone(),
+            token_program.clone(),
+        ],
+        &[treasury_seeds],
+    )?;
+    debug_msg!("Minted {} ticket tokens and fixed the lot supply.", number_of_tickets);
+
+    // Record the lot's vesting schedule
+    invoke_signed(
+        &system_instruction::create_account(
+            buyer_account.key,
+            ticket_lot_account.key,
+            rent.minimum_balance(TICKET_LOT_SIZE),
+            TICKET_LOT_SIZE as u64,
+            program_id,
+        ),
+        &[
+            buyer_account.clone(),
+            ticket_lot_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[TICKET_LOT_SEED, lot_mint_pda.as_ref(), &[ticket_lot_bump]]],
+    )?;
+
+    let ticket_lot = TicketLot {
+        mint: lot_mint_pda,
+        number_of_tickets,
+        deposit_time: Clock::get()?.unix_timestamp,
+        vesting_period,
+        claimed: false,
+    };
+    haprstate::store(ticket_lot_account, AccountKind::TicketLot, &ticket_lot)?;
+
+    debug_msg!("Ticket lot recorded: {:?}", ticket_lot);
+    HaprEvent::TicketsPurchased {
+        owner: *buyer_account.key,
+        number_of_tickets,
+        cost: total_cost,
+        vesting_period,
+        timestamp: ticket_lot.deposit_time,
+    }
+    .emit();
+    Ok(())
+}
+
+//Redeem tokenized tickets by burning them for principal and yield
+pub fn redeem_ticket_lot(
+    accounts: &[AccountInfo],
+    amount: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let holder_account = next_account_info(accounts_iter)?; // Current holder of the lot tokens
+    let holder_lot_token_account = next_account_info(accounts_iter)?; // Holder's lot token account
+    let lot_mint_account = next_account_info(accounts_iter)?; // Lot mint
+    let ticket_lot_account = next_account_info(accounts_iter)?; // Lot record PDA
+    let mint_account = next_account_info(accounts_iter)?; // HAPR mint
+    let holder_token_account = next_account_info(accounts_iter)?; // Holder's HAPR token account
+    let treasury_token_account = next_account_info(accounts_iter)?; // Treasury's token account
+    let treasury_pda_account = next_account_info(accounts_iter)?; // Treasury PDA
+    let token_program = next_account_info(accounts_iter)?;
+
+    haprvalidate::assert_signer(holder_account, "Ticket holder")?;
+    haprvalidate::assert_writable(ticket_lot_account, "Ticket lot account")?;
+    haprvalidate::assert_token_program(token_program)?;
+
+    // Verify PDA authority and the HAPR mint
+    let (treasury_pda, bump_seed) = Pubkey::find_program_address(&[TREASURY_SEED], program_id);
+    if *treasury_pda_account.key != treasury_pda {
+        msg!("Error: Invalid PDA authority");
+        return Err(HaprError::InvalidTreasuryPda.into());
+    }
+    let (mint_pda, _mint_bump) = Pubkey::find_program_address(&[SEED], program_id);
+    if *mint_account.key != mint_pda {
+        msg!("Error: Mint PDA does not match derived PDA.");
+        return Err(HaprError::InvalidMintPda.into());
+    }
+    haprvalidate::assert_hapr_token_account(
+        holder_token_account,
+        holder_account.key,
+        program_id,
+        "Holder token account",
+    )?;
+    haprvalidate::assert_treasury_token_account(treasury_token_account, program_id)?;
+
+    // Load the lot record
+    haprvalidate::assert_program_account(
+        ticket_lot_account,
+        &[TICKET_LOT_SEED, lot_mint_account.key.as_ref()],
+        program_id,
+        HaprError::InvalidTicketLotPda,
+        "Ticket lot account",
+    )?;
+    let mut ticket_lot: TicketLot = haprstate::load(ticket_lot_account, AccountKind::TicketLot)?;
+
+    if ticket_lot.claimed || ticket_lot.number_of_tickets == 0 {
+        msg!("Error: Ticket lot has already been fully redeemed.");
+        return Err(HaprError::TicketAlreadyClaimed.into());
+    }
+
+    let current_time = Clock::get()?.unix_timestamp;
+    if current_time < ticket_lot.deposit_time + ticket_lot.vesting_period {
+        msg!(
+            "Error: Ticket lot is still vesting. Deposit time: {}, Vesting period: {}.",
+            ticket_lot.deposit_time,
+            ticket_lot.vesting_period
+        );
+        return Err(HaprError::VestingNotComplete.into());
+    }
+
+    // Redeem everything held when no amount is given
+    let held_tickets = haprspl::unpack_token_account(holder_lot_token_account)?.amount;
+    let tickets_to_redeem = if amount == 0 { held_tickets } else { amount };
+    if tickets_to_redeem == 0 || tickets_to_redeem > held_tickets {
+        msg!(
+            "Error: Requested {} tickets but only {} are held.",
+            tickets_to_redeem,
+            held_tickets
+        );
+        return Err(HaprError::InsufficientTickets.into());
+    }
+
+    // Burn the ticket tokens (the holder signs as token account owner)
+    haprspl::burn(
+        token_program,
+        holder_lot_token_account,
+        lot_mint_account,
+        holder_account,
+        tickets_to_redeem,
+        &[],
+    )?;
+    debug_msg!("Burned {} ticket tokens.", tickets_to_redeem);
+
+    // Return the principal from the treasury and mint only the yield
+    let principal = tickets_to_redeem * FIXED_TICKET_PRICE;
+    let config = haprtreasury::load_config(treasury_pda_account, program_id)?;
+    let yield_amount = calculate_ticket_yield(
+        tickets_to_redeem,
+        ticket_lot.deposit_time,
+        current_time,
+        config.ticket_yield_bps,
+    )?;
+    haprlimits::record_outflow(
+        accounts,
+        treasury_pda_account,
+        Some(holder_account),
+        principal,
+        program_id,
+    )?;
+    pay_ticket_redemption(
+        principal,
+        yield_amount,
+        config.max_supply,
+        mint_account,
+        treasury_token_account,
+        holder_token_account,
+        treasury_pda_account,
+        token_program,
+        bump_seed,
+    )?;
+
+    ticket_lot.number_of_tickets -= tickets_to_redeem;
+    if ticket_lot.number_of_tickets == 0 {
+        ticket_lot.claimed = true;
+    }
+    haprstate::store(ticket_lot_account, AccountKind::TicketLot, &ticket_lot)?;
+
+    debug_msg!(
+        "Ticket lot redeemed. Remaining tickets in lot: {}",
+        ticket_lot.number_of_tickets
+    );
+    HaprEvent::TicketsRedeemed {
+        owner: *holder_account.key,
+        number_of_tickets: tickets_to_redeem,
+        principal,
+        yield_amount,
+        timestamp: current_time,
+    }
+    .emit();
+    Ok(())
+}
+
+// The lot record held by an account, if it is a TicketLot PDA
+pub fn ticket_lot_at(account: &AccountInfo, program_id: &Pubkey) -> Option<TicketLot> {
+    let data = account.data.borrow();
+    if data.len() != TICKET_LOT_SIZE || !haprstate::has_header(&data, AccountKind::TicketLot) {
+        return None;
+    }
+    let ticket_lot = TicketLot::try_from_slice(&data[ACCOUNT_HEADER_SIZE..]).ok()?;
+    let (ticket_lot_pda, _ticket_lot_bump) = Pubkey::find_program_address(
+        &[TICKET_LOT_SEED, ticket_lot.mint.as_ref()],
+        program_id,
+    );
+    (*account.key == ticket_lot_pda).then_some(ticket_lot)
+}
@@ -0,0 +1,623 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::rent::Rent,
+    sysvar::Sysvar,
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use spl_token_2022::extension::transfer_fee::MAX_FEE_BASIS_POINTS;
+
+use crate::haprdraw;
+use crate::haprerror::HaprError;
+use crate::haprevent::HaprEvent;
+use crate::haprgov::ConfigParam;
+use crate::haprlimits;
+use crate::haprpause;
+use crate::haprspl::{self, MetadataField, MAX_METADATA_VALUE_LEN};
+use crate::haprstate::{self, AccountKind, ACCOUNT_HEADER_SIZE};
+use crate::haprtoken;
+use crate::haprtreasury::{self, Multisig, MAX_MULTISIG_SIGNERS};
+use crate::haprvalidate;
+
+const TREASURY_SEED: &[u8] = b"treasurythissuperhyperAPRtoken";
+const ADMIN_ACTION_SEED: &[u8] = b"admin_action"; // [seed, action_id]
+
+// Sized for the largest action (SetMultisig) and a full approval list; smaller records
+// leave trailing zeros. Total: 691 bytes
+const ADMIN_ACTION_SIZE: usize = ACCOUNT_HEADER_SIZE
+    + 8
+    + (1 + 1 + 4 + 32 * MAX_MULTISIG_SIGNERS)
+    + 8
+    + 8
+    + 8
+    + (4 + 32 * MAX_MULTISIG_SIGNERS);
+// Actions not executed within this long after their eta must be cancelled and queued again
+const GRACE_PERIOD: i64 = 14 * 24 * 60 * 60;
+
+// Admin-only operations. They wait in the queue for the admin timelock delay
+// configured in the treasury before they can be executed, and need `threshold`
+// approvals when the treasury is run by a signer set.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub enum AdminAction {
+    SetConfig {
+        param: ConfigParam, // Any parameter except RetireAdmin
+        value: u64,
+    },
+    WithdrawTreasury {
+        destination: Pubkey, // HAPR token account receiving the tokens
+        amount: u64,
+    },
+    SetAdmin {
+        new_admin: Pubkey,
+    },
+    StartDraw {
+        draw_id: u64,
+        prize_amount: u64,
+        entry_duration: i64,
+    },
+    SetMultisig {
+        threshold: u8,
+        signers: Vec<Pubkey>, // Replaces the admin key (SetAdmin switches back)
+    },
+    SetPauseGuardian {
+        guardian: Pubkey, // Default pubkey removes the guardian
+    },
+    SetOutflowLimits {
+        window_length: i64,      // Seconds per treasury outflow window
+        window_limit: u64,       // 0 removes the per-window limit
+        wallet_daily_limit: u64, // 0 removes the per-wallet limit
+    },
+    MintTokens {
+        destination: Pubkey, // HAPR token account receiving the new tokens
+        amount: u64,
+    },
+    SetTransferFee {
+        transfer_fee_basis_points: u16, // Token-2022 HAPR mints only
+        maximum_fee: u64,
+    },
+    WithdrawTransferFees, // Fees withheld in the HAPR mint go to the treasury token account
+    SetTokenMetadata {
+        field: MetadataField,
+        value: String, // At most MAX_METADATA_VALUE_LEN bytes
+    },
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct QueuedAction {
+    pub action_id: u64,
+    pub action: AdminAction,
+    pub queued_time: i64,
+    pub eta: i64,                // Earliest execution time
+    pub signer_set_version: u64, // Treasury signer set the approvals were given under
+    pub approvals: Vec<Pubkey>,  // Distinct members of that signer set that approved
+}
+
+// Queue an admin action. With the timelock disabled it can be executed right away.
+// With a signer set, the admin and any co-signers passed after the system program approve it.
+pub fn queue_action(
+    accounts: &[AccountInfo],
+    action_id: u64,
+    action: AdminAction,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let admin_account = next_account_info(accounts_iter)?; // Treasury admin (pays rent)
+    let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA
+    let action_account = next_account_info(accounts_iter)?; // Admin action PDA
+    let system_program = next_account_info(accounts_iter)?;
+    let co_signers = accounts_iter.as_slice(); // Other treasury signers, with a signer set
+
+    let multisig = haprtreasury::assert_admin_member(treasury_account, admin_account, program_id)?;
+
+    let is_valid = match &action {
+        AdminAction::SetConfig { param, value } => {
+            *param != ConfigParam::RetireAdmin && param.is_valid_value(*value)
+        }
+        AdminAction::WithdrawTreasury { amount, .. } | AdminAction::MintTokens { amount, .. } => {
+            *amount > 0
+        }
+        AdminAction::SetAdmin { .. } => true,
+        AdminAction::StartDraw {
+            prize_amount,
+            entry_duration,
+            ..
+        } => *prize_amount > 0 && *entry_duration > 0,
+        AdminAction::SetMultisig { threshold, signers } => {
+            let multisig = Multisig {
+                threshold: *threshold,
+                signers: signers.clone(),
+            };
+            if !multisig.is_valid() {
+                msg!("Error: Invalid signer set {:?}", multisig);
+                return Err(HaprError::InvalidMultisig.into());
+            }
+            true
+        }
+        AdminAction::SetPauseGuardian { .. } => true,
+        AdminAction::SetOutflowLimits {
+            window_length,
+            window_limit,
+            ..
+        } => haprlimits::is_valid_limits(*window_length, *window_limit),
+        AdminAction::SetTransferFee {
+            transfer_fee_basis_points,
+            ..
+        } => *transfer_fee_basis_points <= MAX_FEE_BASIS_POINTS,
+        AdminAction::WithdrawTransferFees => true,
+        AdminAction::SetTokenMetadata { value, .. } => value.len() <= MAX_METADATA_VALUE_LEN,
+    };
+    if !is_valid {
+        msg!("Error: Invalid admin action {:?}", action);
+        return Err(HaprError::InvalidAdminAction.into());
+    }
+
+    let action_id_bytes = action_id.to_le_bytes();
+    let action_bump = haprvalidate::assert_pda(
+        action_account,
+        &[ADMIN_ACTION_SEED, &action_id_bytes],
+        program_id,
+        HaprError::InvalidActionPda,
+        "Admin action account",
+    )?;
+    haprvalidate::assert_writable(action_account, "Admin action account")?;
+    if action_account.data_len() != 0 {
+        msg!("Error: Admin action {} is already queued.", action_id);
+        return Err(HaprError::AccountAlreadyExists.into());
+    }
+
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            admin_account.key,
+            action_account.key,
+            rent.minimum_balance(ADMIN_ACTION_SIZE),
+            ADMIN_ACTION_SIZE as u64,
+            program_id,
+        ),
+        &[
+            admin_account.clone(),
+            action_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[ADMIN_ACTION_SEED, &action_id_bytes, &[action_bump]]],
+    )?;
+
+    let config = haprtreasury::load_config(treasury_account, program_id)?;
+    let current_time = Clock::get()?.unix_timestamp;
+    let queued_action = QueuedAction {
+        action_id,
+        action,
+        queued_time: current_time,
+        eta: current_time + config.admin_timelock_delay,
+        signer_set_version: haprtreasury::load_signer_set_version(treasury_account)?,
+        approvals: multisig
+            .map(|multisig| multisig.approvals(admin_account, co_signers))
+            .unwrap_or_default(),
+    };
+    haprstate::store(action_account, AccountKind::AdminAction, &queued_action)?;
+
+    debug_msg!("Admin action queued: {:?}", queued_action);
+    HaprEvent::ActionQueued {
+        action_id,
+        action: queued_action.action,
+        eta: queued_action.eta,
+    }
+    .emit();
+    Ok(())
+}
+
+// Execute a queued admin action once its eta has passed. The accounts after the system
+// program depend on the action:
+//   SetConfig: for StakingAprBps, the HAPR mint and, when the mint uses it, Token-2022
+//   StartDraw: draw PDA
+//   WithdrawTreasury: treasury token account, destination token account, token program,
+//     HAPR mint
+//   MintTokens: destination token account, token program, HAPR mint
+//   SetTransferFee, SetTokenMetadata: token program, HAPR mint
+//   WithdrawTransferFees: treasury token account, token program, HAPR mint
+pub fn execute_action(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let admin_account = next_account_info(accounts_iter)?; // Treasury admin
+    let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA
+    let action_account = next_account_info(accounts_iter)?; // Admin action PDA
+    let system_program = next_account_info(accounts_iter)?;
+
+    let multisig = haprtreasury::assert_admin_member(treasury_account, admin_account, program_id)?;
+    haprvalidate::assert_writable(action_account, "Admin action account")?;
+    let queued_action = load_action(action_account, program_id)?;
+
+    // Approvals given under an earlier signer set no longer count; the executor approves
+    // by signing
+    if let Some(multisig) = multisig {
+        let signer_set_version = haprtreasury::load_signer_set_version(treasury_account)?;
+        let mut approvals: Vec<&Pubkey> = if queued_action.signer_set_version == signer_set_version
+        {
+            queued_action.approvals.iter().collect()
+        } else {
+            Vec::new()
+        };
+        if !approvals.contains(&admin_account.key) {
+            approvals.push(admin_account.key);
+        }
+        if approvals.len() < multisig.threshold as usize {
+            msg!(
+                "Error: Admin action {} has {} of {} required approvals.",
+                queued_action.action_id,
+                approvals.len(),
+                multisig.threshold
+            );
+            return Err(HaprError::ThresholdNotReached.into());
+        }
+    }
+
+    let current_time = Clock::get()?.unix_timestamp;
+    if current_time < queued_action.eta {
+        msg!(
+            "Error: Admin action {} can be executed after {}.",
+            queued_action.action_id,
+            queued_action.eta
+        );
+        return Err(HaprError::ActionNotReady.into());
+    }
+    if current_time > queued_action.eta + GRACE_PERIOD {
+        msg!(
+            "Error: Admin action {} expired at {}.",
+            queued_action.action_id,
+            queued_action.eta + GRACE_PERIOD
+        );
+        return Err(HaprError::ActionExpired.into());
+    }
+
+    match &queued_action.action {
+        AdminAction::SetConfig { param, value } => {
+            let mut config = haprtreasury::load_config(treasury_account, program_id)?;
+            param.apply(&mut config, *value);
+            haprtreasury::store_config(treasury_account, admin_account, system_program, &config)?;
+            if *param == ConfigParam::StakingAprBps {
+                haprspl::sync_interest_rate(
+                    accounts_iter.as_slice(),
+                    treasury_account,
+                    config.staking_apr_bps,
+                    program_id,
+                )?;
+            }
+            debug_msg!("Config updated: {:?}", config);
+        }
+        AdminAction::WithdrawTreasury {
+            destination,
+            amount,
+        } => {
+            let treasury_token_account = next_account_info(accounts_iter)?; // Treasury's token account
+            let destination_account = next_account_info(accounts_iter)?; // Destination token account
+            let token_program = next_account_info(accounts_iter)?;
+            let mint_account = haprspl::find_hapr_mint(accounts_iter.as_slice(), program_id)?;
+
+            haprpause::assert_not_paused(
+                accounts,
+                haprpause::PAUSE_TREASURY_WITHDRAWAL,
+                program_id,
+            )?;
+            let (_treasury_pda, treasury_bump) =
+                Pubkey::find_program_address(&[TREASURY_SEED], program_id);
+            haprvalidate::assert_token_program(token_program)?;
+            haprvalidate::assert_treasury_token_account(treasury_token_account, program_id)?;
+            if *destination_account.key != *destination {
+                msg!("Error: Destination must be the queued account {}.", destination);
+                return Err(HaprError::InvalidTokenAccount.into());
+            }
+            haprlimits::record_outflow(accounts, treasury_account, None, *amount, program_id)?;
+
+            haprspl::transfer(
+                token_program,
+                treasury_token_account,
+                mint_account,
+                destination_account,
+                treasury_account,
+                *amount,
+                &[&[TREASURY_SEED, &[treasury_bump]]],
+            )?;
+            debug_msg!("Withdrew {} tokens from the treasury.", amount);
+        }
+        AdminAction::MintTokens {
+            destination,
+            amount,
+        } => {
+            let destination_account = next_account_info(accounts_iter)?; // Destination token account
+            let token_program = next_account_info(accounts_iter)?;
+            let mint_account = haprspl::find_hapr_mint(accounts_iter.as_slice(), program_id)?;
+
+            haprvalidate::assert_token_program(token_program)?;
+            if *destination_account.key != *destination {
+                msg!(
+                    "Error: Destination must be the queued account {}.",
+                    destination
+                );
+                return Err(HaprError::InvalidTokenAccount.into());
+            }
+            haprvalidate::assert_token_account(
+                destination_account,
+                mint_account.key,
+                None,
+                "Destination token account",
+            )?;
+            haprtoken::mint_within_cap(
+                token_program,
+                mint_account,
+                destination_account,
+                treasury_account,
+                *amount,
+                program_id,
+            )?;
+            debug_msg!("Minted {} tokens.", amount);
+        }
+        AdminAction::SetTransferFee {
+            transfer_fee_basis_points,
+            maximum_fee,
+        } => {
+            let token_program = next_account_info(accounts_iter)?;
+            let mint_account = haprspl::find_hapr_mint(accounts_iter.as_slice(), program_id)?;
+            haprspl::update_transfer_fee(
+                token_program,
+                mint_account,
+                treasury_account,
+                *transfer_fee_basis_points,
+                *maximum_fee,
+                program_id,
+            )?;
+        }
+        AdminAction::WithdrawTransferFees => {
+            let treasury_token_account = next_account_info(accounts_iter)?; // Treasury's token account
+            let token_program = next_account_info(accounts_iter)?;
+            let mint_account = haprspl::find_hapr_mint(accounts_iter.as_slice(), program_id)?;
+            haprvalidate::assert_treasury_token_account(treasury_token_account, program_id)?;
+            let balance = haprspl::unpack_token_account(treasury_token_account)?.amount;
+            haprspl::withdraw_withheld_fees(
+                token_program,
+                mint_account,
+                treasury_token_account,
+                treasury_account,
+                program_id,
+            )?;
+            let withdrawn = haprspl::unpack_token_account(treasury_token_account)?.amount - balance;
+            haprtreasury::credit_accrued_yield(
+                treasury_account,
+                admin_account,
+                system_program,
+                withdrawn,
+            )?;
+            debug_msg!(
+                "{} tokens of withheld transfer fees moved to the treasury.",
+                withdrawn
+            );
+        }
+        AdminAction::SetTokenMetadata { field, value } => {
+            let token_program = next_account_info(accounts_iter)?;
+            let mint_account = haprspl::find_hapr_mint(accounts_iter.as_slice(), program_id)?;
+            haprspl::update_token_metadata(
+                token_program,
+                mint_account,
+                treasury_account,
+                admin_account,
+                system_program,
+                *field,
+                value.clone(),
+                program_id,
+            )?;
+        }
+        AdminAction::SetAdmin { new_admin } => {
+            haprtreasury::set_admin(treasury_account, new_admin)?;
+        }
+        AdminAction::SetMultisig { threshold, signers } => {
+            haprtreasury::set_admin(treasury_account, &Pubkey::default())?;
+            haprtreasury::store_multisig(
+                treasury_account,
+                admin_account,
+                system_program,
+                &Multisig {
+                    threshold: *threshold,
+                    signers: signers.clone(),
+                },
+            )?;
+        }
+        AdminAction::SetPauseGuardian { guardian } => {
+            let mut pause_state = haprtreasury::load_pause_state(treasury_account)?;
+            let old_guardian = pause_state.guardian;
+            pause_state.guardian = *guardian;
+            haprtreasury::store_pause_state(
+                treasury_account,
+                admin_account,
+                system_program,
+                &pause_state,
+            )?;
+            HaprEvent::PauseGuardianChanged {
+                old_guardian,
+                new_guardian: *guardian,
+            }
+            .emit();
+        }
+        AdminAction::SetOutflowLimits {
+            window_length,
+            window_limit,
+            wallet_daily_limit,
+        } => {
+            haprlimits::set_outflow_limits(
+                treasury_account,
+                admin_account,
+                system_program,
+                *window_length,
+                *window_limit,
+                *wallet_daily_limit,
+            )?;
+        }
+        AdminAction::StartDraw {
+            draw_id,
+            prize_amount,
+            entry_duration,
+        } => {
+            let draw_state_account = next_account_info(accounts_iter)?; // Draw PDA
+            haprdraw::open_draw(
+                admin_account,
+                treasury_account,
+                draw_state_account,
+                system_program,
+                *prize_amount,
+                *draw_id,
+                *entry_duration,
+                program_id,
+            )?;
+        }
+    }
+
+    close_action(action_account, admin_account)?;
+    HaprEvent::ActionExecuted {
+        action_id: queued_action.action_id,
+    }
+    .emit();
+    Ok(())
+}
+
+// Approve a queued admin action as a member of the treasury signer set, for signers
+// that cannot co-sign the queue or execute transaction. Approvals given under an earlier
+// signer set are cleared first.
+pub fn approve_action(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let signer_account = next_account_info(accounts_iter)?; // Treasury signer
+    let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA
+    let action_account = next_account_info(accounts_iter)?; // Admin action PDA
+
+    let multisig =
+        match haprtreasury::assert_admin_member(treasury_account, signer_account, program_id)? {
+            Some(multisig) => multisig,
+            None => {
+                msg!("Error: Treasury has no signer set; the admin approves by executing.");
+                return Err(HaprError::InvalidAdminAction.into());
+            }
+        };
+    haprvalidate::assert_writable(action_account, "Admin action account")?;
+    let mut queued_action = load_action(action_account, program_id)?;
+
+    let signer_set_version = haprtreasury::load_signer_set_version(treasury_account)?;
+    if queued_action.signer_set_version != signer_set_version {
+        queued_action.signer_set_version = signer_set_version;
+        queued_action.approvals.clear();
+    }
+    if queued_action.approvals.contains(signer_account.key) {
+        msg!(
+            "Error: Admin action {} is already approved by {}.",
+            queued_action.action_id,
+            signer_account.key
+        );
+        return Err(HaprError::AlreadyApproved.into());
+    }
+    if queued_action.approvals.len() >= multisig.signers.len() {
+        msg!(
+            "Error: Admin action {} already has {} approvals.",
+            queued_action.action_id,
+            queued_action.approvals.len()
+        );
+        return Err(HaprError::InvalidAdminAction.into());
+    }
+
+    queued_action.approvals.push(*signer_account.key);
+    haprstate::store(action_account, AccountKind::AdminAction, &queued_action)?;
+
+    debug_msg!("Admin action approved: {:?}", queued_action);
+    HaprEvent::ActionApproved {
+        action_id: queued_action.action_id,
+        signer: *signer_account.key,
+    }
+    .emit();
+    Ok(())
+}
+
+// Drop a queued admin action without executing it. With a signer set, co-signers
+// passed after the action account must bring the signatures up to the threshold.
+pub fn cancel_action(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let admin_account = next_account_info(accounts_iter)?; // Treasury admin (receives rent)
+    let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA
+    let action_account = next_account_info(accounts_iter)?; // Admin action PDA
+    let co_signers = accounts_iter.as_slice(); // Other treasury signers, with a signer set
+
+    haprtreasury::assert_admin(treasury_account, admin_account, co_signers, program_id)?;
+    haprvalidate::assert_writable(action_account, "Admin action account")?;
+    let queued_action = load_action(action_account, program_id)?;
+
+    close_action(action_account, admin_account)?;
+    debug_msg!("Admin action cancelled: {:?}", queued_action);
+    HaprEvent::ActionCanceled {
+        action_id: queued_action.action_id,
+    }
+    .emit();
+    Ok(())
+}
+
+fn load_action(
+    action_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<QueuedAction, HaprError> {
+    if *action_account.owner != *program_id {
+        msg!("Error: Admin action account is not owned by the program.");
+        return Err(HaprError::InvalidAccountOwner);
+    }
+    let action_data = action_account.data.borrow();
+    if action_data.len() != ADMIN_ACTION_SIZE
+        || !haprstate::has_header(&action_data, AccountKind::AdminAction)
+    {
+        msg!("Error: Admin action account is not a queued action.");
+        return Err(HaprError::InvalidAccountDiscriminator);
+    }
+    // Not `try_from_slice`: smaller actions leave unused bytes at the end of the account
+    let queued_action = QueuedAction::deserialize(&mut &action_data[ACCOUNT_HEADER_SIZE..])
+        .map_err(|_| HaprError::InvalidActionPda)?;
+
+    if *action_account.key != action_address(queued_action.action_id, program_id) {
+        msg!("Error: Invalid Admin Action PDA");
+        return Err(HaprError::InvalidActionPda);
+    }
+    Ok(queued_action)
+}
+
+fn action_address(action_id: u64, program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[ADMIN_ACTION_SEED, &action_id.to_le_bytes()], program_id).0
+}
+
+fn close_action(action_account: &AccountInfo, admin_account: &AccountInfo) -> ProgramResult {
+    haprstate::close_account(action_account, admin_account)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn largest_actions_fit_the_action_account() {
+        for action in [
+            AdminAction::SetMultisig {
+                threshold: 1,
+                signers: vec![Pubkey::new_unique(); MAX_MULTISIG_SIGNERS],
+            },
+            AdminAction::SetTokenMetadata {
+                field: MetadataField::Uri,
+                value: "x".repeat(MAX_METADATA_VALUE_LEN),
+            },
+        ] {
+            let queued_action = QueuedAction {
+                action_id: 0,
+                action,
+                queued_time: 0,
+                eta: 0,
+                signer_set_version: 0,
+                approvals: vec![Pubkey::new_unique(); MAX_MULTISIG_SIGNERS],
+            };
+            let size = ACCOUNT_HEADER_SIZE + borsh::to_vec(&queued_action).unwrap().len();
+            assert!(size <= ADMIN_ACTION_SIZE, "{:?}", queued_action.action);
+        }
+    }
+}
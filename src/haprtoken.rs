@@ -15,12 +15,18 @@ use solana_program::{
 };
 
 use borsh::{BorshDeserialize, BorshSerialize};
-use spl_token::instruction::{burn, initialize_account, mint_to, transfer};
-
-use solana_program::program_pack::Pack; // Add this import for unpack_from_slice
-                                        //use spl_token::state::Account;
-use spl_token::state::Mint;
-use std::io::Cursor;
+use bytemuck::{Pod, Zeroable};
+use spl_pod::primitives::{PodBool, PodI64, PodU64};
+use spl_token_2022::instruction::initialize_account;
+
+use crate::haprerror::HaprError;
+use crate::haprevent::HaprEvent;
+use crate::haprlimits;
+use crate::haprlock;
+use crate::haprspl;
+use crate::haprstate::{self, AccountKind, ACCOUNT_HEADER_SIZE};
+use crate::haprtreasury;
+use crate::haprvalidate;
 
 const SEED: &[u8] = b"mintthissuperhyperAPRtoken"; // Seed for PDA
                                                    //const TICKET_SEED: &[u8] = b"ticketthissuperhyperAPRtoken"; //The treasury seed
@@ -28,9 +34,8 @@ const TREASURY_SEED: &[u8] = b"treasurythissuperhyperAPRtoken";
 const TICKET_SEED: &[u8] = b"ticket_seed";
 const MINT_AUTHORITY_SEED: &[u8] = b"mint-authority-seed"; // Unique seed for mint authority
 
-const YIELD_INTEREST_RATE: u64 = 5; // 5% interest rate
-const MAX_SUPPLY: u64 = 1_000_000_000; // 1 billion max supply
-const FIXED_TICKET_PRICE: u64 = 1_000_000;
+// Not governed: outstanding tickets are redeemed at the price they were bought for
+pub const FIXED_TICKET_PRICE: u64 = 1_000_000;
 
 // Define a seed and bump for the PDA (could be any seed)
 //const TREASURY_AUTHORITY_SEED: &[u8] = SEED; //b"treasury_authority";
@@ -56,33 +61,142 @@ const FIXED_TICKET_PRICE: u64 = 1_000_000;
 //     haprtreasury::withdraw_tokens_internal(accounts, amount, &treasury_pda, bump)
 // }
 
-// Helper function to calculate staking rewards
-fn calculate_staking_rewards(amount_staked: u64, last_staked_time: i64, current_time: i64) -> u64 {
+// Helper function to calculate staking rewards at the configured APR
+pub fn calculate_staking_rewards(
+    amount_staked: u64,
+    last_staked_time: i64,
+    current_time: i64,
+    staking_apr_bps: u64,
+) -> u64 {
     let staking_duration_seconds = current_time - last_staked_time;
-    let annual_reward_rate = 0.05;
+    let annual_reward_rate = staking_apr_bps as f64 / 10_000.0;
     let seconds_in_a_year = 365 * 24 * 60 * 60;
     ((amount_staked as f64)
         * (annual_reward_rate * (staking_duration_seconds as f64) / (seconds_in_a_year as f64)))
         .round() as u64
 }
 
+// Helper function to calculate the yield earned by a ticket lot since its deposit. The
+// product outgrows u64 for large lots held a year or more, so it is taken in u128.
+pub fn calculate_ticket_yield(
+    number_of_tickets: u64,
+    deposit_time: i64,
+    current_time: i64,
+    ticket_yield_bps: u64,
+) -> Result<u64, ProgramError> {
+    let seconds_held = current_time.saturating_sub(deposit_time).max(0) as u128;
+    (number_of_tickets as u128)
+        .checked_mul(FIXED_TICKET_PRICE as u128)
+        .and_then(|value| value.checked_mul(ticket_yield_bps as u128))
+        .and_then(|value| value.checked_mul(seconds_held))
+        .map(|value| value / (365 * 24 * 60 * 60) / 10_000)
+        .and_then(|value| value.try_into().ok())
+        .ok_or(ProgramError::ArithmeticOverflow)
+}
+
+// Helper function to reject mints that would push the supply past the configured cap
+pub fn check_max_supply(mint_account: &AccountInfo, amount: u64, max_supply: u64) -> ProgramResult {
+    let current_supply = haprspl::unpack_mint(mint_account)?.supply;
+
+    if current_supply
+        .checked_add(amount)
+        .filter(|supply| *supply <= max_supply)
+        .is_none()
+    {
+        msg!("Error: Minting would exceed max supply.");
+        return Err(HaprError::MaxSupplyExceeded.into());
+    }
+    debug_msg!("Current supply: {}, Minting: {}", current_supply, amount);
+    Ok(())
+}
+
+// Pay a ticket redemption: principal is returned from the treasury token account and
+// only the yield is newly minted, within the configured supply cap
+#[allow(clippy::too_many_arguments)]
+pub fn pay_ticket_redemption<'a>(
+    principal: u64,
+    yield_amount: u64,
+    max_supply: u64,
+    mint_account: &AccountInfo<'a>,
+    treasury_token_account: &AccountInfo<'a>,
+    recipient_token_account: &AccountInfo<'a>,
+    treasury_pda_account: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    bump_seed: u8,
+) -> ProgramResult {
+    let seeds = &[TREASURY_SEED, &[bump_seed]];
+
+    let treasury_balance = haprspl::unpack_token_account(treasury_token_account)?.amount;
+    if treasury_balance < principal {
+        msg!(
+            "Error: Treasury holds {} tokens but {} are owed as principal.",
+            treasury_balance,
+            principal
+        );
+        return Err(HaprError::InsufficientFunds.into());
+    }
+
+    haprspl::transfer(
+        token_program,
+        treasury_token_account,
+        mint_account,
+        recipient_token_account,
+        treasury_pda_account,
+        principal,
+        &[&seeds[..]],
+    )?;
+    debug_msg!("Principal of {} tokens returned from the treasury.", principal);
+
+    if yield_amount > 0 {
+        check_max_supply(mint_account, yield_amount, max_supply)?;
+
+        haprspl::mint_to(
+            token_program,
+            mint_account,
+            recipient_token_account,
+            treasury_pda_account,
+            yield_amount,
+            &[&seeds[..]],
+        )?;
+        debug_msg!("Yield of {} tokens minted.", yield_amount);
+        HaprEvent::Minted {
+            destination: *recipient_token_account.key,
+            amount: yield_amount,
+        }
+        .emit();
+    }
+
+    Ok(())
+}
+
+// Mint new HAPR (admin only). With the admin timelock enabled mints are queued as admin
+// actions instead.
 pub fn mint_tokens(accounts: &[AccountInfo], amount: u64, program_id: &Pubkey) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
 
     let payer = next_account_info(accounts_iter)?;
-    msg!("Payer: {:?}", payer.key);
+    debug_msg!("Payer: {:?}", payer.key);
 
     let mint_account = next_account_info(accounts_iter)?;
-    msg!("Mint Account: {:?}", mint_account.key);
+    debug_msg!("Mint Account: {:?}", mint_account.key);
 
     let to_account = next_account_info(accounts_iter)?;
-    msg!("Recipient Token Account: {:?}", to_account.key);
+    debug_msg!("Recipient Token Account: {:?}", to_account.key);
 
     let mint_authority = next_account_info(accounts_iter)?;
-    msg!("Mint Authority (Treasury PDA): {:?}", mint_authority.key);
+    debug_msg!("Mint Authority (Treasury PDA): {:?}", mint_authority.key);
 
     let system_program = next_account_info(accounts_iter)?;
     let token_program = next_account_info(accounts_iter)?;
+    let co_signers = accounts_iter.as_slice(); // Other treasury signers, with a signer set
+
+    haprtreasury::assert_admin(mint_authority, payer, co_signers, program_id)?;
+    if haprtreasury::load_config(mint_authority, program_id)?.admin_timelock_delay > 0 {
+        msg!("Error: Admin timelock is enabled; queue the mint with QueueAction.");
+        return Err(HaprError::TimelockRequired.into());
+    }
+    haprvalidate::assert_writable(mint_account, "Mint account")?;
+    haprvalidate::assert_token_program(token_program)?;
 
     // let token_account_data = to_account.try_borrow_data()?;
     // let token_account_info = Account::unpack(&token_account_data)?;
@@ -94,29 +208,26 @@ pub fn mint_tokens(accounts: &[AccountInfo], amount: u64, program_id: &Pubkey) -
 
     if *mint_authority.key != treasury_pda {
         msg!("Error: Mint authority PDA does not match derived PDA.");
-        return Err(ProgramError::InvalidArgument);
+        return Err(HaprError::InvalidTreasuryPda.into());
     }
-    msg!("Mint authority (PDA) verified successfully.");
+    debug_msg!("Mint authority (PDA) verified successfully.");
 
     // Step 1: Check Mint Account Initialization
-    {
-        let mint_data = mint_account.try_borrow_data()?; // Immutable borrow
-        if mint_account.owner != &spl_token::id() {
-            msg!("Error: Mint Account is not owned by the SPL Token Program.");
-            return Err(ProgramError::InvalidAccountData);
-        }
-        if Mint::unpack_from_slice(&mint_data).is_err() {
-            msg!("Error: Mint Account data is invalid or uninitialized.");
-            return Err(ProgramError::InvalidAccountData);
-        }
-        msg!("Mint Account is initialized and valid.");
-    } // Release mint_account borrow here
+    if mint_account.owner != token_program.key {
+        msg!("Error: Mint Account is not owned by the token program.");
+        return Err(HaprError::IncorrectTokenProgram.into());
+    }
+    if haprspl::unpack_mint(mint_account).is_err() {
+        msg!("Error: Mint Account data is invalid or uninitialized.");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    debug_msg!("Mint Account is initialized and valid.");
 
     // Step 2: Initialize Recipient Token Account
-    if to_account.owner != &spl_token::id() || to_account.data_len() == 0 {
-        msg!("Recipient Token Account not initialized. Initializing...");
+    if !haprspl::is_token_program(to_account.owner) || to_account.data_len() == 0 {
+        debug_msg!("Recipient Token Account not initialized. Initializing...");
         let rent = Rent::get()?;
-        let account_size = 165; // SPL Token Account size
+        let account_size = haprspl::token_account_len(mint_account)?; // Base account plus extensions
 
         invoke_signed(
             &system_instruction::create_account(
@@ -124,16 +235,16 @@ pub fn mint_tokens(accounts: &[AccountInfo], amount: u64, program_id: &Pubkey) -
                 to_account.key,
                 rent.minimum_balance(account_size),
                 account_size as u64,
-                &spl_token::id(),
+                token_program.key,
             ),
             &[payer.clone(), to_account.clone(), system_program.clone()],
             &[&[TREASURY_SEED, &[bump_seed]]],
         )?;
-        msg!("Recipient token account created.");
+        debug_msg!("Recipient token account created.");
 
         invoke_signed(
             &initialize_account(
-                &spl_token::id(),
+                token_program.key,
                 to_account.key,
                 mint_account.key,
                 payer.key,
@@ -146,52 +257,59 @@ pub fn mint_tokens(accounts: &[AccountInfo], amount: u64, program_id: &Pubkey) -
             ],
             &[&[TREASURY_SEED, &[bump_seed]]],
         )?;
-        msg!("Recipient token account initialized.");
+        debug_msg!("Recipient token account initialized.");
     } else {
-        msg!("Recipient Token Account is already initialized.");
+        debug_msg!("Recipient Token Account is already initialized.");
     }
 
-    // Step 3: Check Max Supply Constraint
-    {
-        let mint_data = mint_account.try_borrow_data()?; // Immutable borrow
-        let current_supply = u64::from_le_bytes(mint_data[36..44].try_into().unwrap());
-
-        if current_supply + amount > MAX_SUPPLY {
-            msg!("Error: Minting would exceed max supply.");
-            return Err(ProgramError::Custom(1));
-        }
-        msg!("Current supply: {}, Minting: {}", current_supply, amount);
-    } // Release mint_account borrow here
-
-    // Step 4: Mint Tokens
-    let mint_instruction = mint_to(
-        &spl_token::id(),
-        mint_account.key,
-        to_account.key,
-        mint_authority.key,
-        &[],
+    // Step 3: Mint Tokens within the max supply
+    mint_within_cap(
+        token_program,
+        mint_account,
+        to_account,
+        mint_authority,
         amount,
+        program_id,
     )?;
 
-    invoke_signed(
-        &mint_instruction,
-        &[
-            mint_account.clone(),
-            to_account.clone(),
-            mint_authority.clone(),
-            token_program.clone(),
-        ],
+    debug_msg!("Mint operation completed successfully.");
+    Ok(())
+}
+
+// Mint `amount` HAPR to `destination` within the configured supply cap, signed by the
+// treasury PDA as mint authority
+pub fn mint_within_cap<'a>(
+    token_program: &AccountInfo<'a>,
+    mint_account: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    treasury_account: &AccountInfo<'a>,
+    amount: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let (_treasury_pda, bump_seed) = Pubkey::find_program_address(&[TREASURY_SEED], program_id);
+    let config = haprtreasury::load_config(treasury_account, program_id)?;
+    check_max_supply(mint_account, amount, config.max_supply)?;
+
+    haprspl::mint_to(
+        token_program,
+        mint_account,
+        destination,
+        treasury_account,
+        amount,
         &[&[TREASURY_SEED, &[bump_seed]]],
     )?;
-
-    msg!("Mint operation completed successfully.");
+    HaprEvent::Minted {
+        destination: *destination.key,
+        amount,
+    }
+    .emit();
     Ok(())
 }
 
 pub fn test_derived_pda(program_id: &Pubkey) -> ProgramResult {
     let (pda, bump_seed) = Pubkey::find_program_address(&[SEED], program_id);
-    msg!("Expected PDA on-chain: {:?}", pda);
-    msg!("Bump Seed on-chain: {:?}", bump_seed);
+    debug_msg!("Expected PDA on-chain: {:?}", pda);
+    debug_msg!("Bump Seed on-chain: {:?}", bump_seed);
     Ok(())
 }
 
@@ -206,54 +324,59 @@ pub fn burn_tokens(accounts: &[AccountInfo], amount: u64, program_id: &Pubkey) -
     let token_program = next_account_info(accounts_iter)?;
     let sysvar_rent = next_account_info(accounts_iter)?;
 
-    msg!("Payer: {:?}", payer.key);
-    msg!("Burn Account: {:?}", burn_account.key);
-    msg!("Mint Account: {:?}", mint_account.key);
-    msg!("Burn Authority (PDA): {:?}", burn_authority.key);
-
-    // Verify the mint account is owned by the SPL Token Program
-    if mint_account.owner != &spl_token::id() {
-        msg!("Error: Mint account is not owned by the SPL Token Program.");
-        return Err(ProgramError::IncorrectProgramId);
+    debug_msg!("Payer: {:?}", payer.key);
+    debug_msg!("Burn Account: {:?}", burn_account.key);
+    debug_msg!("Mint Account: {:?}", mint_account.key);
+    debug_msg!("Burn Authority (PDA): {:?}", burn_authority.key);
+
+    haprvalidate::assert_signer(payer, "Burn account owner")?;
+    haprvalidate::assert_token_program(token_program)?;
+
+    // Only burns of HAPR are burns of this program's supply
+    haprvalidate::assert_pda(
+        mint_account,
+        &[SEED],
+        program_id,
+        HaprError::InvalidMintPda,
+        "Mint account",
+    )?;
+    if mint_account.owner != token_program.key {
+        msg!("Error: Mint account is not owned by the token program.");
+        return Err(HaprError::IncorrectTokenProgram.into());
     }
-    msg!("Mint account ownership verified.");
+    debug_msg!("Mint account ownership verified.");
 
-    // Verify the burn account is owned by the SPL Token Program
-    if burn_account.owner != &spl_token::id() {
-        msg!("Error: Burn account is not owned by the SPL Token Program.");
-        return Err(ProgramError::IncorrectProgramId);
+    // Verify the burn account is owned by the same token program
+    if burn_account.owner != token_program.key {
+        msg!("Error: Burn account is not owned by the token program.");
+        return Err(HaprError::IncorrectTokenProgram.into());
     }
-    msg!("Burn account ownership verified.");
+    debug_msg!("Burn account ownership verified.");
 
     // Verify the PDA for burn authority
     let (expected_burn_pda, _bump_seed) =
         Pubkey::find_program_address(&[TREASURY_SEED], program_id);
     if *burn_authority.key != expected_burn_pda {
         msg!("Error: Invalid burn authority PDA");
-        return Err(ProgramError::IncorrectProgramId);
-    }
-    msg!("Burn authority PDA verified successfully.");
-
-    // Execute the burn instruction
-    let burn_instruction = burn(
-        &spl_token::id(),
-        &burn_account.key, // User's associated token account
-        &mint_account.key, // Mint account
-        &payer.key,        // User wallet (burn_account owner) must sign
-        &[],               // Additional signers
-        amount,            // Amount to burn
-    )?;
-
-    invoke(
-        &burn_instruction,
-        &[
-            burn_account.clone(),
-            mint_account.clone(),
-            payer.clone(),         // Payer signs as the burn account owner
-            token_program.clone(), // SPL Token Program
-        ],
+        return Err(HaprError::InvalidTreasuryPda.into());
+    }
+    debug_msg!("Burn authority PDA verified successfully.");
+
+    // Execute the burn instruction; the payer signs as the burn account owner
+    haprspl::burn(
+        token_program,
+        burn_account,
+        mint_account,
+        payer,
+        amount,
+        &[],
     )?;
-    msg!("Burn operation completed successfully.");
+    debug_msg!("Burn operation completed successfully.");
+    HaprEvent::Burned {
+        source: *burn_account.key,
+        amount,
+    }
+    .emit();
 
     Ok(())
 }
@@ -273,44 +396,95 @@ pub fn transfer_tokens(
     let system_program = next_account_info(accounts_iter)?;
     let token_program = next_account_info(accounts_iter)?;
 
-    // Transfer tokens using SPL Token Program's transfer instruction
-    let transfer_instruction = spl_token::instruction::transfer(
-        &spl_token::id(),
-        sender_account.key,
-        recipient_account.key,
-        sender_owner.key,
-        &[],
-        amount,
-    )?;
+    haprvalidate::assert_signer(sender_owner, "Sender")?;
+    haprvalidate::assert_token_program(token_program)?;
 
-    msg!(
+    debug_msg!(
         "Transferring {} tokens from {:?} to {:?}",
         amount,
         sender_account.key,
         recipient_account.key
     );
 
-    invoke(
-        &transfer_instruction,
-        &[
-            sender_account.clone(),
-            recipient_account.clone(),
-            sender_owner.clone(),
-            token_program.clone(),
-        ],
+    // Transfer tokens with the token program's transfer_checked instruction
+    haprspl::transfer(
+        token_program,
+        sender_account,
+        mint_account,
+        recipient_account,
+        sender_owner,
+        amount,
+        &[],
     )?;
 
-    msg!("Transfer operation completed successfully.");
+    debug_msg!("Transfer operation completed successfully.");
     Ok(())
 }
 
-// Define the Staker struct to store staking information
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+// Define the Staker struct to store staking information. It is read in place: the
+// integers are little-endian byte arrays, which is also how the earlier Borsh layout
+// stored them.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
 pub struct Staker {
-    pub amount_staked: u64,
-    pub last_staked_time: i64,
-    pub staking_duration: i64,
-    pub owner_pubkey: Pubkey,
+    pub amount_staked: PodU64,
+    pub last_staked_time: PodI64,
+    pub staking_duration: PodI64,
+    pub owner_pubkey: Pubkey,       // Beneficiary owning the stake (seeds the staker PDA)
+    pub withdraw_authority: Pubkey, // May unstake; the owner unless set by StakeFor
+}
+
+pub const STAKER_ACCOUNT_SIZE: usize = ACCOUNT_HEADER_SIZE + 8 + 8 + 8 + 32 + 32; // Total: 97 bytes
+const LEGACY_STAKER_ACCOUNT_SIZE: usize = 8 + 8 + 8 + 32; // Headerless, before withdraw_authority
+const DEFAULT_DURATION: i64 = 10; //24 * 60 * 60; // One day in seconds
+
+impl Staker {
+    pub fn load(data: &[u8]) -> Result<&Self, ProgramError> {
+        haprstate::load_pod(data, AccountKind::Staker)
+    }
+
+    pub fn load_mut(data: &mut [u8]) -> Result<&mut Self, ProgramError> {
+        haprstate::load_pod_mut(data, AccountKind::Staker)
+    }
+
+    // Read a staker account written before account headers. Accounts created before
+    // withdraw_authority existed are 56 bytes long and are withdrawn by their owner.
+    fn unpack_legacy(data: &[u8]) -> Result<Self, ProgramError> {
+        let data_len = data.len();
+        if data_len != LEGACY_STAKER_ACCOUNT_SIZE
+            && data_len != STAKER_ACCOUNT_SIZE - ACCOUNT_HEADER_SIZE
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let data = &mut &data[..];
+        let amount_staked = u64::deserialize(data)?;
+        let last_staked_time = i64::deserialize(data)?;
+        let staking_duration = i64::deserialize(data)?;
+        let owner_pubkey = Pubkey::deserialize(data)?;
+        let withdraw_authority = if data.is_empty() {
+            owner_pubkey
+        } else {
+            Pubkey::deserialize(data)?
+        };
+        Ok(Staker {
+            amount_staked: amount_staked.into(),
+            last_staked_time: last_staked_time.into(),
+            staking_duration: staking_duration.into(),
+            owner_pubkey,
+            withdraw_authority,
+        })
+    }
+}
+
+// PDA and current layout of a staker account written before account headers
+pub fn migrate_staker(
+    data: &[u8],
+    program_id: &Pubkey,
+) -> Result<(Pubkey, Vec<u8>), ProgramError> {
+    let staker_data = Staker::unpack_legacy(data)?;
+    let (staker_pda, _staker_bump) =
+        Pubkey::find_program_address(&[staker_data.owner_pubkey.as_ref(), b"staker"], program_id);
+    Ok((staker_pda, bytemuck::bytes_of(&staker_data).to_vec()))
 }
 
 pub fn stake_tokens(accounts: &[AccountInfo], amount: u64, program_id: &Pubkey) -> ProgramResult {
@@ -318,88 +492,193 @@ pub fn stake_tokens(accounts: &[AccountInfo], amount: u64, program_id: &Pubkey)
 
     let user_token_account = next_account_info(accounts_iter)?;
     let treasury_token_account = next_account_info(accounts_iter)?;
-    let treasury_pda = next_account_info(accounts_iter)?;
+    let _treasury_pda = next_account_info(accounts_iter)?;
     let user = next_account_info(accounts_iter)?;
     let token_program = next_account_info(accounts_iter)?;
     let system_program = next_account_info(accounts_iter)?;
+    let staker_account = next_account_info(accounts_iter)?;
+    // The HAPR mint follows, after the optional treasury PDA read by the pause check
+    let mint_account = haprspl::find_hapr_mint(accounts_iter.as_slice(), program_id)?;
+
+    haprvalidate::assert_signer(user, "Staking user")?;
+
+    stake_for_beneficiary(
+        user,
+        user_token_account,
+        treasury_token_account,
+        mint_account,
+        token_program,
+        system_program,
+        staker_account,
+        user.key,
+        user.key,
+        true,
+        amount,
+        program_id,
+    )
+}
 
-    const DEFAULT_DURATION: i64 = 10; //24 * 60 * 60; // One day in seconds
-    const STAKER_ACCOUNT_SIZE: usize = 8 + 8 + 8 + 32; // Total: 56 bytes
+// Stake on behalf of a beneficiary: the funder signs and pays, the beneficiary owns the
+// staker record and `withdraw_authority` (default: the beneficiary) may unstake it. Only the
+// beneficiary can delegate the authority, so any other authority needs their signature too.
+pub fn stake_for(
+    accounts: &[AccountInfo],
+    amount: u64,
+    withdraw_authority: Option<Pubkey>,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
 
-    let staker_seed = &[user.key.as_ref(), b"staker"];
-    let (staker_pda, bump_seed) = Pubkey::find_program_address(staker_seed, program_id);
-    let staker_account = next_account_info(accounts_iter)?;
+    let funder = next_account_info(accounts_iter)?; // Pays tokens and rent
+    let funder_token_account = next_account_info(accounts_iter)?; // Funder's token account
+    let treasury_token_account = next_account_info(accounts_iter)?; // Treasury's token account
+    let beneficiary = next_account_info(accounts_iter)?; // Owner of the stake
+    let staker_account = next_account_info(accounts_iter)?; // Beneficiary's staker PDA
+    let token_program = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    // The HAPR mint follows, after the optional treasury PDA read by the pause check
+    let mint_account = haprspl::find_hapr_mint(accounts_iter.as_slice(), program_id)?;
+
+    haprvalidate::assert_signer(funder, "Funder")?;
+
+    stake_for_beneficiary(
+        funder,
+        funder_token_account,
+        treasury_token_account,
+        mint_account,
+        token_program,
+        system_program,
+        staker_account,
+        beneficiary.key,
+        &withdraw_authority.unwrap_or(*beneficiary.key),
+        beneficiary.is_signer,
+        amount,
+        program_id,
+    )
+}
 
-    if *staker_account.key != staker_pda {
-        msg!("Error: Staker account does not match derived PDA");
-        return Err(ProgramError::InvalidAccountData);
+#[allow(clippy::too_many_arguments)]
+fn stake_for_beneficiary<'a>(
+    funder: &AccountInfo<'a>,
+    funder_token_account: &AccountInfo<'a>,
+    treasury_token_account: &AccountInfo<'a>,
+    mint_account: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    staker_account: &AccountInfo<'a>,
+    beneficiary: &Pubkey,
+    withdraw_authority: &Pubkey,
+    beneficiary_signed: bool,
+    amount: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    // Otherwise a funder could create or take over someone's record with an authority of
+    // their choosing, and the beneficiary could never stake into it again
+    if withdraw_authority != beneficiary && !beneficiary_signed {
+        msg!("Unauthorized: Only the beneficiary can delegate the withdraw authority.");
+        return Err(HaprError::UnauthorizedAccess.into());
     }
 
-    if staker_account.data_len() == 0 {
+    let bump_seed = haprvalidate::assert_pda(
+        staker_account,
+        &[beneficiary.as_ref(), b"staker"],
+        program_id,
+        HaprError::InvalidStakerPda,
+        "Staker account",
+    )?;
+    haprvalidate::assert_writable(staker_account, "Staker account")?;
+    haprvalidate::assert_token_program(token_program)?;
+    haprvalidate::assert_hapr_token_account(
+        funder_token_account,
+        funder.key,
+        program_id,
+        "Funder token account",
+    )?;
+    haprvalidate::assert_treasury_token_account(treasury_token_account, program_id)?;
+
+    let created = staker_account.data_len() == 0;
+    if created {
         let rent = Rent::get()?;
         let lamports_required = rent.minimum_balance(STAKER_ACCOUNT_SIZE);
 
         invoke_signed(
             &system_instruction::create_account(
-                user.key,
+                funder.key,
                 staker_account.key,
                 lamports_required,
                 STAKER_ACCOUNT_SIZE as u64,
                 program_id,
             ),
-            &[user.clone(), staker_account.clone(), system_program.clone()],
-            &[&[user.key.as_ref(), b"staker", &[bump_seed]]],
+            &[
+                funder.clone(),
+                staker_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[beneficiary.as_ref(), b"staker", &[bump_seed]]],
         )?;
-        msg!("Staker account created.");
+        debug_msg!("Staker account created.");
     } else {
-        msg!(
+        haprvalidate::assert_owned_by(staker_account, program_id, "Staker account")?;
+        debug_msg!(
             "Staker account size: {}, Expected: {}",
             staker_account.data_len(),
             STAKER_ACCOUNT_SIZE
         );
     }
 
-    let mut staker_data = if staker_account.data_len() == 0 {
-        Staker {
-            amount_staked: 0,
-            last_staked_time: 0,
-            staking_duration: DEFAULT_DURATION,
-            owner_pubkey: *user.key,
-        }
-    } else {
-        Staker::try_from_slice(&staker_account.try_borrow_data()?)?
+    let current_time = Clock::get()?.unix_timestamp;
+    let total_staked = {
+        let mut staker_account_data = staker_account.try_borrow_mut_data()?;
+        let staker_data = if created {
+            let staker_data: &mut Staker =
+                haprstate::init_pod(&mut staker_account_data, AccountKind::Staker)?;
+            staker_data.owner_pubkey = *beneficiary;
+            staker_data.withdraw_authority = *withdraw_authority;
+            staker_data
+        } else {
+            let staker_data = Staker::load_mut(&mut staker_account_data)?;
+            // Tokens are never added to a stake that someone else can withdraw. Only the
+            // beneficiary may hand a fully unstaked record to a new withdraw authority.
+            if staker_data.withdraw_authority != *withdraw_authority {
+                if u64::from(staker_data.amount_staked) > 0 || !beneficiary_signed {
+                    msg!(
+                        "Unauthorized: Stake is already withdrawable by {}.",
+                        staker_data.withdraw_authority
+                    );
+                    return Err(HaprError::UnauthorizedAccess.into());
+                }
+                staker_data.withdraw_authority = *withdraw_authority;
+            }
+            staker_data
+        };
+        debug_msg!("Current staker data: {:?}", staker_data);
+
+        let total_staked = u64::from(staker_data.amount_staked) + amount;
+        staker_data.amount_staked = total_staked.into();
+        staker_data.last_staked_time = current_time.into();
+        staker_data.staking_duration = DEFAULT_DURATION.into();
+        debug_msg!("Updated staker data: {:?}", staker_data);
+        total_staked
     };
 
-    msg!("Current staker data: {:?}", staker_data);
-
-    let transfer_instruction = spl_token::instruction::transfer(
-        &spl_token::id(),
-        user_token_account.key,
-        treasury_token_account.key,
-        user.key,
-        &[],
+    // The funder covers any transfer fee so the full stake reaches the treasury
+    let paid = haprspl::transfer_in(
+        token_program,
+        funder_token_account,
+        mint_account,
+        treasury_token_account,
+        funder,
         amount,
     )?;
-    invoke(
-        &transfer_instruction,
-        &[
-            user_token_account.clone(),
-            treasury_token_account.clone(),
-            user.clone(),
-            token_program.clone(),
-        ],
-    )?;
-    msg!("Staked {} tokens successfully.", amount);
-
-    staker_data.amount_staked += amount;
-    staker_data.last_staked_time = Clock::get()?.unix_timestamp;
-    staker_data.staking_duration = DEFAULT_DURATION;
-    staker_data.owner_pubkey = *user.key;
+    debug_msg!("Staked {} tokens successfully ({} paid).", amount, paid);
 
-    staker_data.serialize(&mut Cursor::new(
-        &mut staker_account.try_borrow_mut_data()?[..],
-    ))?;
-    msg!("Updated staker data: {:?}", staker_data);
+    HaprEvent::Staked {
+        staker: *beneficiary,
+        amount,
+        total_staked,
+        timestamp: current_time,
+    }
+    .emit();
 
     Ok(())
 }
@@ -414,47 +693,66 @@ pub fn unstake_tokens(accounts: &[AccountInfo], amount: u64, program_id: &Pubkey
     let user_token_account = next_account_info(accounts_iter)?; // User's token account
     let treasury_token_account = next_account_info(accounts_iter)?; // Treasury's token account
     let authority_account = next_account_info(accounts_iter)?; // Treasury PDA authority
-    let token_program = next_account_info(accounts_iter)?; // SPL Token program
+    let token_program = next_account_info(accounts_iter)?; // SPL Token or Token-2022 program
+    // The HAPR mint, optionally the staker's VeLock PDA (reward boost), and with a wallet
+    // outflow limit the user's wallet outflow PDA and the system program
+    let extra_accounts = accounts_iter.as_slice();
+
+    haprvalidate::assert_signer(user, "Withdraw authority")?;
+    haprvalidate::assert_writable(staker_account, "Staker account")?;
+    haprvalidate::assert_token_program(token_program)?;
+    let mint_account = haprspl::find_hapr_mint(extra_accounts, program_id)?;
 
     // Verify PDA Authority
     let (treasury_pda, bump_seed) = Pubkey::find_program_address(&[TREASURY_SEED], program_id);
     if *authority_account.key != treasury_pda {
         msg!("Error: Invalid Treasury PDA authority");
-        return Err(ProgramError::IncorrectProgramId);
+        return Err(HaprError::InvalidTreasuryPda.into());
     }
-    msg!("Treasury PDA authority verified.");
+    debug_msg!("Treasury PDA authority verified.");
 
-    // Ensure token accounts are owned by the SPL Token program
-    if user_token_account.owner != &spl_token::id() {
-        msg!("Error: User Token Account is not owned by SPL Token Program.");
-        return Err(ProgramError::IncorrectProgramId);
-    }
-    if treasury_token_account.owner != &spl_token::id() {
-        msg!("Error: Treasury Token Account is not owned by SPL Token Program.");
-        return Err(ProgramError::IncorrectProgramId);
-    }
-    msg!("Token account ownership verified.");
+    // Payouts only go from the treasury ATA to the user's own HAPR account
+    haprvalidate::assert_hapr_token_account(
+        user_token_account,
+        user.key,
+        program_id,
+        "User token account",
+    )?;
+    haprvalidate::assert_treasury_token_account(treasury_token_account, program_id)?;
+    debug_msg!("Token account ownership verified.");
 
     // Deserialize Staker Metadata
-    let mut staker_data = Staker::try_from_slice(&staker_account.data.borrow())?;
+    haprvalidate::assert_owned_by(staker_account, program_id, "Staker account")?;
+    let mut staker_account_data = staker_account.try_borrow_mut_data()?;
+    let staker_data = Staker::load_mut(&mut staker_account_data)?;
+    haprvalidate::assert_pda(
+        staker_account,
+        &[staker_data.owner_pubkey.as_ref(), b"staker"],
+        program_id,
+        HaprError::InvalidStakerPda,
+        "Staker account",
+    )?;
 
-    // Validate Ownership
-    if staker_data.owner_pubkey != *user.key {
+    // Validate the withdraw authority
+    if staker_data.withdraw_authority != *user.key {
         msg!(
             "Error: Unstake attempted by unauthorized user. Expected: {}, Found: {}",
-            staker_data.owner_pubkey,
+            staker_data.withdraw_authority,
             user.key
         );
-        return Err(ProgramError::IllegalOwner);
+        return Err(HaprError::UnauthorizedAccess.into());
     }
 
-    msg!("Ownership validation successful.");
-    msg!(
+    debug_msg!("Ownership validation successful.");
+    let amount_staked = u64::from(staker_data.amount_staked);
+    let last_staked_time = i64::from(staker_data.last_staked_time);
+    let staking_duration = i64::from(staker_data.staking_duration);
+    debug_msg!(
         "Staker Metadata: Staker: {}, Amount Staked: {}, Last Staked Time: {}, Staking Duration: {}",
         staker_data.owner_pubkey,
-        staker_data.amount_staked,
-        staker_data.last_staked_time,
-        staker_data.staking_duration,
+        amount_staked,
+        last_staked_time,
+        staking_duration,
     );
 
     // Fetch current time`
@@ -462,57 +760,61 @@ pub fn unstake_tokens(accounts: &[AccountInfo], amount: u64, program_id: &Pubkey
     let current_time = clock.unix_timestamp;
 
     // Check staking duration
-    if current_time < staker_data.last_staked_time + staker_data.staking_duration {
+    if current_time < last_staked_time + staking_duration {
         msg!(
             "Error: Staking period not complete. Current time: {}, Required time: {}",
             current_time,
-            staker_data.last_staked_time + staker_data.staking_duration
+            last_staked_time + staking_duration
         );
-        return Err(ProgramError::Custom(
-            CustomError::StakingPeriodNotComplete as u32,
-        ));
+        return Err(HaprError::StakingPeriodNotComplete.into());
     }
 
-    let unstake_amount = if amount >= staker_data.amount_staked {
-        staker_data.amount_staked
+    let unstake_amount = if amount >= amount_staked {
+        amount_staked
     } else {
         amount
     };
 
     // Calculate Rewards
-    let reward_amount =
-        calculate_staking_rewards(unstake_amount, staker_data.last_staked_time, current_time);
+    let config = haprtreasury::load_config(authority_account, program_id)?;
+    let ve_lock_pda = haprlock::ve_lock_address(&staker_data.owner_pubkey, program_id);
+    let ve_lock_account = extra_accounts.iter().find(|account| *account.key == ve_lock_pda);
+    let ve_lock =
+        haprlock::load_owner_ve_lock(ve_lock_account, &staker_data.owner_pubkey, program_id)?;
+    let reward_amount = haprlock::boosted_reward(
+        calculate_staking_rewards(
+            unstake_amount,
+            last_staked_time,
+            current_time,
+            config.staking_apr_bps,
+        ),
+        amount_staked,
+        ve_lock.as_ref(),
+        last_staked_time,
+        current_time,
+    );
     let total_amount = unstake_amount + reward_amount;
 
-    msg!(
+    debug_msg!(
         "Rewards calculated: Amount Staked: {}, Reward: {}, Total: {}",
         unstake_amount,
         reward_amount,
         total_amount
     );
 
-    msg!(
-        "Treasury Token Account Owner: {:?}",
-        treasury_token_account.owner
-    );
-    if treasury_token_account.owner != &spl_token::id() {
-        msg!("Error: Treasury Token Account is not owned by SPL Token Program.");
-        return Err(ProgramError::IncorrectProgramId);
-    }
+    debug_msg!("Source Token Account: {:?}", treasury_token_account.key);
+    debug_msg!("Destination Token Account: {:?}", user_token_account.key);
+    debug_msg!("Transfer Authority: {:?}", authority_account.key);
 
-    msg!("Source Token Account: {:?}", treasury_token_account.key);
-    msg!("Destination Token Account: {:?}", user_token_account.key);
-    msg!("Transfer Authority: {:?}", authority_account.key);
-
-    msg!(
+    debug_msg!(
         "Source Token Account Owner: {:?}",
         treasury_token_account.owner
     );
-    msg!(
+    debug_msg!(
         "Destination Token Account Owner: {:?}",
         user_token_account.owner
     );
-    msg!("Authority Provided: {:?}", authority_account.key);
+    debug_msg!("Authority Provided: {:?}", authority_account.key);
 
     // let token_account_data =
     //     spl_token::state::Account::unpack(&treasury_token_account.try_borrow_data()?)?;
@@ -526,54 +828,85 @@ pub fn unstake_tokens(accounts: &[AccountInfo], amount: u64, program_id: &Pubkey
     // }
 
     // Transfer tokens from Treasury to User
+    haprlimits::record_outflow(accounts, authority_account, Some(user), total_amount, program_id)?;
     let seeds = &[TREASURY_SEED, &[bump_seed]];
-    let transfer_instruction = spl_token::instruction::transfer(
-        &spl_token::id(),
-        &treasury_token_account.key,
-        &user_token_account.key,
-        &authority_account.key,
-        &[], // No additional signers
+    haprspl::transfer(
+        token_program,
+        treasury_token_account,
+        mint_account,
+        user_token_account,
+        authority_account,
         total_amount,
-    )?;
-    invoke_signed(
-        &transfer_instruction,
-        &[
-            treasury_token_account.clone(),
-            user_token_account.clone(),
-            authority_account.clone(),
-            token_program.clone(),
-        ],
         &[seeds],
     )?;
-    msg!("Tokens transferred: {}.", total_amount);
+    debug_msg!("Tokens transferred: {}.", total_amount);
 
     // Reset or adjust staker data based on the unstaked amount
-    if amount >= staker_data.amount_staked {
+    if amount >= amount_staked {
         // Fully unstake
-        staker_data.amount_staked = 0;
-        staker_data.last_staked_time = 0;
-        staker_data.staking_duration = 0;
-        msg!("Full unstake completed. Staker data reset.");
+        staker_data.amount_staked = 0.into();
+        staker_data.last_staked_time = 0.into();
+        staker_data.staking_duration = 0.into();
+        debug_msg!("Full unstake completed. Staker data reset.");
     } else {
         // Partial unstake
-        staker_data.amount_staked -= amount;
+        staker_data.amount_staked = (amount_staked - amount).into();
         //staker_data.last_staked_time = Clock::get()?.unix_timestamp; // Update staking timestamp
-        msg!(
+        debug_msg!(
             "Partial unstake completed. Remaining staked amount: {}",
-            staker_data.amount_staked
+            amount_staked - amount
         );
     }
 
-    // Serialize the updated staker data back into the account
-    staker_data.serialize(&mut Cursor::new(
-        &mut staker_account.try_borrow_mut_data()?[..],
-    ))?;
-
-    msg!("Staker data updated successfully.");
+    debug_msg!("Staker data updated successfully.");
+    HaprEvent::Unstaked {
+        staker: staker_data.owner_pubkey,
+        amount: unstake_amount,
+        remaining_staked: u64::from(staker_data.amount_staked),
+        timestamp: current_time,
+    }
+    .emit();
+    if reward_amount > 0 {
+        HaprEvent::RewardsPaid {
+            recipient: *user.key,
+            amount: reward_amount,
+        }
+        .emit();
+    }
 
     Ok(())
 }
 
+// Close the owner's staker account once everything has been unstaked, returning its rent
+// to the owner
+pub fn close_staker(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let owner_account = next_account_info(accounts_iter)?; // Staker owner (receives the rent)
+    let staker_account = next_account_info(accounts_iter)?; // Staker PDA
+
+    haprvalidate::assert_signer(owner_account, "Staker owner")?;
+    haprvalidate::assert_writable(owner_account, "Staker owner")?;
+    haprvalidate::assert_program_account(
+        staker_account,
+        &[owner_account.key.as_ref(), b"staker"],
+        program_id,
+        HaprError::InvalidStakerPda,
+        "Staker account",
+    )?;
+    haprvalidate::assert_writable(staker_account, "Staker account")?;
+
+    let amount_staked = u64::from(Staker::load(&staker_account.data.borrow())?.amount_staked);
+    if amount_staked != 0 {
+        msg!(
+            "Error: Staker account still holds {} staked tokens.",
+            amount_staked
+        );
+        return Err(HaprError::AccountNotEmpty.into());
+    }
+
+    haprstate::close_account(staker_account, owner_account)
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
 pub struct Ticket {
     // pub owner: Pubkey,
@@ -584,11 +917,484 @@ pub struct Ticket {
     pub claimed: bool,
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+// A lot held in a TicketAccount slot. Slots holding no tickets are free.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct TicketSlot {
+    pub number_of_tickets: PodU64,
+    pub deposit_time: PodI64,
+    pub vesting_period: PodI64,
+}
+
+// Receipt recorded for every (partial) redemption of a ticket lot
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct TicketReceipt {
+    pub number_of_tickets: PodU64, // Tickets redeemed from the lot
+    pub deposit_time: PodI64,      // Deposit time of the redeemed lot
+    pub vesting_period: PodI64,    // Vesting period of the redeemed lot
+    pub redeemed_amount: PodU64,   // Principal plus yield paid out
+    pub redeemed_time: PodI64,
+    pub claimed: PodBool, // True when this redemption emptied the lot
+}
+
+// Fixed-size ticket account read and written in place. Lots sit in fixed slots, so a
+// lot's index stays the same while other lots come and go, and receipts are kept in a
+// ring that overwrites the oldest one.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
 pub struct TicketAccount {
-    pub owner: Pubkey,        // Owner of this ticket account
-    pub tickets: Vec<Ticket>, // List of tickets
-    pub ticket_total: u64,
+    pub owner: Pubkey, // Owner of this ticket account
+    pub ticket_total: PodU64,
+    pub draw_locked_until: PodI64, // Tickets entered in a draw can't move before this time
+    pub keep_history: PodBool,     // Record receipts for redeemed lots
+    pub next_slot: u8,             // Slot searched first for a new lot
+    pub next_receipt: u8,          // Ring position the next receipt is written to
+    pub receipt_count: u8,         // Receipts held in the ring
+    pub slots: [TicketSlot; MAX_TICKET_LOTS],
+    pub receipts: [TicketReceipt; MAX_TICKET_RECEIPTS],
+}
+
+const TICKET_SLOT_SIZE: usize = 8 + 8 + 8; // Size of each lot slot
+const TICKET_RECEIPT_SIZE: usize = 8 + 8 + 8 + 8 + 8 + 1; // Size of each receipt
+const MAX_TICKET_RECEIPTS: usize = 32; // Oldest receipts are overwritten beyond this
+const MAX_TICKET_LOTS: usize = 64;
+pub const TICKET_ACCOUNT_SIZE: usize = ACCOUNT_HEADER_SIZE
+    + 32 // Owner (Pubkey)
+    + 8 // ticket_total (u64)
+    + 8 // draw_locked_until (i64)
+    + 1 // keep_history (bool)
+    + 1 // next_slot (u8)
+    + 1 // next_receipt (u8)
+    + 1 // receipt_count (u8)
+    + MAX_TICKET_LOTS * TICKET_SLOT_SIZE
+    + MAX_TICKET_RECEIPTS * TICKET_RECEIPT_SIZE; // Total: 2909 bytes
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+impl Ticket {
+    // Day on which this lot finishes vesting; lots sharing a maturity day are merged
+    fn maturity_day(&self) -> i64 {
+        (self.deposit_time + self.vesting_period).div_euclid(SECONDS_PER_DAY)
+    }
+
+    // Merge another lot into this one. The deposit time becomes the ticket-weighted
+    // average so the accrued yield is unchanged, and the later maturity is kept.
+    fn merge(&mut self, other: &Ticket) {
+        let total_tickets = self.number_of_tickets + other.number_of_tickets;
+        let maturity = std::cmp::max(
+            self.deposit_time + self.vesting_period,
+            other.deposit_time + other.vesting_period,
+        );
+        let weighted_deposit_time = (self.number_of_tickets as i128 * self.deposit_time as i128
+            + other.number_of_tickets as i128 * other.deposit_time as i128)
+            / total_tickets as i128;
+
+        self.number_of_tickets = total_tickets;
+        self.deposit_time = weighted_deposit_time as i64;
+        self.vesting_period = maturity - self.deposit_time;
+    }
+}
+
+impl TicketSlot {
+    pub fn is_free(&self) -> bool {
+        u64::from(self.number_of_tickets) == 0
+    }
+
+    pub fn ticket(&self) -> Ticket {
+        Ticket {
+            number_of_tickets: self.number_of_tickets.into(),
+            deposit_time: self.deposit_time.into(),
+            vesting_period: self.vesting_period.into(),
+            claimed: false,
+        }
+    }
+
+    // Store `ticket` in this slot, freeing it when no tickets are left
+    fn set(&mut self, ticket: &Ticket) {
+        *self = if ticket.number_of_tickets == 0 {
+            TicketSlot::default()
+        } else {
+            TicketSlot {
+                number_of_tickets: ticket.number_of_tickets.into(),
+                deposit_time: ticket.deposit_time.into(),
+                vesting_period: ticket.vesting_period.into(),
+            }
+        };
+    }
+}
+
+impl TicketAccount {
+    pub fn load(data: &[u8]) -> Result<&Self, ProgramError> {
+        haprstate::load_pod(data, AccountKind::TicketAccount)
+    }
+
+    pub fn load_mut(data: &mut [u8]) -> Result<&mut Self, ProgramError> {
+        haprstate::load_pod_mut(data, AccountKind::TicketAccount)
+    }
+
+    // Reject moving or redeeming tickets that are entered in a running draw
+    pub fn check_draw_lock(&self, current_time: i64) -> ProgramResult {
+        let draw_locked_until = i64::from(self.draw_locked_until);
+        if current_time < draw_locked_until {
+            msg!(
+                "Error: Tickets are entered in a draw and locked until {}.",
+                draw_locked_until
+            );
+            return Err(HaprError::TicketsLockedInDraw.into());
+        }
+        Ok(())
+    }
+
+    // Slot indexes around the ring, starting after the slot filled most recently
+    fn slot_order(&self) -> impl Iterator<Item = usize> {
+        let next_slot = self.next_slot as usize;
+        (0..MAX_TICKET_LOTS).map(move |offset| (next_slot + offset) % MAX_TICKET_LOTS)
+    }
+
+    // Held lots and their slot indexes
+    pub fn lots(&self) -> impl Iterator<Item = (usize, Ticket)> + '_ {
+        self.slot_order()
+            .filter(|index| !self.slots[*index].is_free())
+            .map(|index| (index, self.slots[index].ticket()))
+    }
+
+    // Held receipts, oldest first
+    pub fn receipts(&self) -> impl Iterator<Item = &TicketReceipt> {
+        let receipt_count = self.receipt_count as usize;
+        let oldest = (self.next_receipt as usize + MAX_TICKET_RECEIPTS - receipt_count)
+            % MAX_TICKET_RECEIPTS;
+        (0..receipt_count)
+            .map(move |offset| &self.receipts[(oldest + offset) % MAX_TICKET_RECEIPTS])
+    }
+
+    // Append a redemption receipt when history is enabled
+    fn record_receipt(&mut self, receipt: TicketReceipt) {
+        if !bool::from(self.keep_history) {
+            return;
+        }
+        self.receipts[self.next_receipt as usize] = receipt;
+        self.next_receipt = ((self.next_receipt as usize + 1) % MAX_TICKET_RECEIPTS) as u8;
+        self.receipt_count =
+            std::cmp::min(self.receipt_count as usize + 1, MAX_TICKET_RECEIPTS) as u8;
+    }
+
+    fn clear_receipts(&mut self) {
+        self.receipts = [TicketReceipt::default(); MAX_TICKET_RECEIPTS];
+        self.next_receipt = 0;
+        self.receipt_count = 0;
+    }
+
+    // Add a lot, merging it into an existing lot with the same maturity day when possible
+    // and otherwise filling the next free slot around the ring
+    pub fn add_ticket_lot(&mut self, ticket: Ticket) -> ProgramResult {
+        let number_of_tickets = ticket.number_of_tickets;
+        let maturity_day = ticket.maturity_day();
+        if let Some(slot) = self
+            .slots
+            .iter_mut()
+            .find(|slot| !slot.is_free() && slot.ticket().maturity_day() == maturity_day)
+        {
+            let mut existing = slot.ticket();
+            existing.merge(&ticket);
+            slot.set(&existing);
+            debug_msg!(
+                "Merged tickets into existing lot maturing on day {}.",
+                maturity_day
+            );
+        } else {
+            let index = self
+                .slot_order()
+                .find(|index| self.slots[*index].is_free())
+                .ok_or_else(|| {
+                    msg!(
+                        "Error: Ticket account already holds the maximum of {} lots.",
+                        MAX_TICKET_LOTS
+                    );
+                    HaprError::TooManyTicketLots
+                })?;
+            self.slots[index].set(&ticket);
+            self.next_slot = ((index + 1) % MAX_TICKET_LOTS) as u8;
+        }
+        self.ticket_total = (u64::from(self.ticket_total) + number_of_tickets).into();
+        Ok(())
+    }
+
+    // Split up to `amount` tickets (0 = the whole lot) off the lot in slot `ticket_index`,
+    // keeping its vesting schedule
+    pub fn take_tickets(
+        &mut self,
+        ticket_index: usize,
+        amount: u64,
+    ) -> Result<Ticket, ProgramError> {
+        let slot = match self.slots.get_mut(ticket_index) {
+            Some(slot) if !slot.is_free() => slot,
+            _ => {
+                msg!("Error: No ticket lot in slot {}.", ticket_index);
+                return Err(HaprError::InvalidTicketIndex.into());
+            }
+        };
+
+        let mut ticket = slot.ticket();
+        let tickets_to_move = if amount == 0 || amount >= ticket.number_of_tickets {
+            ticket.number_of_tickets // Move the whole lot
+        } else {
+            amount
+        };
+
+        let taken_ticket = Ticket {
+            number_of_tickets: tickets_to_move,
+            deposit_time: ticket.deposit_time,
+            vesting_period: ticket.vesting_period,
+            claimed: false,
+        };
+
+        ticket.number_of_tickets -= tickets_to_move;
+        slot.set(&ticket);
+        self.ticket_total = (u64::from(self.ticket_total) - tickets_to_move).into();
+        Ok(taken_ticket)
+    }
+
+    // Merge every group of lots sharing a maturity day into the first slot of the group
+    fn compact(&mut self) {
+        for index in 0..MAX_TICKET_LOTS {
+            if self.slots[index].is_free() {
+                continue;
+            }
+            let mut ticket = self.slots[index].ticket();
+            let maturity_day = ticket.maturity_day();
+            for other_index in index + 1..MAX_TICKET_LOTS {
+                let other = self.slots[other_index];
+                if !other.is_free() && other.ticket().maturity_day() == maturity_day {
+                    ticket.merge(&other.ticket());
+                    self.slots[other_index] = TicketSlot::default();
+                }
+            }
+            self.slots[index].set(&ticket);
+        }
+    }
+}
+
+// Redemption receipt in the version 1 layout
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+struct LegacyTicketReceipt {
+    number_of_tickets: u64,
+    deposit_time: i64,
+    vesting_period: i64,
+    redeemed_amount: u64,
+    redeemed_time: i64,
+    claimed: bool,
+}
+
+// Ticket account in the Borsh layout written before fixed slots: headerless, or behind a
+// version 1 header
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+struct LegacyTicketAccount {
+    owner: Pubkey,
+    tickets: Vec<Ticket>,
+    ticket_total: u64,
+    keep_history: bool,
+    receipts: Vec<LegacyTicketReceipt>,
+    draw_locked_until: i64,
+}
+
+impl LegacyTicketAccount {
+    // Fields appended after the original layout (owner, tickets, ticket_total) fall back
+    // to their defaults when absent.
+    fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        let data = &mut &data[..];
+        let owner = Pubkey::deserialize(data)?;
+        let tickets = Vec::<Ticket>::deserialize(data)?;
+        let ticket_total = u64::deserialize(data)?;
+
+        let keep_history = if data.is_empty() {
+            false
+        } else {
+            bool::deserialize(data)?
+        };
+        let receipts = if data.is_empty() {
+            Vec::new()
+        } else {
+            Vec::<LegacyTicketReceipt>::deserialize(data)?
+        };
+        let draw_locked_until = if data.is_empty() {
+            0
+        } else {
+            i64::deserialize(data)?
+        };
+
+        Ok(LegacyTicketAccount {
+            owner,
+            tickets,
+            ticket_total,
+            keep_history,
+            receipts,
+            draw_locked_until,
+        })
+    }
+}
+
+// Grow or shrink a program-owned account to `required_size`, topping up rent from the payer when
+// growing and refunding the excess when shrinking
+pub fn resize_account<'a>(
+    account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    required_size: usize,
+) -> ProgramResult {
+    if account.data_len() == required_size {
+        return Ok(());
+    }
+
+    if account.data_len() < required_size {
+        grow_account(account, payer, system_program, required_size)?;
+    } else {
+        account.realloc(required_size, false)?;
+        debug_msg!("Reallocated account to new size: {}", required_size);
+    }
+
+    // Return rent freed by shrinking to the payer
+    let rent_required = Rent::get()?.minimum_balance(required_size);
+    let excess_lamports = account.lamports().saturating_sub(rent_required);
+    if excess_lamports > 0 {
+        **account.try_borrow_mut_lamports()? -= excess_lamports;
+        **payer.try_borrow_mut_lamports()? += excess_lamports;
+        debug_msg!("Refunded {} lamports of excess rent.", excess_lamports);
+    }
+    Ok(())
+}
+
+// Grow a program-owned account to `required_size`, topping up rent from the payer. Lamports
+// already above the new rent stay in the account.
+pub fn grow_account<'a>(
+    account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    required_size: usize,
+) -> ProgramResult {
+    let rent = Rent::get()?;
+    let new_lamports_required = rent.minimum_balance(required_size);
+    let current_lamports = account.lamports();
+
+    if new_lamports_required > current_lamports {
+        let lamports_to_add = new_lamports_required - current_lamports;
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, lamports_to_add),
+            &[
+                payer.clone(),
+                account.clone(),
+                system_program.clone(),
+            ],
+        )?;
+        debug_msg!(
+            "Transferred {} lamports to the account for rent-exemption.",
+            lamports_to_add
+        );
+    }
+
+    account.realloc(required_size, false)?;
+    debug_msg!("Reallocated account to new size: {}", required_size);
+    Ok(())
+}
+
+// PDA and current layout of a TicketAccount in the headerless or version 1 layout. Lots
+// keep their order, so the first lots land in slots 0, 1, ...
+pub fn migrate_ticket_account(
+    data: &[u8],
+    program_id: &Pubkey,
+) -> Result<(Pubkey, Vec<u8>), ProgramError> {
+    let legacy = LegacyTicketAccount::unpack(data)?;
+    let (ticket_pda, _ticket_bump) =
+        Pubkey::find_program_address(&[TICKET_SEED, legacy.owner.as_ref()], program_id);
+
+    let mut body = vec![0u8; TICKET_ACCOUNT_SIZE - ACCOUNT_HEADER_SIZE];
+    let ticket_account_data: &mut TicketAccount = bytemuck::from_bytes_mut(&mut body);
+    ticket_account_data.owner = legacy.owner;
+    ticket_account_data.keep_history = legacy.keep_history.into();
+    ticket_account_data.draw_locked_until = legacy.draw_locked_until.into();
+
+    ticket_account_data.ticket_total = legacy.ticket_total.into();
+
+    let held_lots: Vec<&Ticket> = legacy
+        .tickets
+        .iter()
+        .filter(|ticket| !ticket.claimed)
+        .collect();
+    if held_lots.len() > MAX_TICKET_LOTS {
+        msg!(
+            "Error: Legacy ticket account holds more than {} lots.",
+            MAX_TICKET_LOTS
+        );
+        return Err(HaprError::TooManyTicketLots.into());
+    }
+    for (slot, ticket) in ticket_account_data.slots.iter_mut().zip(&held_lots) {
+        slot.set(ticket);
+    }
+    ticket_account_data.next_slot = (held_lots.len() % MAX_TICKET_LOTS) as u8;
+
+    // Keep the newest receipts, oldest first from the start of the ring
+    let skipped = legacy.receipts.len().saturating_sub(MAX_TICKET_RECEIPTS);
+    let kept_receipts = &legacy.receipts[skipped..];
+    for (slot, receipt) in ticket_account_data.receipts.iter_mut().zip(kept_receipts) {
+        *slot = TicketReceipt {
+            number_of_tickets: receipt.number_of_tickets.into(),
+            deposit_time: receipt.deposit_time.into(),
+            vesting_period: receipt.vesting_period.into(),
+            redeemed_amount: receipt.redeemed_amount.into(),
+            redeemed_time: receipt.redeemed_time.into(),
+            claimed: receipt.claimed.into(),
+        };
+    }
+    ticket_account_data.receipt_count = kept_receipts.len() as u8;
+    ticket_account_data.next_receipt = (kept_receipts.len() % MAX_TICKET_RECEIPTS) as u8;
+    Ok((ticket_pda, body))
+}
+
+// Create `owner`'s TicketAccount (paid by `payer`) if it doesn't exist yet, otherwise
+// check that the program owns it. Callers borrow the data and load it afterwards.
+pub fn ensure_ticket_account<'a>(
+    ticket_account: &AccountInfo<'a>,
+    owner: &Pubkey,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let ticket_bump_seed = haprvalidate::assert_pda(
+        ticket_account,
+        &[TICKET_SEED, owner.as_ref()],
+        program_id,
+        HaprError::InvalidTicketPda,
+        "Ticket account",
+    )?;
+    haprvalidate::assert_writable(ticket_account, "Ticket account")?;
+
+    if ticket_account.data_len() == 0 {
+        let rent = Rent::get()?;
+
+        invoke_signed(
+            &system_instruction::create_account(
+                payer.key,                                 // Payer
+                ticket_account.key,                        // New TicketAccount
+                rent.minimum_balance(TICKET_ACCOUNT_SIZE), // Rent-exempt balance
+                TICKET_ACCOUNT_SIZE as u64,                // Account size
+                program_id,                                // Program ID as the owner
+            ),
+            &[
+                payer.clone(),
+                ticket_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[TICKET_SEED, owner.as_ref(), &[ticket_bump_seed]]],
+        )?;
+        debug_msg!("Ticket account created and owned by the program.");
+
+        let mut data = ticket_account.try_borrow_mut_data()?;
+        let ticket_account_data: &mut TicketAccount =
+            haprstate::init_pod(&mut data, AccountKind::TicketAccount)?;
+        ticket_account_data.owner = *owner;
+        return Ok(());
+    }
+
+    haprvalidate::assert_owned_by(ticket_account, program_id, "Ticket account")
 }
 
 //Purchase a ticket for a certain amount via PDA
@@ -605,15 +1411,28 @@ pub fn purchase_tickets(
     let ticket_account = next_account_info(accounts_iter)?; // Ticket account
     let token_program = next_account_info(accounts_iter)?;
     let system_program = next_account_info(accounts_iter)?;
-
-    // Validate Ticket PDA
-    let (ticket_pda, ticket_bump_seed) =
-        Pubkey::find_program_address(&[TICKET_SEED, buyer_account.key.as_ref()], program_id);
-    if *ticket_account.key != ticket_pda {
-        msg!("Error: Invalid Ticket PDA");
-        return Err(ProgramError::InvalidAccountData);
-    }
-    msg!("Ticket PDA verified successfully.");
+    // The HAPR mint follows, after the optional treasury PDA read by the pause check
+    let mint_account = haprspl::find_hapr_mint(accounts_iter.as_slice(), program_id)?;
+
+    haprvalidate::assert_signer(buyer_account, "Buyer")?;
+    haprvalidate::assert_token_program(token_program)?;
+    haprvalidate::assert_hapr_token_account(
+        buyer_token_account,
+        buyer_account.key,
+        program_id,
+        "Buyer token account",
+    )?;
+    haprvalidate::assert_treasury_token_account(treasury_token_account, program_id)?;
+
+    // Validate the Ticket PDA, creating the account on the first purchase
+    ensure_ticket_account(
+        ticket_account,
+        buyer_account.key,
+        buyer_account,
+        system_program,
+        program_id,
+    )?;
+    debug_msg!("Ticket PDA verified successfully.");
 
     // Calculate the total cost for tickets
     let number_of_tickets = amount / FIXED_TICKET_PRICE;
@@ -621,137 +1440,53 @@ pub fn purchase_tickets(
 
     if number_of_tickets == 0 {
         msg!("Error: Amount is insufficient to purchase tickets.");
-        return Err(ProgramError::Custom(0x01));
+        return Err(HaprError::InsufficientTicketAmount.into());
     }
 
-    msg!(
+    debug_msg!(
         "Purchasing {} tickets for {} tokens (Price per ticket: {}).",
         number_of_tickets,
         total_cost,
         FIXED_TICKET_PRICE
     );
 
-    // Perform the token transfer
-    let transfer_instruction = transfer(
-        &spl_token::id(),
-        &buyer_token_account.key,
-        &treasury_token_account.key,
-        &buyer_account.key,
-        &[],
+    // Perform the token transfer; the buyer covers any transfer fee so the treasury holds
+    // the full principal owed back at redemption
+    let paid = haprspl::transfer_in(
+        token_program,
+        buyer_token_account,
+        mint_account,
+        treasury_token_account,
+        buyer_account,
         total_cost,
     )?;
-    invoke(
-        &transfer_instruction,
-        &[
-            buyer_token_account.clone(),
-            treasury_token_account.clone(),
-            buyer_account.clone(),
-            token_program.clone(),
-        ],
-    )?;
-    msg!("Token transfer successful.");
-
-    // Load or initialize the TicketAccount
-    let mut ticket_account_data: TicketAccount;
-    if ticket_account.data_len() == 0 {
-        // Account doesn't exist, create it
-        let rent = Rent::get()?;
-        let ticket_size = 8 + 8 + 8 + 1; // Ticket size
-        let required_size = 32 // Owner (Pubkey)
-            + 8                // ticket_total (u64)
-            + 4                // Vec metadata
-            + (1 * ticket_size); // Minimum size for one ticket
-
-        let lamports_required = rent.minimum_balance(required_size);
-        invoke_signed(
-            &system_instruction::create_account(
-                buyer_account.key,    // Payer
-                ticket_account.key,   // New TicketAccount
-                lamports_required,    // Rent-exempt balance
-                required_size as u64, // Account size
-                program_id,           // Program ID as the owner
-            ),
-            &[
-                buyer_account.clone(),
-                ticket_account.clone(),
-                system_program.clone(),
-            ],
-            &[&[TICKET_SEED, buyer_account.key.as_ref(), &[ticket_bump_seed]]],
-        )?;
-        msg!("Ticket account created and owned by the program.");
-
-        ticket_account_data = TicketAccount {
-            owner: *buyer_account.key,
-            tickets: Vec::new(),
-            ticket_total: 0,
-        };
-    } else {
-        // Load existing account
-        if *ticket_account.owner != *program_id {
-            msg!("Error: Account owner mismatch. Cannot deserialize.");
-            return Err(ProgramError::IllegalOwner);
-        }
-
-        ticket_account_data = TicketAccount::try_from_slice(&ticket_account.data.borrow())
-            .map_err(|_| ProgramError::InvalidAccountData)?;
-        msg!("Existing TicketAccount loaded.");
-    }
+    debug_msg!("Token transfer successful ({} paid).", paid);
 
     // Add new tickets to the account
+    let deposit_time = Clock::get()?.unix_timestamp;
     let ticket = Ticket {
         number_of_tickets,
-        deposit_time: Clock::get()?.unix_timestamp,
+        deposit_time,
         vesting_period,
         claimed: false,
     };
-    ticket_account_data.tickets.push(ticket);
-    ticket_account_data.ticket_total += number_of_tickets;
-
-    // Calculate required size for updated TicketAccount
-    let ticket_size = 8 + 8 + 8 + 1; // Size of each ticket
-    let required_size = 32 // Owner (Pubkey)
-        + 8               // ticket_total (u64)
-        + 4               // Vec metadata
-        + (ticket_account_data.tickets.len() * ticket_size);
-
-    if ticket_account.data_len() < required_size {
-        // Calculate required rent-exempt balance
-        let rent = Rent::get()?;
-        let new_lamports_required = rent.minimum_balance(required_size);
-        let current_lamports = ticket_account.lamports();
-
-        if new_lamports_required > current_lamports {
-            let lamports_to_add = new_lamports_required - current_lamports;
-            invoke_signed(
-                &system_instruction::transfer(
-                    buyer_account.key,
-                    ticket_account.key,
-                    lamports_to_add,
-                ),
-                &[
-                    buyer_account.clone(),
-                    ticket_account.clone(),
-                    system_program.clone(),
-                ],
-                &[&[TICKET_SEED, buyer_account.key.as_ref(), &[ticket_bump_seed]]],
-            )?;
-            msg!(
-                "Transferred {} lamports to the TicketAccount for rent-exemption.",
-                lamports_to_add
-            );
-        }
-        ticket_account.realloc(required_size, false)?;
-        msg!("Reallocated TicketAccount to new size: {}", required_size);
-    }
-
-    // Serialize updated TicketAccount
-    ticket_account_data.serialize(&mut *ticket_account.data.borrow_mut())?;
-    msg!("Updated TicketAccount serialized successfully.");
-
-    msg!(
-        "Ticket data of tickets added: {:?}",
-        ticket_account_data.tickets
+    let mut ticket_account_bytes = ticket_account.try_borrow_mut_data()?;
+    let ticket_account_data = TicketAccount::load_mut(&mut ticket_account_bytes)?;
+    ticket_account_data.add_ticket_lot(ticket)?;
+    debug_msg!(
+        "Ticket account holds {} tickets in {} lots.",
+        u64::from(ticket_account_data.ticket_total),
+        ticket_account_data.lots().count()
     );
+
+    HaprEvent::TicketsPurchased {
+        owner: *buyer_account.key,
+        number_of_tickets,
+        cost: total_cost,
+        vesting_period,
+        timestamp: deposit_time,
+    }
+    .emit();
     Ok(())
 }
 
@@ -761,202 +1496,673 @@ pub fn redeem_tickets(
     mut amount: u64,
     program_id: &Pubkey,
 ) -> ProgramResult {
-    msg!("Redeem tickets function called.");
+    debug_msg!("Redeem tickets function called.");
 
     let accounts_iter = &mut accounts.iter();
     let ticket_account = next_account_info(accounts_iter)?; // Ticket account
     let owner_account = next_account_info(accounts_iter)?; // User's main account
     let mint_account = next_account_info(accounts_iter)?; // Token mint account
     let owner_token_account = next_account_info(accounts_iter)?; // User's token account
+    let treasury_token_account = next_account_info(accounts_iter)?; // Treasury's token account
     let treasury_pda_account = next_account_info(accounts_iter)?; // Treasury PDA
     let token_program = next_account_info(accounts_iter)?; // Token program
-    msg!("Checkpoint: Accounts Loaded!");
+    let _system_program = next_account_info(accounts_iter)?; // Unused now that ticket accounts have a fixed size
+    debug_msg!("Checkpoint: Accounts Loaded!");
+
+    haprvalidate::assert_signer(owner_account, "Ticket owner")?;
+    haprvalidate::assert_token_program(token_program)?;
+    haprvalidate::assert_program_account(
+        ticket_account,
+        &[TICKET_SEED, owner_account.key.as_ref()],
+        program_id,
+        HaprError::InvalidTicketPda,
+        "Ticket account",
+    )?;
+    haprvalidate::assert_writable(ticket_account, "Ticket account")?;
+    haprvalidate::assert_hapr_token_account(
+        owner_token_account,
+        owner_account.key,
+        program_id,
+        "Owner token account",
+    )?;
+    haprvalidate::assert_treasury_token_account(treasury_token_account, program_id)?;
 
     // Verify PDA authority
     let (treasury_pda, bump_seed) = Pubkey::find_program_address(&[TREASURY_SEED], program_id);
     if *treasury_pda_account.key != treasury_pda {
         msg!("Error: Invalid PDA authority");
-        return Err(ProgramError::IncorrectProgramId);
-    }
-    msg!("PDA authority verified successfully.");
-
-    // Load the ticket account data
-    let mut ticket_account_data = match TicketAccount::try_from_slice(&ticket_account.data.borrow())
-    {
-        Ok(data) => data,
-        Err(e) => {
-            msg!("Error deserializing TicketAccount: {:?}", e);
-            return Err(ProgramError::InvalidAccountData);
-        }
-    };
-
-    msg!("Checkpoint: Ticket Account Data Loaded");
-    if ticket_account.data_len() == 0 {
-        msg!("Error: TicketAccount data is uninitialized.");
-        return Err(ProgramError::UninitializedAccount);
+        return Err(HaprError::InvalidTreasuryPda.into());
     }
+    debug_msg!("PDA authority verified successfully.");
 
-    // Verify ticket account ownership
-    if ticket_account_data.owner != *owner_account.key {
-        msg!("Unauthorized: Only the owner can redeem tickets.");
-        return Err(ProgramError::Custom(CustomError::UnauthorizedAccess as u32));
+    let (mint_pda, _mint_bump) = Pubkey::find_program_address(&[SEED], program_id);
+    if *mint_account.key != mint_pda {
+        msg!("Error: Mint PDA does not match derived PDA.");
+        return Err(HaprError::InvalidMintPda.into());
     }
-    msg!("Checkpoint: Ticket Owner verified");
-
-    // Sanity check: Ensure sufficient tickets are available
-    if ticket_account_data.ticket_total > 0 && amount > ticket_account_data.ticket_total {
-        msg!(
-            "WARNING: Requested {} tickets but only {} tickets are available. Attemping only available amount.",
-            amount,
-            ticket_account_data.ticket_total
-        );
-        amount = ticket_account_data.ticket_total; // Adjust amount
-    } else if ticket_account_data.ticket_total == 0 {
-        msg!("Error: You don't have any tickets.",);
-        return Err(ProgramError::Custom(
-            CustomError::InsufficientTickets as u32,
-        ));
-    };
-
-    msg!("Checkpoint: You have enough tickets to redeem!");
 
     // Use Clock to get the current time
     let clock = Clock::get()?;
     let current_time = clock.unix_timestamp;
+    let config = haprtreasury::load_config(treasury_pda_account, program_id)?;
 
     let mut remaining_amount = amount;
+    let mut total_principal = 0;
     let mut total_yield = 0;
-    let mut index = 0;
+    let tickets_left;
+    {
+        let mut ticket_account_bytes = ticket_account.try_borrow_mut_data()?;
+        let ticket_account_data = TicketAccount::load_mut(&mut ticket_account_bytes)?;
+        debug_msg!("Checkpoint: Ticket Account Data Loaded");
+
+        // Verify ticket account ownership
+        if ticket_account_data.owner != *owner_account.key {
+            msg!("Unauthorized: Only the owner can redeem tickets.");
+            return Err(HaprError::UnauthorizedAccess.into());
+        }
+        debug_msg!("Checkpoint: Ticket Owner verified");
 
-    // Process tickets in the array
-    while remaining_amount > 0 && index < ticket_account_data.tickets.len() {
-        let ticket = &mut ticket_account_data.tickets[index];
+        ticket_account_data.check_draw_lock(current_time)?;
 
-        // Check if the vesting period has elapsed
-        if current_time < ticket.deposit_time + ticket.vesting_period {
-            msg!(
-                "Ticket at index {} is still vesting. Deposit time: {}, Vesting period: {}.",
-                index,
-                ticket.deposit_time,
-                ticket.vesting_period
+        // Sanity check: Ensure sufficient tickets are available
+        let ticket_total = u64::from(ticket_account_data.ticket_total);
+        if ticket_total > 0 && amount > ticket_total {
+            debug_msg!(
+                "WARNING: Requested {} tickets but only {} tickets are available. Attemping only available amount.",
+                amount,
+                ticket_total
             );
-            index += 1;
-            continue;
-        }
-
-        let redeemable_tickets = std::cmp::min(ticket.number_of_tickets, remaining_amount);
+            amount = ticket_total; // Adjust amount
+            remaining_amount = amount;
+        } else if ticket_total == 0 {
+            msg!("Error: You don't have any tickets.",);
+            return Err(HaprError::InsufficientTickets.into());
+        };
 
-        // Calculate yield for this batch of tickets
-        let yield_amount = (redeemable_tickets as u64
-            * FIXED_TICKET_PRICE
-            * YIELD_INTEREST_RATE
-            * (current_time as u64 - ticket.deposit_time as u64)
-            / (365 * 24 * 60 * 60))
-            / 100;
-        total_yield += redeemable_tickets * FIXED_TICKET_PRICE + yield_amount;
+        debug_msg!("Checkpoint: You have enough tickets to redeem!");
+
+        // Process lots around the ring, oldest allocation first
+        let mut redeemed_lots = 0;
+        let slot_order: Vec<usize> = ticket_account_data.slot_order().collect();
+        for index in slot_order {
+            if remaining_amount == 0 {
+                break;
+            }
+
+            let slot = &mut ticket_account_data.slots[index];
+            if slot.is_free() {
+                continue;
+            }
+            let mut ticket = slot.ticket();
+
+            // Check if the vesting period has elapsed
+            if current_time < ticket.deposit_time + ticket.vesting_period {
+                debug_msg!(
+                    "Ticket at index {} is still vesting. Deposit time: {}, Vesting period: {}.",
+                    index,
+                    ticket.deposit_time,
+                    ticket.vesting_period
+                );
+                continue;
+            }
+
+            let redeemable_tickets = std::cmp::min(ticket.number_of_tickets, remaining_amount);
+
+            // Calculate yield for this batch of tickets
+            let yield_amount = calculate_ticket_yield(
+                redeemable_tickets,
+                ticket.deposit_time,
+                current_time,
+                config.ticket_yield_bps,
+            )?;
+            let principal = redeemable_tickets * FIXED_TICKET_PRICE;
+            let redeemed_amount = principal + yield_amount;
+            total_principal += principal;
+            total_yield += yield_amount;
+
+            // Update ticket state; an emptied slot is freed
+            ticket.number_of_tickets -= redeemable_tickets;
+            slot.set(&ticket);
+            remaining_amount -= redeemable_tickets;
+            redeemed_lots += 1;
+
+            ticket_account_data.record_receipt(TicketReceipt {
+                number_of_tickets: redeemable_tickets.into(),
+                deposit_time: ticket.deposit_time.into(),
+                vesting_period: ticket.vesting_period.into(),
+                redeemed_amount: redeemed_amount.into(),
+                redeemed_time: current_time.into(),
+                claimed: (ticket.number_of_tickets == 0).into(),
+            });
+        }
 
-        // Update ticket state
-        ticket.number_of_tickets -= redeemable_tickets;
-        remaining_amount -= redeemable_tickets;
+        if redeemed_lots == 0 {
+            msg!("Error: None of your tickets have finished vesting.");
+            return Err(HaprError::VestingNotComplete.into());
+        }
 
-        if ticket.number_of_tickets == 0 {
-            // Remove the ticket if fully redeemed
-            ticket_account_data.tickets.remove(index);
-        } else {
-            index += 1;
+        if remaining_amount > 0 {
+            msg!(
+                "Error: Insufficient vested tickets to redeem {} tickets.",
+                amount
+            );
+            return Err(HaprError::InsufficientVestedTickets.into());
         }
-    }
 
-    if remaining_amount > 0 {
-        msg!(
-            "Error: Insufficient vested tickets to redeem {} tickets.",
-            amount
-        );
-        return Err(ProgramError::Custom(
-            CustomError::InsufficientVestedTickets as u32,
-        ));
+        // Update the total ticket count in the account
+        tickets_left = ticket_total - amount;
+        ticket_account_data.ticket_total = tickets_left.into();
     }
 
-    // Update the total ticket count in the account
-    ticket_account_data.ticket_total -= amount;
-
-    // Mint the total yield to the owner's token account
-    let mint_instruction = mint_to(
-        &spl_token::id(),
-        mint_account.key,
-        owner_token_account.key,
-        treasury_pda_account.key,
-        &[],
-        total_yield,
+    // Return the principal from the treasury and mint the total yield to the owner's token account
+    haprlimits::record_outflow(
+        accounts,
+        treasury_pda_account,
+        Some(owner_account),
+        total_principal,
+        program_id,
     )?;
-    let seeds = &[TREASURY_SEED, &[bump_seed]];
-    invoke_signed(
-        &mint_instruction,
-        &[
-            mint_account.clone(),
-            owner_token_account.clone(),
-            treasury_pda_account.clone(),
-            token_program.clone(),
-        ],
-        &[&seeds[..]],
+    pay_ticket_redemption(
+        total_principal,
+        total_yield,
+        config.max_supply,
+        mint_account,
+        treasury_token_account,
+        owner_token_account,
+        treasury_pda_account,
+        token_program,
+        bump_seed,
     )?;
-    msg!(
-        "Yield of {} tokens minted to user's account successfully.",
+    debug_msg!(
+        "Redeemed {} tokens of principal and {} tokens of yield to user's account successfully.",
+        total_principal,
         total_yield
     );
+    HaprEvent::TicketsRedeemed {
+        owner: *owner_account.key,
+        number_of_tickets: amount,
+        principal: total_principal,
+        yield_amount: total_yield,
+        timestamp: current_time,
+    }
+    .emit();
 
-    // Serialize the updated ticket account data
-    let ticket_size = 8 + 8 + 8 + 1; // Size of each ticket
-    let new_size = 32 // Owner (Pubkey)
-        + 8               // ticket_total (u64)
-        + 4               // Vec metadata
-        + (ticket_account_data.tickets.len() * ticket_size); // TODO: Make this dynamic to match the size requirements in the purchase ticket function
+    debug_msg!(
+        "Tickets redeemed successfully. Remaining tickets: {}",
+        tickets_left
+    );
+    Ok(())
+}
 
-    //If reallocation is required, ensure sufficient space:
-    if ticket_account.data_len() != new_size {
-        ticket_account.realloc(new_size, true)?;
-    }
+// Transfer a ticket lot (or part of it) to another wallet's TicketAccount
+pub fn transfer_tickets(
+    accounts: &[AccountInfo],
+    amount: u64,
+    ticket_index: usize,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let owner_account = next_account_info(accounts_iter)?; // Current ticket owner (signer, pays rent)
+    let from_ticket_account = next_account_info(accounts_iter)?; // Owner's Ticket PDA
+    let recipient_account = next_account_info(accounts_iter)?; // Recipient's main account
+    let to_ticket_account = next_account_info(accounts_iter)?; // Recipient's Ticket PDA
+    let system_program = next_account_info(accounts_iter)?;
 
-    // Serialize the updated ticket account data
-    ticket_account_data.serialize(&mut &mut ticket_account.data.borrow_mut()[..])?;
+    haprvalidate::assert_signer(owner_account, "Ticket owner")?;
 
-    // // Zero out any leftover space to prevent stale data issues
-    if ticket_account.data_len() > new_size {
-        ticket_account.data.borrow_mut()[new_size..].fill(0); // Prevents stale data :D
+    if owner_account.key == recipient_account.key {
+        msg!("Error: Cannot transfer tickets to the same owner.");
+        return Err(HaprError::SelfTransfer.into());
     }
 
-    msg!(
-        "Serialized ticket account size: {} bytes",
-        ticket_account.data.borrow().len()
-    );
+    // Validate and load the sender's TicketAccount
+    haprvalidate::assert_program_account(
+        from_ticket_account,
+        &[TICKET_SEED, owner_account.key.as_ref()],
+        program_id,
+        HaprError::InvalidTicketPda,
+        "Sender ticket account",
+    )?;
+    haprvalidate::assert_writable(from_ticket_account, "Sender ticket account")?;
 
-    msg!(
-        "Tickets redeemed successfully. Remaining tickets: {}",
-        ticket_account_data.ticket_total
+    // Create the recipient's TicketAccount before either side is borrowed
+    ensure_ticket_account(
+        to_ticket_account,
+        recipient_account.key,
+        owner_account,
+        system_program,
+        program_id,
+    )?;
+
+    // Split the requested tickets off the lot
+    let transferred_ticket = {
+        let mut from_ticket_bytes = from_ticket_account.try_borrow_mut_data()?;
+        let from_ticket_data = TicketAccount::load_mut(&mut from_ticket_bytes)?;
+        if from_ticket_data.owner != *owner_account.key {
+            msg!("Unauthorized: Only the owner can transfer tickets.");
+            return Err(HaprError::UnauthorizedAccess.into());
+        }
+
+        from_ticket_data.check_draw_lock(Clock::get()?.unix_timestamp)?;
+        from_ticket_data.take_tickets(ticket_index, amount)?
+    };
+    let tickets_to_move = transferred_ticket.number_of_tickets;
+
+    let mut to_ticket_bytes = to_ticket_account.try_borrow_mut_data()?;
+    TicketAccount::load_mut(&mut to_ticket_bytes)?.add_ticket_lot(transferred_ticket)?;
+
+    debug_msg!(
+        "Transferred {} tickets from {} to {}.",
+        tickets_to_move,
+        owner_account.key,
+        recipient_account.key
     );
+    Ok(())
+}
+
+// Merge lots sharing a maturity day, freeing their slots
+pub fn compact_tickets(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let owner_account = next_account_info(accounts_iter)?; // Ticket owner
+    let ticket_account = next_account_info(accounts_iter)?; // Ticket PDA
+    let _system_program = next_account_info(accounts_iter)?; // Unused now that ticket accounts have a fixed size
+
+    haprvalidate::assert_signer(owner_account, "Ticket owner")?;
+    haprvalidate::assert_program_account(
+        ticket_account,
+        &[TICKET_SEED, owner_account.key.as_ref()],
+        program_id,
+        HaprError::InvalidTicketPda,
+        "Ticket account",
+    )?;
+    haprvalidate::assert_writable(ticket_account, "Ticket account")?;
 
-    msg!(
-        "Ticket data of tickets left: {:?}",
-        ticket_account_data.tickets
+    let mut ticket_account_bytes = ticket_account.try_borrow_mut_data()?;
+    let ticket_account_data = TicketAccount::load_mut(&mut ticket_account_bytes)?;
+    if ticket_account_data.owner != *owner_account.key {
+        msg!("Unauthorized: Only the owner can compact tickets.");
+        return Err(HaprError::UnauthorizedAccess.into());
+    }
+
+    let lots_before = ticket_account_data.lots().count();
+    ticket_account_data.compact();
+
+    debug_msg!(
+        "Compacted ticket lots from {} to {}.",
+        lots_before,
+        ticket_account_data.lots().count()
     );
     Ok(())
 }
 
-// Custom Errors for various states
-#[derive(Debug)]
-pub enum CustomError {
-    AlreadyStaking,
-    InsufficientFunds,
-    VestingNotComplete,
-    TicketAlreadyClaimed,
-    StakingPeriodNotComplete,
-    UnauthorizedAccess,
-    InsufficientTickets,
-    InsufficientVestedTickets,
+// Enable or disable the on-chain receipt history for redeemed tickets
+pub fn set_ticket_history(
+    accounts: &[AccountInfo],
+    keep_history: bool,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let owner_account = next_account_info(accounts_iter)?; // Ticket owner
+    let ticket_account = next_account_info(accounts_iter)?; // Ticket PDA
+    let _system_program = next_account_info(accounts_iter)?; // Unused now that ticket accounts have a fixed size
+
+    haprvalidate::assert_signer(owner_account, "Ticket owner")?;
+    haprvalidate::assert_program_account(
+        ticket_account,
+        &[TICKET_SEED, owner_account.key.as_ref()],
+        program_id,
+        HaprError::InvalidTicketPda,
+        "Ticket account",
+    )?;
+    haprvalidate::assert_writable(ticket_account, "Ticket account")?;
+
+    let mut ticket_account_bytes = ticket_account.try_borrow_mut_data()?;
+    let ticket_account_data = TicketAccount::load_mut(&mut ticket_account_bytes)?;
+    if ticket_account_data.owner != *owner_account.key {
+        msg!("Unauthorized: Only the owner can change ticket history.");
+        return Err(HaprError::UnauthorizedAccess.into());
+    }
+
+    ticket_account_data.keep_history = keep_history.into();
+    if !keep_history {
+        ticket_account_data.clear_receipts();
+    }
+
+    debug_msg!("Ticket receipt history enabled: {}", keep_history);
+    Ok(())
+}
+
+// Close the owner's ticket account once every ticket has been redeemed or moved out,
+// returning its rent to the owner
+pub fn close_ticket_account(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let owner_account = next_account_info(accounts_iter)?; // Ticket owner (receives the rent)
+    let ticket_account = next_account_info(accounts_iter)?; // Ticket PDA
+
+    haprvalidate::assert_signer(owner_account, "Ticket owner")?;
+    haprvalidate::assert_writable(owner_account, "Ticket owner")?;
+    haprvalidate::assert_program_account(
+        ticket_account,
+        &[TICKET_SEED, owner_account.key.as_ref()],
+        program_id,
+        HaprError::InvalidTicketPda,
+        "Ticket account",
+    )?;
+    haprvalidate::assert_writable(ticket_account, "Ticket account")?;
+
+    {
+        let ticket_account_bytes = ticket_account.try_borrow_data()?;
+        let ticket_account_data = TicketAccount::load(&ticket_account_bytes)?;
+        ticket_account_data.check_draw_lock(Clock::get()?.unix_timestamp)?;
+        let ticket_total = u64::from(ticket_account_data.ticket_total);
+        if ticket_total != 0 {
+            msg!(
+                "Error: Ticket account still holds {} tickets.",
+                ticket_total
+            );
+            return Err(HaprError::AccountNotEmpty.into());
+        }
+    }
+
+    haprstate::close_account(ticket_account, owner_account)
 }
 
-impl From<CustomError> for ProgramError {
-    fn from(e: CustomError) -> Self {
-        ProgramError::Custom(e as u32)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Run `handler` with `count` blank accounts of which none signs
+    fn call_unsigned(
+        count: usize,
+        handler: fn(&[AccountInfo], u64, &Pubkey) -> ProgramResult,
+    ) -> ProgramResult {
+        let program_id = Pubkey::new_unique();
+        let keys: Vec<Pubkey> = (0..count).map(|_| Pubkey::new_unique()).collect();
+        let mut lamports = vec![1_000_000u64; count];
+        let mut data = vec![Vec::<u8>::new(); count];
+        let accounts: Vec<AccountInfo> = keys
+            .iter()
+            .zip(lamports.iter_mut())
+            .zip(data.iter_mut())
+            .map(|((key, lamports), data)| {
+                AccountInfo::new(key, false, true, lamports, data, &program_id, false, 0)
+            })
+            .collect();
+        handler(&accounts, 1, &program_id)
+    }
+
+    #[test]
+    fn unstake_requires_the_withdraw_authority_signature() {
+        assert_eq!(
+            call_unsigned(6, unstake_tokens),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+    }
+
+    #[test]
+    fn stake_for_cannot_delegate_someone_elses_stake() {
+        let program_id = Pubkey::new_unique();
+        let (mint_pda, _mint_bump) = Pubkey::find_program_address(&[SEED], &program_id);
+        let token_program_id = spl_token::id();
+        let system_program_id = solana_program::system_program::id();
+        let funder = Pubkey::new_unique();
+        let victim = Pubkey::new_unique();
+        let (staker_pda, _staker_bump) =
+            Pubkey::find_program_address(&[victim.as_ref(), b"staker"], &program_id);
+        let keys = [
+            funder,
+            Pubkey::new_unique(), // Funder token account
+            Pubkey::new_unique(), // Treasury token account
+            victim,
+            staker_pda,
+            token_program_id,
+            system_program_id,
+            mint_pda,
+        ];
+        let mut lamports = [1_000_000u64; 8];
+        let mut data = vec![Vec::<u8>::new(); 8];
+        let accounts: Vec<AccountInfo> = keys
+            .iter()
+            .zip(lamports.iter_mut())
+            .zip(data.iter_mut())
+            .enumerate()
+            .map(|(index, ((key, lamports), data))| {
+                // Only the funder signs; the mint is owned by the token program
+                let owner = if index == 7 {
+                    &token_program_id
+                } else {
+                    &program_id
+                };
+                AccountInfo::new(key, index == 0, true, lamports, data, owner, false, 0)
+            })
+            .collect();
+
+        // The funder names themselves withdraw authority of the victim's stake record
+        assert_eq!(
+            stake_for(&accounts, 1, Some(funder), &program_id),
+            Err(HaprError::UnauthorizedAccess.into())
+        );
+    }
+
+    #[test]
+    fn mint_requires_the_treasury_admin() {
+        let program_id = Pubkey::new_unique();
+        let (treasury_pda, _treasury_bump) =
+            Pubkey::find_program_address(&[TREASURY_SEED], &program_id);
+        let (mint_pda, _mint_bump) = Pubkey::find_program_address(&[SEED], &program_id);
+        let keys = [
+            Pubkey::new_unique(), // Payer, not the admin
+            mint_pda,
+            Pubkey::new_unique(), // Recipient token account
+            treasury_pda,
+            solana_program::system_program::id(),
+            spl_token::id(),
+        ];
+        let mut lamports = [1_000_000u64; 6];
+        let mut data = vec![Vec::<u8>::new(); 6];
+        // Treasury with another admin
+        data[3] = vec![0; ACCOUNT_HEADER_SIZE];
+        haprstate::write_header(&mut data[3], AccountKind::Treasury);
+        data[3].extend([&[1][..], Pubkey::new_unique().as_ref(), &[0; 8]].concat());
+        let accounts: Vec<AccountInfo> = keys
+            .iter()
+            .zip(lamports.iter_mut())
+            .zip(data.iter_mut())
+            .enumerate()
+            .map(|(index, ((key, lamports), data))| {
+                AccountInfo::new(key, index == 0, true, lamports, data, &program_id, false, 0)
+            })
+            .collect();
+
+        assert_eq!(
+            mint_tokens(&accounts, 1, &program_id),
+            Err(HaprError::UnauthorizedAccess.into())
+        );
+    }
+
+    #[test]
+    fn burn_rejects_a_mint_other_than_hapr() {
+        let program_id = Pubkey::new_unique();
+        let (treasury_pda, _treasury_bump) =
+            Pubkey::find_program_address(&[TREASURY_SEED], &program_id);
+        let token_program_id = spl_token::id();
+        let keys = [
+            Pubkey::new_unique(), // Payer
+            Pubkey::new_unique(), // Burn account
+            Pubkey::new_unique(), // Some other mint
+            treasury_pda,
+            solana_program::system_program::id(),
+            token_program_id,
+            solana_program::sysvar::rent::id(),
+        ];
+        let mut lamports = [1_000_000u64; 7];
+        let mut data = vec![Vec::<u8>::new(); 7];
+        let accounts: Vec<AccountInfo> = keys
+            .iter()
+            .zip(lamports.iter_mut())
+            .zip(data.iter_mut())
+            .enumerate()
+            .map(|(index, ((key, lamports), data))| {
+                AccountInfo::new(
+                    key,
+                    index == 0,
+                    true,
+                    lamports,
+                    data,
+                    &token_program_id,
+                    false,
+                    0,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            burn_tokens(&accounts, 1, &program_id),
+            Err(HaprError::InvalidMintPda.into())
+        );
+    }
+
+    #[test]
+    fn redeem_requires_the_ticket_owner_signature() {
+        assert_eq!(
+            call_unsigned(8, redeem_tickets),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+    }
+
+    // A lot of `number_of_tickets` maturing on `day`
+    fn lot(number_of_tickets: u64, day: i64) -> Ticket {
+        Ticket {
+            number_of_tickets,
+            deposit_time: day * SECONDS_PER_DAY,
+            vesting_period: 0,
+            claimed: false,
+        }
+    }
+
+    fn receipt(redeemed_time: i64) -> TicketReceipt {
+        TicketReceipt {
+            number_of_tickets: 1.into(),
+            redeemed_time: redeemed_time.into(),
+            ..TicketReceipt::default()
+        }
+    }
+
+    #[test]
+    fn ticket_yield_does_not_overflow_for_large_lots() {
+        // 1000 tickets at 500 bps held two years: the u64 product would be ~3.2e19
+        assert_eq!(
+            calculate_ticket_yield(1_000, 0, 2 * 365 * SECONDS_PER_DAY, 500),
+            Ok(1_000 * FIXED_TICKET_PRICE * 500 * 2 / 10_000)
+        );
+        assert_eq!(
+            calculate_ticket_yield(u64::MAX, 0, 365 * SECONDS_PER_DAY, 10_000),
+            Err(ProgramError::ArithmeticOverflow)
+        );
+        assert_eq!(calculate_ticket_yield(10, 100, 50, 500), Ok(0));
+    }
+
+    #[test]
+    fn ticket_account_size_matches_the_layout() {
+        assert_eq!(
+            TICKET_ACCOUNT_SIZE,
+            ACCOUNT_HEADER_SIZE + std::mem::size_of::<TicketAccount>()
+        );
+    }
+
+    #[test]
+    fn lots_keep_their_slot_and_freed_slots_are_reused() {
+        let mut data = vec![0u8; TICKET_ACCOUNT_SIZE];
+        let account: &mut TicketAccount =
+            haprstate::init_pod(&mut data, AccountKind::TicketAccount).unwrap();
+        for day in 0..MAX_TICKET_LOTS as i64 {
+            account.add_ticket_lot(lot(10, day)).unwrap();
+        }
+        assert_eq!(
+            account.add_ticket_lot(lot(10, 1_000)),
+            Err(HaprError::TooManyTicketLots.into())
+        );
+
+        // Emptying slot 5 leaves slot 6 where it was and frees slot 5 for the next lot
+        assert_eq!(account.take_tickets(5, 0).unwrap().number_of_tickets, 10);
+        assert!(account.slots[5].is_free());
+        assert_eq!(u64::from(account.slots[6].number_of_tickets), 10);
+        assert_eq!(
+            account.take_tickets(5, 1).unwrap_err(),
+            HaprError::InvalidTicketIndex.into()
+        );
+        account.add_ticket_lot(lot(7, 1_000)).unwrap();
+        assert_eq!(u64::from(account.slots[5].number_of_tickets), 7);
+
+        // A lot maturing on a day already held merges instead of taking a slot
+        account.add_ticket_lot(lot(3, 6)).unwrap();
+        assert_eq!(u64::from(account.slots[6].number_of_tickets), 13);
+        assert_eq!(u64::from(account.ticket_total), 64 * 10 - 10 + 7 + 3);
+    }
+
+    #[test]
+    fn receipt_ring_keeps_the_newest_receipts_oldest_first() {
+        let mut data = vec![0u8; TICKET_ACCOUNT_SIZE];
+        let account: &mut TicketAccount =
+            haprstate::init_pod(&mut data, AccountKind::TicketAccount).unwrap();
+        account.record_receipt(receipt(0));
+        assert_eq!(account.receipts().count(), 0); // History is off by default
+
+        account.keep_history = true.into();
+        for redeemed_time in 1..=40 {
+            account.record_receipt(receipt(redeemed_time));
+        }
+        let times: Vec<i64> = account
+            .receipts()
+            .map(|receipt| receipt.redeemed_time.into())
+            .collect();
+        assert_eq!(times, (9..=40).collect::<Vec<i64>>());
+
+        account.clear_receipts();
+        assert_eq!(account.receipts().count(), 0);
+    }
+
+    #[test]
+    fn version_1_ticket_account_migrates_to_fixed_slots() {
+        let program_id = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut claimed_lot = lot(0, 1);
+        claimed_lot.claimed = true;
+        let legacy = LegacyTicketAccount {
+            owner,
+            tickets: vec![lot(4, 1), claimed_lot, lot(6, 2)],
+            ticket_total: 10,
+            keep_history: true,
+            receipts: vec![LegacyTicketReceipt {
+                number_of_tickets: 2,
+                deposit_time: 0,
+                vesting_period: 0,
+                redeemed_amount: 2 * FIXED_TICKET_PRICE,
+                redeemed_time: 50,
+                claimed: true,
+            }],
+            draw_locked_until: 99,
+        };
+        let mut legacy_data = Vec::new();
+        legacy.serialize(&mut legacy_data).unwrap();
+
+        let (address, body) = migrate_ticket_account(&legacy_data, &program_id).unwrap();
+        let (ticket_pda, _) =
+            Pubkey::find_program_address(&[TICKET_SEED, owner.as_ref()], &program_id);
+        assert_eq!(address, ticket_pda);
+
+        let account: &TicketAccount = bytemuck::from_bytes(&body);
+        assert_eq!(account.owner, owner);
+        assert_eq!(u64::from(account.ticket_total), 10);
+        assert_eq!(i64::from(account.draw_locked_until), 99);
+        assert!(bool::from(account.keep_history));
+        let lots: Vec<(usize, u64)> = account
+            .lots()
+            .map(|(index, ticket)| (index, ticket.number_of_tickets))
+            .collect();
+        assert_eq!(lots, vec![(0, 4), (1, 6)]);
+        let receipts: Vec<&TicketReceipt> = account.receipts().collect();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(i64::from(receipts[0].redeemed_time), 50);
     }
 }
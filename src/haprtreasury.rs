@@ -11,11 +11,26 @@ use solana_program::{
     sysvar::Sysvar,
 };
 
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::{Pod, Zeroable};
 use spl_associated_token_account::{
-    get_associated_token_address, instruction::create_associated_token_account_idempotent,
+    get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
 };
-use spl_token::instruction::initialize_mint;
-use spl_token::state::{Account, AccountState};
+use spl_pod::primitives::{PodBool, PodU64};
+use spl_token_2022::extension::{
+    interest_bearing_mint, metadata_pointer,
+    transfer_fee::instruction::initialize_transfer_fee_config, ExtensionType,
+};
+use spl_token_2022::instruction::initialize_mint;
+use spl_token_2022::state::Mint;
+use spl_token_metadata_interface::state::TokenMetadata;
+
+use crate::haprerror::HaprError;
+use crate::haprevent::HaprEvent;
+use crate::haprstate::{self, AccountKind, ACCOUNT_HEADER_SIZE};
+use crate::haprtoken::resize_account;
+use crate::haprvalidate;
 
 pub const SEED: &[u8] = b"mintthissuperhyperAPRtoken"; // Seed for PDA
 pub const TREASURY_AUTHORITY_SEED: &[u8] = b"treasurythissuperhyperAPRtoken";
@@ -24,9 +39,536 @@ const MINT_AUTHORITY_SEED: &[u8] = b"mint-authority-seed"; // Unique seed for mi
 const TREASURY_ACCOUNT_SIZE: usize = 41; // Initialization flag (1) + Admin Pubkey (32) + Balance (8)
 const MINT_ACCOUNT_SIZE: usize = 82; // Fixed size for SPL Token Mint
 
+// Token metadata stored in a Token-2022 HAPR mint
+const TOKEN_NAME: &str = "Hyper APR";
+const TOKEN_SYMBOL: &str = "HAPR";
+const TOKEN_URI: &str = "";
+
+pub const TREASURY_CONFIG_SIZE: usize = 8 * 8; // Total: 64 bytes, stored after the treasury header
+const FIRST_TREASURY_CONFIG_SIZE: usize = 8 * 7; // Before admin_timelock_delay
+
+pub const MAX_MULTISIG_SIGNERS: usize = 10;
+// Region offsets count from the end of the account header
+const MULTISIG_OFFSET: usize = TREASURY_ACCOUNT_SIZE + TREASURY_CONFIG_SIZE;
+const MULTISIG_SIZE: usize = 1 + 4 + 32 * MAX_MULTISIG_SIGNERS; // Total: 325 bytes, after the config
+const PAUSE_OFFSET: usize = MULTISIG_OFFSET + MULTISIG_SIZE;
+const PAUSE_STATE_SIZE: usize = 32 + 1; // Total: 33 bytes, after the signer set
+const OUTFLOW_OFFSET: usize = PAUSE_OFFSET + PAUSE_STATE_SIZE;
+const OUTFLOW_LIMITS_SIZE: usize = 8 * 5; // Total: 40 bytes, after the pause state
+const SIGNER_SET_VERSION_OFFSET: usize = OUTFLOW_OFFSET + OUTFLOW_LIMITS_SIZE;
+const SIGNER_SET_VERSION_SIZE: usize = 8; // Total: 8 bytes, after the outflow limits
+const ACCRUED_YIELD_OFFSET: usize = SIGNER_SET_VERSION_OFFSET + SIGNER_SET_VERSION_SIZE;
+const ACCRUED_YIELD_SIZE: usize = 8; // Total: 8 bytes, after the signer set version
+
+// Start of the treasury account, viewed in place
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct TreasuryHeader {
+    pub is_initialized: PodBool,
+    pub admin: Pubkey,
+    pub balance: PodU64,
+}
+
+// Protocol parameters changed through governance. Treasuries initialized before the config
+// existed are only 41 bytes long and use the defaults until the first executed proposal.
+// Fields appended later read as zero from shorter configs.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Pod, Zeroable)]
+pub struct TreasuryConfig {
+    pub staking_apr_bps: u64,  // Annual staking reward rate
+    pub ticket_yield_bps: u64, // Annual yield paid on ticket principal
+    pub max_supply: u64,       // Cap enforced on every mint
+    pub quorum_votes: u64,     // Minimum vote weight cast for a proposal to pass
+    pub threshold_bps: u64,    // Share of the vote weight that must be in favour
+    pub voting_period: i64,    // Seconds a proposal is open for votes
+    pub timelock_delay: i64,   // Seconds between the end of voting and execution
+    pub admin_timelock_delay: i64, // Seconds admin actions wait in the queue (0 disables it)
+}
+
+impl Default for TreasuryConfig {
+    fn default() -> Self {
+        TreasuryConfig {
+            staking_apr_bps: 500,
+            ticket_yield_bps: 500,
+            max_supply: 1_000_000_000, // 1 billion max supply
+            quorum_votes: 1_000_000,
+            threshold_bps: 5_000,
+            voting_period: 3 * 24 * 60 * 60,
+            timelock_delay: 2 * 24 * 60 * 60,
+            admin_timelock_delay: 0,
+        }
+    }
+}
+
+// Optional M-of-N signer set that replaces the single admin key. A threshold of 0
+// (or a treasury too short to hold one) means the admin key is in charge.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Multisig {
+    pub threshold: u8,
+    pub signers: Vec<Pubkey>,
+}
+
+impl Multisig {
+    pub fn is_valid(&self) -> bool {
+        let mut distinct_signers = self.signers.clone();
+        distinct_signers.sort();
+        distinct_signers.dedup();
+        self.threshold > 0
+            && self.threshold as usize <= self.signers.len()
+            && self.signers.len() <= MAX_MULTISIG_SIGNERS
+            && distinct_signers.len() == self.signers.len()
+    }
+
+    // Distinct members of the signer set that signed, `admin_account` first
+    pub fn approvals<'a>(
+        &self,
+        admin_account: &AccountInfo<'a>,
+        co_signers: &[AccountInfo<'a>],
+    ) -> Vec<Pubkey> {
+        let mut approvals: Vec<Pubkey> = Vec::new();
+        for account in std::iter::once(admin_account).chain(co_signers) {
+            if account.is_signer
+                && self.signers.contains(account.key)
+                && !approvals.contains(account.key)
+            {
+                approvals.push(*account.key);
+            }
+        }
+        approvals
+    }
+}
+
+// Circuit breakers. Treasuries too short to hold them have nothing paused and no guardian.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Pod, Zeroable)]
+pub struct PauseState {
+    pub guardian: Pubkey, // May pause but not unpause; default pubkey when unset
+    pub paused: u8,       // PAUSE_* bits of the disabled instruction groups
+}
+
+// Caps on tokens leaving the treasury token account. A limit of 0 (or a treasury too short
+// to hold them) means no cap.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Pod, Zeroable)]
+pub struct OutflowLimits {
+    pub window_length: i64,      // Seconds per window, windows aligned to the unix epoch
+    pub window_limit: u64,       // Max tokens out of the treasury per window
+    pub wallet_daily_limit: u64, // Max tokens paid to one wallet per day
+    pub window_start: i64,       // Start of the window counted in `window_outflow`
+    pub window_outflow: u64,     // Tokens out of the treasury so far this window
+}
+
+// The treasury data after its account header. Treasuries created before account headers
+// must be migrated first.
+fn body(treasury_data: &[u8]) -> Result<&[u8], ProgramError> {
+    haprstate::account_body(treasury_data, AccountKind::Treasury)
+}
+
+fn body_mut(treasury_data: &mut [u8]) -> Result<&mut [u8], ProgramError> {
+    haprstate::account_body_mut(treasury_data, AccountKind::Treasury)
+}
+
+// Length of the treasury data after its account header
+fn body_len(treasury_account: &AccountInfo) -> usize {
+    treasury_account
+        .data_len()
+        .saturating_sub(ACCOUNT_HEADER_SIZE)
+}
+
+// The treasury header, or None while the account is too short to hold one
+fn header(treasury_data: &[u8]) -> Option<&TreasuryHeader> {
+    treasury_data
+        .get(..TREASURY_ACCOUNT_SIZE)
+        .map(bytemuck::from_bytes)
+}
+
+fn header_mut(treasury_data: &mut [u8]) -> Option<&mut TreasuryHeader> {
+    treasury_data
+        .get_mut(..TREASURY_ACCOUNT_SIZE)
+        .map(bytemuck::from_bytes_mut)
+}
+
+// Copy out the `T` stored at `offset`, or None while the treasury is too short to hold it.
+// The regions after the header are not aligned for their fields.
+fn read_region<T: Pod>(treasury_data: &[u8], offset: usize) -> Option<T> {
+    treasury_data
+        .get(offset..offset + std::mem::size_of::<T>())
+        .map(bytemuck::pod_read_unaligned)
+}
+
+fn write_region<T: Pod>(treasury_data: &mut [u8], offset: usize, value: &T) -> ProgramResult {
+    treasury_data
+        .get_mut(offset..offset + std::mem::size_of::<T>())
+        .ok_or(ProgramError::AccountDataTooSmall)?
+        .copy_from_slice(bytemuck::bytes_of(value));
+    Ok(())
+}
+
+// Read the protocol config from the treasury account
+pub fn load_config(
+    treasury_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<TreasuryConfig, ProgramError> {
+    let (pda, _bump_seed) = Pubkey::find_program_address(&[TREASURY_AUTHORITY_SEED], program_id);
+    if *treasury_account.key != pda {
+        msg!("Error: Treasury PDA does not match derived PDA.");
+        return Err(HaprError::InvalidTreasuryPda.into());
+    }
+    if treasury_account.data_len() == 0 {
+        return Ok(TreasuryConfig::default());
+    }
+    if treasury_account.owner != program_id {
+        msg!("Error: Treasury account is not owned by the program.");
+        return Err(HaprError::InvalidAccountOwner.into());
+    }
+    let treasury_data = treasury_account.try_borrow_data()?;
+    let treasury_body = body(&treasury_data)?;
+    if treasury_body.len() < TREASURY_ACCOUNT_SIZE + FIRST_TREASURY_CONFIG_SIZE {
+        return Ok(TreasuryConfig::default());
+    }
+    let stored = &treasury_body[TREASURY_ACCOUNT_SIZE..];
+    let stored = &stored[..stored.len().min(TREASURY_CONFIG_SIZE)];
+    let mut config_data = [0u8; TREASURY_CONFIG_SIZE];
+    config_data[..stored.len()].copy_from_slice(stored);
+    Ok(bytemuck::pod_read_unaligned(&config_data))
+}
+
+// Write the protocol config into an initialized treasury account, growing legacy
+// treasuries at the payer's expense
+pub fn store_config<'a>(
+    treasury_account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    config: &TreasuryConfig,
+) -> ProgramResult {
+    if body_len(treasury_account) < TREASURY_ACCOUNT_SIZE + TREASURY_CONFIG_SIZE {
+        resize_account(
+            treasury_account,
+            payer,
+            system_program,
+            ACCOUNT_HEADER_SIZE + TREASURY_ACCOUNT_SIZE + TREASURY_CONFIG_SIZE,
+        )?;
+    }
+    let mut treasury_data = treasury_account.try_borrow_mut_data()?;
+    write_region(body_mut(&mut treasury_data)?, TREASURY_ACCOUNT_SIZE, config)
+}
+
+// Replace the admin key stored in the treasury header. The new admin also replaces any
+// signer set.
+pub fn set_admin(treasury_account: &AccountInfo, new_admin: &Pubkey) -> ProgramResult {
+    let mut treasury_data = treasury_account.try_borrow_mut_data()?;
+    let treasury_data = body_mut(&mut treasury_data)?;
+    let header = header_mut(treasury_data).ok_or(ProgramError::InvalidAccountData)?;
+    let old_admin = std::mem::replace(&mut header.admin, *new_admin);
+    if treasury_data.len() >= MULTISIG_OFFSET + MULTISIG_SIZE {
+        treasury_data[MULTISIG_OFFSET] = 0; // Zero threshold disables the signer set
+    }
+    HaprEvent::AdminChanged {
+        old_admin,
+        new_admin: *new_admin,
+    }
+    .emit();
+    Ok(())
+}
+
+// The treasury's signer set, if one is enabled
+pub fn load_multisig(treasury_account: &AccountInfo) -> Result<Option<Multisig>, ProgramError> {
+    let treasury_data = treasury_account.try_borrow_data()?;
+    let treasury_data = body(&treasury_data)?;
+    if treasury_data.len() < MULTISIG_OFFSET + MULTISIG_SIZE {
+        return Ok(None);
+    }
+    let multisig = Multisig::deserialize(&mut &treasury_data[MULTISIG_OFFSET..])?;
+    Ok((multisig.threshold > 0).then_some(multisig))
+}
+
+// Counts the signer sets stored so far. Approvals of queued admin actions only count
+// while the signer set they were given under is still in place.
+pub fn load_signer_set_version(treasury_account: &AccountInfo) -> Result<u64, ProgramError> {
+    let treasury_data = treasury_account.try_borrow_data()?;
+    Ok(read_region(body(&treasury_data)?, SIGNER_SET_VERSION_OFFSET).unwrap_or(0))
+}
+
+// Hand the treasury to a signer set, growing the account at the payer's expense, and
+// start a new signer set version. Clear the admin key with `set_admin` first.
+pub fn store_multisig<'a>(
+    treasury_account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    multisig: &Multisig,
+) -> ProgramResult {
+    let version = load_signer_set_version(treasury_account)?.wrapping_add(1);
+    grow_treasury(
+        treasury_account,
+        payer,
+        system_program,
+        SIGNER_SET_VERSION_OFFSET + SIGNER_SET_VERSION_SIZE,
+    )?;
+    let mut treasury_data = treasury_account.try_borrow_mut_data()?;
+    let treasury_data = body_mut(&mut treasury_data)?;
+    multisig.serialize(&mut &mut treasury_data[MULTISIG_OFFSET..])?;
+    write_region(treasury_data, SIGNER_SET_VERSION_OFFSET, &version)?;
+    HaprEvent::MultisigChanged {
+        threshold: multisig.threshold,
+        signers: multisig.signers.clone(),
+    }
+    .emit();
+    Ok(())
+}
+
+pub fn load_pause_state(treasury_account: &AccountInfo) -> Result<PauseState, ProgramError> {
+    let treasury_data = treasury_account.try_borrow_data()?;
+    Ok(read_region(body(&treasury_data)?, PAUSE_OFFSET).unwrap_or_default())
+}
+
+// Write the circuit breakers, growing the account at the payer's expense
+pub fn store_pause_state<'a>(
+    treasury_account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    pause_state: &PauseState,
+) -> ProgramResult {
+    grow_treasury(
+        treasury_account,
+        payer,
+        system_program,
+        PAUSE_OFFSET + PAUSE_STATE_SIZE,
+    )?;
+    let mut treasury_data = treasury_account.try_borrow_mut_data()?;
+    write_region(body_mut(&mut treasury_data)?, PAUSE_OFFSET, pause_state)
+}
+
+pub fn load_outflow_limits(
+    treasury_account: &AccountInfo,
+) -> Result<OutflowLimits, ProgramError> {
+    let treasury_data = treasury_account.try_borrow_data()?;
+    Ok(read_region(body(&treasury_data)?, OUTFLOW_OFFSET).unwrap_or_default())
+}
+
+// Write the outflow limits, growing the account at the payer's expense
+pub fn store_outflow_limits<'a>(
+    treasury_account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    limits: &OutflowLimits,
+) -> ProgramResult {
+    grow_treasury(
+        treasury_account,
+        payer,
+        system_program,
+        OUTFLOW_OFFSET + OUTFLOW_LIMITS_SIZE,
+    )?;
+    update_outflow_limits(treasury_account, limits)
+}
+
+// Write the outflow limits into a treasury that already holds them
+pub fn update_outflow_limits(
+    treasury_account: &AccountInfo,
+    limits: &OutflowLimits,
+) -> ProgramResult {
+    let mut treasury_data = treasury_account.try_borrow_mut_data()?;
+    write_region(body_mut(&mut treasury_data)?, OUTFLOW_OFFSET, limits)
+}
+
+// Protocol income held in the treasury token account beside depositors' principal (market
+// fees and withdrawn transfer fees) that is not yet set aside for a draw prize
+pub fn load_accrued_yield(treasury_account: &AccountInfo) -> Result<u64, ProgramError> {
+    let treasury_data = treasury_account.try_borrow_data()?;
+    Ok(read_region(body(&treasury_data)?, ACCRUED_YIELD_OFFSET).unwrap_or(0))
+}
+
+// Count `amount` of income that reached the treasury token account as accrued yield,
+// growing the account at the payer's expense
+pub fn credit_accrued_yield<'a>(
+    treasury_account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    let accrued_yield = load_accrued_yield(treasury_account)?
+        .checked_add(amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    grow_treasury(
+        treasury_account,
+        payer,
+        system_program,
+        ACCRUED_YIELD_OFFSET + ACCRUED_YIELD_SIZE,
+    )?;
+    let mut treasury_data = treasury_account.try_borrow_mut_data()?;
+    write_region(
+        body_mut(&mut treasury_data)?,
+        ACCRUED_YIELD_OFFSET,
+        &accrued_yield,
+    )
+}
+
+// Set `amount` of the accrued yield aside for a draw prize
+pub fn reserve_accrued_yield(treasury_account: &AccountInfo, amount: u64) -> ProgramResult {
+    let accrued_yield = load_accrued_yield(treasury_account)?;
+    if accrued_yield < amount {
+        msg!(
+            "Error: Treasury has accrued {} tokens of yield; {} are needed.",
+            accrued_yield,
+            amount
+        );
+        return Err(HaprError::InsufficientFunds.into());
+    }
+    let mut treasury_data = treasury_account.try_borrow_mut_data()?;
+    write_region(
+        body_mut(&mut treasury_data)?,
+        ACCRUED_YIELD_OFFSET,
+        &(accrued_yield - amount),
+    )
+}
+
+// Return a reserved prize that was not paid out to the accrued yield
+pub fn release_accrued_yield(treasury_account: &AccountInfo, amount: u64) -> ProgramResult {
+    let accrued_yield = load_accrued_yield(treasury_account)?
+        .checked_add(amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    let mut treasury_data = treasury_account.try_borrow_mut_data()?;
+    write_region(
+        body_mut(&mut treasury_data)?,
+        ACCRUED_YIELD_OFFSET,
+        &accrued_yield,
+    )
+}
+
+// Grow the treasury to `required_size`. Legacy treasuries store the default config first,
+// since the regions after it must not be read as a zeroed config.
+fn grow_treasury<'a>(
+    treasury_account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    required_size: usize,
+) -> ProgramResult {
+    if body_len(treasury_account) < TREASURY_ACCOUNT_SIZE + FIRST_TREASURY_CONFIG_SIZE {
+        store_config(
+            treasury_account,
+            payer,
+            system_program,
+            &TreasuryConfig::default(),
+        )?;
+    }
+    if body_len(treasury_account) < required_size {
+        resize_account(
+            treasury_account,
+            payer,
+            system_program,
+            ACCOUNT_HEADER_SIZE + required_size,
+        )?;
+    }
+    Ok(())
+}
+
+// Whether the treasury account has been initialized. Treasuries created before account
+// headers are rejected until migrated.
+pub fn is_initialized(treasury_account: &AccountInfo) -> Result<bool, ProgramError> {
+    let treasury_data = treasury_account.try_borrow_data()?;
+    if treasury_data.is_empty() {
+        return Ok(false);
+    }
+    Ok(header(body(&treasury_data)?).is_some_and(|header| bool::from(header.is_initialized)))
+}
+
+// PDA and current layout of a treasury written before account headers. Its regions keep
+// their offsets behind the new header.
+pub fn migrate_treasury(
+    data: &[u8],
+    program_id: &Pubkey,
+) -> Result<(Pubkey, Vec<u8>), ProgramError> {
+    if !header(data).is_some_and(|header| bool::from(header.is_initialized)) {
+        return Err(HaprError::TreasuryNotInitialized.into());
+    }
+    let (pda, _bump_seed) = Pubkey::find_program_address(&[TREASURY_AUTHORITY_SEED], program_id);
+    Ok((pda, data.to_vec()))
+}
+
+// The initialized treasury PDA among `accounts`, for instructions that take it as a
+// trailing account
+pub fn find_treasury<'a, 'b>(
+    accounts: &'b [AccountInfo<'a>],
+    program_id: &Pubkey,
+) -> Result<&'b AccountInfo<'a>, ProgramError> {
+    let (pda, _bump_seed) = Pubkey::find_program_address(&[TREASURY_AUTHORITY_SEED], program_id);
+    let Some(treasury_account) = accounts.iter().find(|account| *account.key == pda) else {
+        msg!("Error: Treasury PDA {} is missing from the accounts.", pda);
+        return Err(HaprError::MissingTreasuryAccount.into());
+    };
+    assert_treasury(treasury_account, program_id)?;
+    Ok(treasury_account)
+}
+
+// Verify that `treasury_account` is the initialized treasury PDA
+pub fn assert_treasury(treasury_account: &AccountInfo, program_id: &Pubkey) -> ProgramResult {
+    let (pda, _bump_seed) = Pubkey::find_program_address(&[TREASURY_AUTHORITY_SEED], program_id);
+    if *treasury_account.key != pda || treasury_account.owner != program_id {
+        msg!("Error: Treasury PDA does not match derived PDA.");
+        return Err(HaprError::InvalidTreasuryPda.into());
+    }
+    if !is_initialized(treasury_account)? {
+        msg!("Error: Treasury is not initialized.");
+        return Err(HaprError::TreasuryNotInitialized.into());
+    }
+    Ok(())
+}
+
+// Verify the treasury authority: the admin key's signature, or with a signer set at least
+// `threshold` distinct member signatures among `admin_account` and `co_signers`
+pub fn assert_admin<'a>(
+    treasury_account: &AccountInfo,
+    admin_account: &AccountInfo<'a>,
+    co_signers: &[AccountInfo<'a>],
+    program_id: &Pubkey,
+) -> ProgramResult {
+    if let Some(multisig) = assert_admin_member(treasury_account, admin_account, program_id)? {
+        let approvals = multisig.approvals(admin_account, co_signers);
+        if approvals.len() < multisig.threshold as usize {
+            msg!(
+                "Error: {} of {} required signers signed.",
+                approvals.len(),
+                multisig.threshold
+            );
+            return Err(HaprError::ThresholdNotReached.into());
+        }
+    }
+    Ok(())
+}
+
+// Verify that `admin_account` signed and is the admin, or a member of the signer set.
+// Returns the signer set when one is enabled.
+pub fn assert_admin_member(
+    treasury_account: &AccountInfo,
+    admin_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<Option<Multisig>, ProgramError> {
+    assert_treasury(treasury_account, program_id)?;
+
+    let treasury_data = treasury_account.try_borrow_data()?;
+    let multisig = load_multisig(treasury_account)?;
+    let is_authorized = match &multisig {
+        Some(multisig) => multisig.signers.contains(admin_account.key),
+        None => {
+            header(body(&treasury_data)?).is_some_and(|header| header.admin == *admin_account.key)
+        }
+    };
+    if !admin_account.is_signer || !is_authorized {
+        msg!("Unauthorized: Only the treasury admin can perform this action.");
+        return Err(HaprError::UnauthorizedAccess.into());
+    }
+    Ok(multisig)
+}
+
+// Create the treasury and the HAPR mint. The mint is created under the token program passed
+// in. Under Token-2022 it also gets a metadata pointer to its own token metadata, a transfer
+// fee of `transfer_fee_basis_points` capped at `maximum_fee`, and an interest-bearing rate
+// that governance keeps equal to the staking APR. The treasury PDA holds every authority
+// over them, so the fee and metadata only change through queued admin actions.
 pub fn initialize_treasury(
     accounts: &[AccountInfo],
     admin: &Pubkey,
+    transfer_fee_basis_points: u16,
+    maximum_fee: u64,
     program_id: &Pubkey,
 ) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
@@ -38,24 +580,26 @@ pub fn initialize_treasury(
     let sysvar_rent = next_account_info(accounts_iter)?;
     //let treasury_token_account = next_account_info(accounts_iter)?; // Treasury Token Account
 
+    haprvalidate::assert_signer(admin_account, "Treasury admin")?;
+    haprvalidate::assert_writable(treasury_account, "Treasury account")?;
+    haprvalidate::assert_writable(mint_account_info, "Mint account")?;
+    haprvalidate::assert_token_program(token_program)?;
+
     // Check if the treasury is already initialized
-    if treasury_account.try_borrow_data()?.len() >= TREASURY_ACCOUNT_SIZE {
-        let treasury_data = treasury_account.try_borrow_data()?;
-        if treasury_data[0] == 1 {
-            msg!("Treasury is already initialized.");
-            return Err(ProgramError::AccountAlreadyInitialized);
-        }
+    if is_initialized(treasury_account)? {
+        msg!("Treasury is already initialized.");
+        return Err(HaprError::AccountAlreadyExists.into());
     }
 
     // Create treasury account if it has insufficient space
-    if treasury_account.data_len() < TREASURY_ACCOUNT_SIZE {
+    if treasury_account.data_len() < ACCOUNT_HEADER_SIZE + TREASURY_ACCOUNT_SIZE {
         let rent = Rent::from_account_info(sysvar_rent)?;
-        let lamports_required = rent.minimum_balance(TREASURY_ACCOUNT_SIZE);
+        let lamports_required = rent.minimum_balance(ACCOUNT_HEADER_SIZE + TREASURY_ACCOUNT_SIZE);
 
         let (pda, bump_seed) = Pubkey::find_program_address(&[TREASURY_AUTHORITY_SEED], program_id);
         if *treasury_account.key != pda {
             msg!("Error: Treasury PDA does not match derived PDA.");
-            return Err(ProgramError::InvalidArgument);
+            return Err(HaprError::InvalidTreasuryPda.into());
         }
 
         let seeds = &[TREASURY_AUTHORITY_SEED, &[bump_seed]];
@@ -65,7 +609,7 @@ pub fn initialize_treasury(
                 admin,
                 treasury_account.key,
                 lamports_required,
-                TREASURY_ACCOUNT_SIZE as u64,
+                (ACCOUNT_HEADER_SIZE + TREASURY_ACCOUNT_SIZE) as u64,
                 program_id,
             ),
             &[
@@ -76,16 +620,27 @@ pub fn initialize_treasury(
             ],
             &[seeds],
         )?;
-        msg!("Treasury account created with correct size.");
+        debug_msg!("Treasury account created with correct size.");
     }
 
     // Initialize treasury data structure
-    let mut treasury_data = treasury_account.try_borrow_mut_data()?;
-    treasury_data[0] = 1; // Mark as initialized
-    treasury_data[1..33].copy_from_slice(admin.as_ref()); // Store admin Pubkey
-    treasury_data[33..41].copy_from_slice(&0u64.to_le_bytes()); // Initialize balance to zero
+    {
+        let mut treasury_data = treasury_account.try_borrow_mut_data()?;
+        haprstate::write_header(&mut treasury_data, AccountKind::Treasury);
+        *header_mut(body_mut(&mut treasury_data)?).ok_or(ProgramError::AccountDataTooSmall)? =
+            TreasuryHeader {
+                is_initialized: true.into(), // Mark as initialized
+                admin: *admin,               // Store admin Pubkey
+                balance: 0.into(),           // Initialize balance to zero
+            };
+    }
 
-    msg!("Treasury account initialized with admin and balance.");
+    debug_msg!("Treasury account initialized with admin and balance.");
+    HaprEvent::AdminChanged {
+        old_admin: Pubkey::default(),
+        new_admin: *admin,
+    }
+    .emit();
 
     // Derive Mint PDA
     let (mint_pda, mint_bump_seed) = Pubkey::find_program_address(&[SEED], program_id);
@@ -93,15 +648,41 @@ pub fn initialize_treasury(
     // Check mint account data length; if uninitialized, create and initialize it
     if mint_account_info.try_borrow_data()?.len() < MINT_ACCOUNT_SIZE {
         let rent = Rent::from_account_info(sysvar_rent)?;
-        let mint_size = MINT_ACCOUNT_SIZE;
+        let metadata = if *token_program.key == spl_token_2022::id() {
+            Some(TokenMetadata {
+                update_authority: Some(*treasury_account.key).try_into()?,
+                mint: mint_pda,
+                name: TOKEN_NAME.to_string(),
+                symbol: TOKEN_SYMBOL.to_string(),
+                uri: TOKEN_URI.to_string(),
+                additional_metadata: Vec::new(),
+            })
+        } else if transfer_fee_basis_points != 0 || maximum_fee != 0 {
+            msg!("Error: Transfer fees need a Token-2022 mint.");
+            return Err(ProgramError::InvalidInstructionData);
+        } else {
+            None
+        };
+        let (mint_size, metadata_size) = match &metadata {
+            Some(metadata) => (
+                ExtensionType::try_calculate_account_len::<Mint>(&[
+                    ExtensionType::MetadataPointer,
+                    ExtensionType::TransferFeeConfig,
+                    ExtensionType::InterestBearingConfig,
+                ])?,
+                metadata.tlv_size_of()?,
+            ),
+            None => (MINT_ACCOUNT_SIZE, 0),
+        };
 
+        // Token-2022 grows the mint when the metadata is written, so that rent is paid now
         invoke_signed(
             &system_instruction::create_account(
                 admin,
                 &mint_pda,
-                rent.minimum_balance(mint_size),
+                rent.minimum_balance(mint_size + metadata_size),
                 mint_size as u64,
-                &spl_token::id(),
+                token_program.key,
             ),
             &[
                 admin_account.clone(),
@@ -111,12 +692,47 @@ pub fn initialize_treasury(
             ],
             &[&[SEED, &[mint_bump_seed]]],
         )?;
-        msg!("Mint account created.");
+        debug_msg!("Mint account created.");
+
+        // Mint extensions are initialized before the mint itself
+        if metadata.is_some() {
+            let staking_apr_bps = load_config(treasury_account, program_id)?.staking_apr_bps;
+            let rate = i16::try_from(staking_apr_bps).map_err(|_| ProgramError::InvalidArgument)?;
+            for extension_instruction in [
+                metadata_pointer::instruction::initialize(
+                    token_program.key,
+                    &mint_pda,
+                    Some(*treasury_account.key),
+                    Some(mint_pda),
+                )?,
+                initialize_transfer_fee_config(
+                    token_program.key,
+                    &mint_pda,
+                    Some(treasury_account.key),
+                    Some(treasury_account.key),
+                    transfer_fee_basis_points,
+                    maximum_fee,
+                )?,
+                interest_bearing_mint::instruction::initialize(
+                    token_program.key,
+                    &mint_pda,
+                    Some(*treasury_account.key),
+                    rate,
+                )?,
+            ] {
+                invoke_signed(
+                    &extension_instruction,
+                    &[mint_account_info.clone(), token_program.clone()],
+                    &[&[SEED, &[mint_bump_seed]]],
+                )?;
+            }
+            debug_msg!("Mint extensions initialized.");
+        }
 
         // Initialize mint account with the token program
         invoke_signed(
             &initialize_mint(
-                &spl_token::id(),
+                token_program.key,
                 &mint_pda,
                 &treasury_account.key, // Treasury PDA as mint authority
                 None,                  // No freeze authority
@@ -129,9 +745,35 @@ pub fn initialize_treasury(
             ],
             &[&[SEED, &[mint_bump_seed]]],
         )?;
-        msg!("Mint account initialized.");
+        debug_msg!("Mint account initialized.");
+
+        // The treasury PDA signs as mint authority to write the token metadata, and becomes
+        // its update authority
+        if let Some(metadata) = metadata {
+            let (_treasury_pda, treasury_bump) =
+                Pubkey::find_program_address(&[TREASURY_AUTHORITY_SEED], program_id);
+            invoke_signed(
+                &spl_token_metadata_interface::instruction::initialize(
+                    token_program.key,
+                    &mint_pda,
+                    treasury_account.key,
+                    &mint_pda,
+                    treasury_account.key,
+                    metadata.name,
+                    metadata.symbol,
+                    metadata.uri,
+                ),
+                &[
+                    mint_account_info.clone(),
+                    treasury_account.clone(),
+                    token_program.clone(),
+                ],
+                &[&[TREASURY_AUTHORITY_SEED, &[treasury_bump]]],
+            )?;
+            debug_msg!("Mint token metadata initialized.");
+        }
     } else {
-        msg!("Mint account already initialized.");
+        debug_msg!("Mint account already initialized.");
     }
 
     // Step 3: Initialize the Treasury Token Account
@@ -165,19 +807,23 @@ pub fn create_treasury_ata(
     let treasury_token_account = next_account_info(accounts_iter)?; // Treasury Token Account'
     let associated_token_account = next_account_info(accounts_iter)?; // associated Token Account
 
+    haprvalidate::assert_signer(admin_account, "Payer")?;
+    haprvalidate::assert_token_program(token_program)?;
+
     let (pda, bump_seed) = Pubkey::find_program_address(&[TREASURY_AUTHORITY_SEED], program_id);
     // Derive Mint PDA
     let (mint_pda, mint_bump_seed) = Pubkey::find_program_address(&[SEED], program_id);
     let seeds = &[TREASURY_AUTHORITY_SEED, &[bump_seed]];
-    let ata_address = get_associated_token_address(&pda, &mint_pda);
-    msg!("Derived ATA address: {}", ata_address);
-    msg!("Treasury Token Account: {}", treasury_token_account.key);
+    let ata_address =
+        get_associated_token_address_with_program_id(&pda, &mint_pda, token_program.key);
+    debug_msg!("Derived ATA address: {}", ata_address);
+    debug_msg!("Treasury Token Account: {}", treasury_token_account.key);
     // Validate that the passed ATA matches the derived ATA
     if treasury_token_account.key != &ata_address {
         msg!("Error: Provided ATA address does not match derived address.");
-        return Err(ProgramError::InvalidArgument);
+        return Err(HaprError::InvalidTreasuryTokenAccount.into());
     }
-    msg!("Treasury Token Account not initialized. Proceeding with creation...");
+    debug_msg!("Treasury Token Account not initialized. Proceeding with creation...");
 
     // Create the associated token account creation instruction
     let create_ata_instruction = create_associated_token_account_idempotent(
@@ -203,7 +849,7 @@ pub fn create_treasury_ata(
         &[seeds], // Pass seeds for PDA authorization
     )?;
 
-    msg!("Treasury Token Account successfully created.");
+    debug_msg!("Treasury Token Account successfully created.");
     // msg!("Ensuring the Treasury ATA is owned by the Treasury PDA");
     // let set_authority_ix = spl_token::instruction::set_authority(
     //     token_program.key,                                   // SPL Token Program ID
@@ -265,7 +911,7 @@ pub fn deposit_tokens_internal(
     // Verify the treasury PDA authority
     if treasury_account.owner != treasury_pda {
         msg!("Unauthorized attempt to deposit into treasury.");
-        return Err(ProgramError::Custom(0)); // Unauthorized access error
+        return Err(HaprError::UnauthorizedAccess.into());
     }
 
     // Perform the deposit with the PDA signature
@@ -293,7 +939,7 @@ pub fn withdraw_tokens_internal(
     // Verify that the treasury PDA is the authorized signer
     if treasury_account.owner != treasury_pda {
         msg!("Unauthorized attempt to withdraw from treasury.");
-        return Err(ProgramError::Custom(1)); // Unauthorized access error
+        return Err(HaprError::UnauthorizedAccess.into());
     }
 
     // Execute withdrawal with PDA authority
@@ -376,3 +1022,126 @@ pub fn withdraw_tokens_internal(
 //         &[],
 //     )
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn treasury_regions_match_their_layouts() {
+        assert_eq!(std::mem::size_of::<TreasuryHeader>(), TREASURY_ACCOUNT_SIZE);
+        assert_eq!(std::mem::size_of::<TreasuryConfig>(), TREASURY_CONFIG_SIZE);
+        assert_eq!(std::mem::size_of::<PauseState>(), PAUSE_STATE_SIZE);
+        assert_eq!(std::mem::size_of::<OutflowLimits>(), OUTFLOW_LIMITS_SIZE);
+    }
+
+    #[test]
+    fn regions_past_the_end_of_a_short_treasury_read_as_absent() {
+        let limits = OutflowLimits {
+            window_length: 60,
+            window_limit: 1_000,
+            ..OutflowLimits::default()
+        };
+        let mut treasury_data = vec![0u8; OUTFLOW_OFFSET + OUTFLOW_LIMITS_SIZE];
+        write_region(&mut treasury_data, OUTFLOW_OFFSET, &limits).unwrap();
+        assert_eq!(read_region(&treasury_data, OUTFLOW_OFFSET), Some(limits));
+        assert_eq!(
+            read_region::<u64>(&treasury_data, SIGNER_SET_VERSION_OFFSET),
+            None
+        );
+        assert_eq!(
+            write_region(&mut treasury_data, SIGNER_SET_VERSION_OFFSET, &1u64),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+    }
+
+    #[test]
+    fn draw_prizes_are_reserved_from_accrued_yield() {
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut lamports = [1_000_000u64; 2];
+        let mut treasury_data = vec![0u8; ACCOUNT_HEADER_SIZE + ACCRUED_YIELD_OFFSET];
+        haprstate::write_header(&mut treasury_data, AccountKind::Treasury);
+        treasury_data.extend(0u64.to_le_bytes());
+        let mut other_data = Vec::new();
+        let [treasury_lamports, other_lamports] = &mut lamports;
+        let treasury_account = AccountInfo::new(
+            &key,
+            false,
+            true,
+            treasury_lamports,
+            &mut treasury_data,
+            &program_id,
+            false,
+            0,
+        );
+        let other_account = AccountInfo::new(
+            &key,
+            true,
+            true,
+            other_lamports,
+            &mut other_data,
+            &program_id,
+            false,
+            0,
+        );
+
+        assert_eq!(
+            reserve_accrued_yield(&treasury_account, 1),
+            Err(HaprError::InsufficientFunds.into())
+        );
+        credit_accrued_yield(&treasury_account, &other_account, &other_account, 100).unwrap();
+        reserve_accrued_yield(&treasury_account, 60).unwrap();
+        assert_eq!(load_accrued_yield(&treasury_account), Ok(40));
+        assert_eq!(
+            reserve_accrued_yield(&treasury_account, 41),
+            Err(HaprError::InsufficientFunds.into())
+        );
+        release_accrued_yield(&treasury_account, 60).unwrap();
+        assert_eq!(load_accrued_yield(&treasury_account), Ok(100));
+    }
+
+    #[test]
+    fn legacy_treasury_keeps_its_regions_behind_the_header() {
+        let program_id = Pubkey::new_unique();
+        let (pda, _bump_seed) =
+            Pubkey::find_program_address(&[TREASURY_AUTHORITY_SEED], &program_id);
+        let admin = Pubkey::new_unique();
+        let mut legacy = vec![0u8; TREASURY_ACCOUNT_SIZE + TREASURY_CONFIG_SIZE];
+        *header_mut(&mut legacy).unwrap() = TreasuryHeader {
+            is_initialized: true.into(),
+            admin,
+            balance: 7.into(),
+        };
+
+        let mut lamports = 1_000_000;
+        let mut legacy_data = legacy.clone();
+        let legacy_account = AccountInfo::new(
+            &pda,
+            false,
+            true,
+            &mut lamports,
+            &mut legacy_data,
+            &program_id,
+            false,
+            0,
+        );
+        assert_eq!(
+            is_initialized(&legacy_account),
+            Err(HaprError::InvalidAccountDiscriminator.into())
+        );
+
+        let (address, migrated) = migrate_treasury(&legacy, &program_id).unwrap();
+        assert_eq!(address, pda);
+        let mut data = vec![0u8; ACCOUNT_HEADER_SIZE];
+        haprstate::write_header(&mut data, AccountKind::Treasury);
+        data.extend(migrated);
+        assert_eq!(header(body(&data).unwrap()).unwrap().admin, admin);
+        assert_eq!(
+            body(&data).unwrap()[TREASURY_ACCOUNT_SIZE..],
+            legacy[TREASURY_ACCOUNT_SIZE..]
+        );
+
+        assert!(migrate_treasury(&[0u8; TREASURY_ACCOUNT_SIZE], &program_id).is_err());
+    }
+}
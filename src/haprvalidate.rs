@@ -0,0 +1,416 @@
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, msg, program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_2022::state::Account;
+
+use crate::haprerror::HaprError;
+use crate::haprspl;
+
+const SEED: &[u8] = b"mintthissuperhyperAPRtoken"; // Seed for the HAPR mint PDA
+const TREASURY_SEED: &[u8] = b"treasurythissuperhyperAPRtoken";
+
+// Account constraints shared by the instruction handlers. Each check logs the role of the
+// account it rejected ("Ticket owner", "Staker account", ...) before returning its error.
+
+pub fn assert_signer(account: &AccountInfo, role: &str) -> ProgramResult {
+    if !account.is_signer {
+        msg!("Error: {} {} must sign.", role, account.key);
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    Ok(())
+}
+
+pub fn assert_writable(account: &AccountInfo, role: &str) -> ProgramResult {
+    if !account.is_writable {
+        msg!("Error: {} {} must be writable.", role, account.key);
+        return Err(HaprError::AccountNotWritable.into());
+    }
+    Ok(())
+}
+
+pub fn assert_owned_by(account: &AccountInfo, owner: &Pubkey, role: &str) -> ProgramResult {
+    if *account.owner != *owner {
+        msg!("Error: {} is owned by {}, expected {}.", role, account.owner, owner);
+        return Err(HaprError::InvalidAccountOwner.into());
+    }
+    Ok(())
+}
+
+// Check that `account` is the PDA derived from `seeds` and return its bump
+pub fn assert_pda(
+    account: &AccountInfo,
+    seeds: &[&[u8]],
+    program_id: &Pubkey,
+    error: HaprError,
+    role: &str,
+) -> Result<u8, ProgramError> {
+    let (pda, bump_seed) = Pubkey::find_program_address(seeds, program_id);
+    if *account.key != pda {
+        msg!("Error: {} {} does not match derived PDA {}.", role, account.key, pda);
+        return Err(error.into());
+    }
+    Ok(bump_seed)
+}
+
+// A program-owned state account at the PDA derived from `seeds`
+pub fn assert_program_account(
+    account: &AccountInfo,
+    seeds: &[&[u8]],
+    program_id: &Pubkey,
+    error: HaprError,
+    role: &str,
+) -> Result<u8, ProgramError> {
+    let bump_seed = assert_pda(account, seeds, program_id, error, role)?;
+    assert_owned_by(account, program_id, role)?;
+    Ok(bump_seed)
+}
+
+// An SPL Token or Token-2022 account holding `mint`, optionally owned by `owner`
+pub fn assert_token_account(
+    account: &AccountInfo,
+    mint: &Pubkey,
+    owner: Option<&Pubkey>,
+    role: &str,
+) -> Result<Account, ProgramError> {
+    if !haprspl::is_token_program(account.owner) {
+        msg!("Error: {} is not owned by a token program.", role);
+        return Err(HaprError::IncorrectTokenProgram.into());
+    }
+    let token_account = haprspl::unpack_token_account(account)?;
+    if token_account.mint != *mint {
+        msg!("Error: {} holds mint {}, expected {}.", role, token_account.mint, mint);
+        return Err(HaprError::InvalidTokenAccount.into());
+    }
+    if let Some(owner) = owner {
+        if token_account.owner != *owner {
+            msg!("Error: {} belongs to {}, expected {}.", role, token_account.owner, owner);
+            return Err(HaprError::InvalidTokenAccount.into());
+        }
+    }
+    Ok(token_account)
+}
+
+// A HAPR token account owned by `owner`
+pub fn assert_hapr_token_account(
+    account: &AccountInfo,
+    owner: &Pubkey,
+    program_id: &Pubkey,
+    role: &str,
+) -> Result<Account, ProgramError> {
+    let (mint_pda, _mint_bump) = Pubkey::find_program_address(&[SEED], program_id);
+    assert_token_account(account, &mint_pda, Some(owner), role)
+}
+
+// The treasury PDA's associated token account for the HAPR mint, under the token program
+// that owns it
+pub fn assert_treasury_token_account(
+    account: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<Account, ProgramError> {
+    let (mint_pda, _mint_bump) = Pubkey::find_program_address(&[SEED], program_id);
+    let (treasury_pda, _treasury_bump) =
+        Pubkey::find_program_address(&[TREASURY_SEED], program_id);
+    if *account.key
+        != get_associated_token_address_with_program_id(&treasury_pda, &mint_pda, account.owner)
+    {
+        msg!("Error: Treasury token account is not the treasury ATA.");
+        return Err(HaprError::InvalidTreasuryTokenAccount.into());
+    }
+    assert_token_account(account, &mint_pda, Some(&treasury_pda), "Treasury token account")
+}
+
+pub fn assert_token_program(account: &AccountInfo) -> ProgramResult {
+    if !haprspl::is_token_program(account.key) {
+        msg!(
+            "Error: Expected the SPL Token or Token-2022 program, got {}.",
+            account.key
+        );
+        return Err(HaprError::IncorrectTokenProgram.into());
+    }
+    Ok(())
+}
+
+pub fn assert_system_program(account: &AccountInfo) -> ProgramResult {
+    if !solana_program::system_program::check_id(account.key) {
+        msg!("Error: Expected the system program, got {}.", account.key);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::{program_option::COption, program_pack::Pack};
+    use spl_token_2022::state::AccountState;
+
+    struct TestAccount {
+        key: Pubkey,
+        owner: Pubkey,
+        lamports: u64,
+        data: Vec<u8>,
+    }
+
+    impl TestAccount {
+        fn new(key: Pubkey, owner: Pubkey, data: Vec<u8>) -> Self {
+            TestAccount {
+                key,
+                owner,
+                lamports: 1_000_000,
+                data,
+            }
+        }
+
+        // An initialized SPL Token account at `key`
+        fn token_account(key: Pubkey, mint: Pubkey, owner: Pubkey) -> Self {
+            let mut data = vec![0; Account::LEN];
+            Account {
+                mint,
+                owner,
+                amount: 100,
+                delegate: COption::None,
+                state: AccountState::Initialized,
+                is_native: COption::None,
+                delegated_amount: 0,
+                close_authority: COption::None,
+            }
+            .pack_into_slice(&mut data);
+            TestAccount::new(key, spl_token::id(), data)
+        }
+
+        fn info(&mut self, is_signer: bool, is_writable: bool) -> AccountInfo<'_> {
+            AccountInfo::new(
+                &self.key,
+                is_signer,
+                is_writable,
+                &mut self.lamports,
+                &mut self.data,
+                &self.owner,
+                false,
+                0,
+            )
+        }
+    }
+
+    fn hapr_mint(program_id: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[SEED], program_id).0
+    }
+
+    fn treasury_pda(program_id: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[TREASURY_SEED], program_id).0
+    }
+
+    #[test]
+    fn signer_is_required() {
+        let mut account = TestAccount::new(Pubkey::new_unique(), Pubkey::new_unique(), vec![]);
+        assert_eq!(
+            assert_signer(&account.info(false, true), "Owner"),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        assert_eq!(assert_signer(&account.info(true, false), "Owner"), Ok(()));
+    }
+
+    #[test]
+    fn writable_is_required() {
+        let mut account = TestAccount::new(Pubkey::new_unique(), Pubkey::new_unique(), vec![]);
+        assert_eq!(
+            assert_writable(&account.info(true, false), "Staker account"),
+            Err(HaprError::AccountNotWritable.into())
+        );
+        assert_eq!(
+            assert_writable(&account.info(false, true), "Staker account"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn wrong_owner_is_rejected() {
+        let program_id = Pubkey::new_unique();
+        let mut account = TestAccount::new(Pubkey::new_unique(), Pubkey::new_unique(), vec![]);
+        assert_eq!(
+            assert_owned_by(&account.info(false, false), &program_id, "Staker account"),
+            Err(HaprError::InvalidAccountOwner.into())
+        );
+        account.owner = program_id;
+        assert_eq!(
+            assert_owned_by(&account.info(false, false), &program_id, "Staker account"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn wrong_pda_is_rejected() {
+        let program_id = Pubkey::new_unique();
+        let user = Pubkey::new_unique();
+        let seeds: &[&[u8]] = &[user.as_ref(), b"staker"];
+        let mut account = TestAccount::new(Pubkey::new_unique(), program_id, vec![]);
+        assert_eq!(
+            assert_pda(
+                &account.info(false, false),
+                seeds,
+                &program_id,
+                HaprError::InvalidStakerPda,
+                "Staker account"
+            ),
+            Err(HaprError::InvalidStakerPda.into())
+        );
+
+        let (pda, bump_seed) = Pubkey::find_program_address(seeds, &program_id);
+        account.key = pda;
+        assert_eq!(
+            assert_pda(
+                &account.info(false, false),
+                seeds,
+                &program_id,
+                HaprError::InvalidStakerPda,
+                "Staker account"
+            ),
+            Ok(bump_seed)
+        );
+    }
+
+    #[test]
+    fn program_account_must_be_owned_by_the_program() {
+        let program_id = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let seeds: &[&[u8]] = &[b"ticket_seed", owner.as_ref()];
+        let (pda, _bump_seed) = Pubkey::find_program_address(seeds, &program_id);
+        let mut account = TestAccount::new(pda, Pubkey::new_unique(), vec![]);
+        assert_eq!(
+            assert_program_account(
+                &account.info(false, true),
+                seeds,
+                &program_id,
+                HaprError::InvalidTicketPda,
+                "Ticket account"
+            ),
+            Err(HaprError::InvalidAccountOwner.into())
+        );
+    }
+
+    #[test]
+    fn token_account_must_hold_the_hapr_mint() {
+        let program_id = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut account =
+            TestAccount::token_account(Pubkey::new_unique(), Pubkey::new_unique(), owner);
+        assert_eq!(
+            assert_hapr_token_account(&account.info(false, true), &owner, &program_id, "Owner")
+                .map(|_| ()),
+            Err(HaprError::InvalidTokenAccount.into())
+        );
+
+        let mut account =
+            TestAccount::token_account(Pubkey::new_unique(), hapr_mint(&program_id), owner);
+        assert!(assert_hapr_token_account(
+            &account.info(false, true),
+            &owner,
+            &program_id,
+            "Owner"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn token_account_must_belong_to_the_owner() {
+        let program_id = Pubkey::new_unique();
+        let mut account = TestAccount::token_account(
+            Pubkey::new_unique(),
+            hapr_mint(&program_id),
+            Pubkey::new_unique(),
+        );
+        assert_eq!(
+            assert_hapr_token_account(
+                &account.info(false, true),
+                &Pubkey::new_unique(),
+                &program_id,
+                "Owner"
+            )
+            .map(|_| ()),
+            Err(HaprError::InvalidTokenAccount.into())
+        );
+    }
+
+    #[test]
+    fn token_account_must_be_owned_by_the_token_program() {
+        let program_id = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut account =
+            TestAccount::token_account(Pubkey::new_unique(), hapr_mint(&program_id), owner);
+        account.owner = Pubkey::new_unique();
+        assert_eq!(
+            assert_hapr_token_account(&account.info(false, true), &owner, &program_id, "Owner")
+                .map(|_| ()),
+            Err(HaprError::IncorrectTokenProgram.into())
+        );
+    }
+
+    #[test]
+    fn treasury_token_account_must_be_the_canonical_ata() {
+        let program_id = Pubkey::new_unique();
+        let mint = hapr_mint(&program_id);
+        let treasury = treasury_pda(&program_id);
+
+        // Right mint and owner, but not at the associated token address
+        let mut account = TestAccount::token_account(Pubkey::new_unique(), mint, treasury);
+        assert_eq!(
+            assert_treasury_token_account(&account.info(false, true), &program_id).map(|_| ()),
+            Err(HaprError::InvalidTreasuryTokenAccount.into())
+        );
+
+        let mut account = TestAccount::token_account(
+            get_associated_token_address_with_program_id(&treasury, &mint, &spl_token::id()),
+            mint,
+            treasury,
+        );
+        assert!(assert_treasury_token_account(&account.info(false, true), &program_id).is_ok());
+    }
+
+    #[test]
+    fn token_2022_accounts_are_accepted() {
+        let program_id = Pubkey::new_unique();
+        let mint = hapr_mint(&program_id);
+        let treasury = treasury_pda(&program_id);
+        let token_2022_ata =
+            get_associated_token_address_with_program_id(&treasury, &mint, &spl_token_2022::id());
+
+        let mut account = TestAccount::token_account(token_2022_ata, mint, treasury);
+        account.owner = spl_token_2022::id();
+        assert!(assert_treasury_token_account(&account.info(false, true), &program_id).is_ok());
+
+        // The classic ATA address does not match an account owned by Token-2022
+        let mut account = TestAccount::token_account(
+            get_associated_token_address_with_program_id(&treasury, &mint, &spl_token::id()),
+            mint,
+            treasury,
+        );
+        account.owner = spl_token_2022::id();
+        assert_eq!(
+            assert_treasury_token_account(&account.info(false, true), &program_id).map(|_| ()),
+            Err(HaprError::InvalidTreasuryTokenAccount.into())
+        );
+
+        let mut token_program =
+            TestAccount::new(spl_token_2022::id(), Pubkey::new_unique(), vec![]);
+        assert_eq!(
+            assert_token_program(&token_program.info(false, false)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn program_ids_are_checked() {
+        let mut account = TestAccount::new(Pubkey::new_unique(), Pubkey::new_unique(), vec![]);
+        assert_eq!(
+            assert_token_program(&account.info(false, false)),
+            Err(HaprError::IncorrectTokenProgram.into())
+        );
+        assert_eq!(
+            assert_system_program(&account.info(false, false)),
+            Err(ProgramError::IncorrectProgramId)
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::set_return_data,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use std::cell::Ref;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::haprerror::HaprError;
+use crate::haprtoken::{
+    calculate_staking_rewards, calculate_ticket_yield, Staker, TicketAccount, FIXED_TICKET_PRICE,
+    STAKER_ACCOUNT_SIZE,
+};
+use crate::haprlock;
+use crate::haprmarket;
+use crate::haprstate::{self, AccountKind};
+use crate::haprticketlot;
+use crate::haprtreasury;
+use crate::haprvalidate;
+
+const TICKET_SEED: &[u8] = b"ticket_seed";
+const STAKER_SEED: &[u8] = b"staker"; // [user, seed]
+
+const SECONDS_PER_YEAR: i64 = 365 * 24 * 60 * 60;
+
+// Returned by GetStakingRewards
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct StakingRewardsView {
+    pub amount_staked: u64,
+    pub pending_rewards: u64, // Reward paid if unstaking everything now
+    pub unlock_time: i64,
+    pub unlocked: bool,
+}
+
+// Returned by GetTicketBalances
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct TicketBalancesView {
+    pub vested_tickets: u64,
+    pub unvested_tickets: u64,
+    pub redeemable_principal: u64, // Paid from the treasury when redeeming every vested ticket now
+    pub redeemable_yield: u64,     // Minted when redeeming every vested ticket now
+    pub draw_locked_until: i64,
+}
+
+// Returned by GetTreasurySolvency
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct TreasurySolvencyView {
+    pub treasury_balance: u64,
+    pub liabilities: u64, // Staked and locked tokens, rewards and ticket principal owed
+    pub solvency_ratio_bps: u64, // u64::MAX when there are no liabilities
+}
+
+// Returned by GetCurrentApr
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct AprView {
+    pub staking_apr_bps: u64,
+    pub ticket_apr_bps: u64,
+}
+
+// Returned by GetVotingPower
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct VotingPowerView {
+    pub amount_locked: u64,
+    pub unlock_time: i64,
+    pub voting_power: u64, // Decays linearly to zero at unlock_time
+}
+
+// Pending staking rewards for a staker account, as unstake_tokens would pay them now
+pub fn get_staking_rewards(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let staker_account = next_account_info(accounts_iter)?; // Staker PDA
+    let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA (protocol config)
+    let ve_lock_account = accounts_iter.next(); // Optional: staker's VeLock PDA (reward boost)
+
+    let staker_data = load_staker(staker_account, program_id)?;
+    let ve_lock =
+        haprlock::load_owner_ve_lock(ve_lock_account, &staker_data.owner_pubkey, program_id)?;
+    let config = haprtreasury::load_config(treasury_account, program_id)?;
+    let current_time = Clock::get()?.unix_timestamp;
+    let amount_staked = u64::from(staker_data.amount_staked);
+    let last_staked_time = i64::from(staker_data.last_staked_time);
+    let unlock_time = last_staked_time + i64::from(staker_data.staking_duration);
+
+    let view = StakingRewardsView {
+        amount_staked,
+        pending_rewards: haprlock::boosted_reward(
+            calculate_staking_rewards(
+                amount_staked,
+                last_staked_time,
+                current_time,
+                config.staking_apr_bps,
+            ),
+            amount_staked,
+            ve_lock.as_ref(),
+            last_staked_time,
+            current_time,
+        ),
+        unlock_time,
+        unlocked: current_time >= unlock_time,
+    };
+    return_view(&view)
+}
+
+// Vested/unvested ticket counts and what redeem_tickets would pay out now
+pub fn get_ticket_balances(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let ticket_account = next_account_info(accounts_iter)?; // Ticket PDA
+    let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA (protocol config)
+
+    let ticket_account_data = load_ticket_account(ticket_account, program_id)?;
+    let config = haprtreasury::load_config(treasury_account, program_id)?;
+    let current_time = Clock::get()?.unix_timestamp;
+
+    let mut view = TicketBalancesView {
+        vested_tickets: 0,
+        unvested_tickets: 0,
+        redeemable_principal: 0,
+        redeemable_yield: 0,
+        draw_locked_until: ticket_account_data.draw_locked_until.into(),
+    };
+    for (_index, ticket) in ticket_account_data.lots() {
+        if current_time < ticket.deposit_time + ticket.vesting_period {
+            view.unvested_tickets += ticket.number_of_tickets;
+            continue;
+        }
+        view.vested_tickets += ticket.number_of_tickets;
+        view.redeemable_principal += ticket.number_of_tickets * FIXED_TICKET_PRICE;
+        view.redeemable_yield += calculate_ticket_yield(
+            ticket.number_of_tickets,
+            ticket.deposit_time,
+            current_time,
+            config.ticket_yield_bps,
+        )?;
+    }
+    return_view(&view)
+}
+
+// Treasury balance against the obligations of the staker, lock, ticket, ticket lot and
+// listing accounts passed after the treasury token account and treasury PDA. An account
+// passed more than once is only counted once.
+pub fn get_treasury_solvency(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let treasury_token_account = next_account_info(accounts_iter)?; // Treasury's token account
+    let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA (protocol config)
+
+    let config = haprtreasury::load_config(treasury_account, program_id)?;
+    let treasury_balance =
+        haprvalidate::assert_treasury_token_account(treasury_token_account, program_id)?.amount;
+
+    let current_time = Clock::get()?.unix_timestamp;
+    let mut liabilities: u64 = 0;
+    let mut counted: Vec<&Pubkey> = Vec::new();
+    for account in accounts_iter {
+        if counted.contains(&account.key) {
+            debug_msg!("Skipping {}, already counted.", account.key);
+            continue;
+        }
+        counted.push(account.key);
+        if *account.owner != *program_id {
+            msg!("Error: {} is not owned by the program.", account.key);
+            return Err(HaprError::InvalidAccountOwner.into());
+        }
+
+        // Staker, lock, lot and listing accounts sit at PDAs derived from their contents;
+        // anything else must be a TicketAccount
+        let owed = if let Some(ve_lock) = haprlock::ve_lock_at(account, program_id) {
+            Some(ve_lock.amount_locked)
+        } else if let Some(staker_data) = staker_at(account, program_id) {
+            let amount_staked = u64::from(staker_data.amount_staked);
+            amount_staked.checked_add(calculate_staking_rewards(
+                amount_staked,
+                staker_data.last_staked_time.into(),
+                current_time,
+                config.staking_apr_bps,
+            ))
+        } else if let Some(ticket_lot) = haprticketlot::ticket_lot_at(account, program_id) {
+            ticket_principal(ticket_lot.number_of_tickets, ticket_lot.claimed)
+        } else if let Some(listing) = haprmarket::listing_at(account, program_id) {
+            ticket_principal(listing.ticket.number_of_tickets, listing.ticket.claimed)
+        } else {
+            let ticket_account_data = load_ticket_account(account, program_id)?;
+            let principal = ticket_account_data
+                .lots()
+                .try_fold(0u64, |total, (_index, ticket)| {
+                    total.checked_add(ticket_principal(ticket.number_of_tickets, ticket.claimed)?)
+                });
+            principal
+        };
+        liabilities = owed
+            .and_then(|owed| liabilities.checked_add(owed))
+            .ok_or_else(|| {
+                msg!("Error: Liabilities overflow at {}.", account.key);
+                ProgramError::ArithmeticOverflow
+            })?;
+    }
+
+    let solvency_ratio_bps = if liabilities == 0 {
+        u64::MAX
+    } else {
+        u64::try_from(treasury_balance as u128 * 10_000 / liabilities as u128).unwrap_or(u64::MAX)
+    };
+    let view = TreasurySolvencyView {
+        treasury_balance,
+        liabilities,
+        solvency_ratio_bps,
+    };
+    return_view(&view)
+}
+
+// Current staking and ticket APR in basis points, derived from the reward math itself
+pub fn get_current_apr(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA (protocol config)
+
+    let config = haprtreasury::load_config(treasury_account, program_id)?;
+    let view = AprView {
+        staking_apr_bps: calculate_staking_rewards(
+            10_000,
+            0,
+            SECONDS_PER_YEAR,
+            config.staking_apr_bps,
+        ),
+        ticket_apr_bps: calculate_ticket_yield(1, 0, SECONDS_PER_YEAR, config.ticket_yield_bps)?
+            * 10_000
+            / FIXED_TICKET_PRICE,
+    };
+    return_view(&view)
+}
+
+// Current veHAPR voting power of a lock
+pub fn get_voting_power(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let ve_lock_account = next_account_info(accounts_iter)?; // VeLock PDA
+
+    let ve_lock = haprlock::load_ve_lock(ve_lock_account, program_id)?;
+    let current_time = Clock::get()?.unix_timestamp;
+    let view = VotingPowerView {
+        amount_locked: ve_lock.amount_locked,
+        unlock_time: ve_lock.unlock_time,
+        voting_power: ve_lock.voting_power(current_time),
+    };
+    return_view(&view)
+}
+
+// Principal owed on unclaimed tickets, None on overflow
+fn ticket_principal(number_of_tickets: u64, claimed: bool) -> Option<u64> {
+    if claimed {
+        return Some(0);
+    }
+    number_of_tickets.checked_mul(FIXED_TICKET_PRICE)
+}
+
+fn load_staker(staker_account: &AccountInfo, program_id: &Pubkey) -> Result<Staker, HaprError> {
+    if *staker_account.owner != *program_id {
+        msg!("Error: Staker account is not owned by the program.");
+        return Err(HaprError::InvalidAccountOwner);
+    }
+    staker_at(staker_account, program_id).ok_or_else(|| {
+        msg!("Error: Staker account does not match derived PDA");
+        HaprError::InvalidStakerPda
+    })
+}
+
+// The staker record held by an account, if it is a staker PDA
+fn staker_at(account: &AccountInfo, program_id: &Pubkey) -> Option<Staker> {
+    if account.data_len() != STAKER_ACCOUNT_SIZE
+        || !haprstate::has_header(&account.data.borrow(), AccountKind::Staker)
+    {
+        return None;
+    }
+    let staker_data = *Staker::load(&account.data.borrow()).ok()?;
+    let (staker_pda, _staker_bump) = Pubkey::find_program_address(
+        &[staker_data.owner_pubkey.as_ref(), STAKER_SEED],
+        program_id,
+    );
+    (*account.key == staker_pda).then_some(staker_data)
+}
+
+// Borrow a TicketAccount in place; it is too large to copy onto the stack
+fn load_ticket_account<'b>(
+    ticket_account: &'b AccountInfo,
+    program_id: &Pubkey,
+) -> Result<Ref<'b, TicketAccount>, HaprError> {
+    if *ticket_account.owner != *program_id {
+        msg!("Error: Ticket account is not owned by the program.");
+        return Err(HaprError::InvalidAccountOwner);
+    }
+    let ticket_account_data = ticket_account
+        .try_borrow_data()
+        .ok()
+        .and_then(|data| Ref::filter_map(data, |data| TicketAccount::load(data).ok()).ok())
+        .ok_or(HaprError::InvalidTicketPda)?;
+    let (ticket_pda, _ticket_bump) = Pubkey::find_program_address(
+        &[TICKET_SEED, ticket_account_data.owner.as_ref()],
+        program_id,
+    );
+    if *ticket_account.key != ticket_pda {
+        msg!("Error: Invalid Ticket PDA");
+        return Err(HaprError::InvalidTicketPda);
+    }
+    Ok(ticket_account_data)
+}
+
+fn return_view<T: BorshSerialize + std::fmt::Debug>(view: &T) -> ProgramResult {
+    let mut data = Vec::new();
+    view.serialize(&mut data)?;
+    set_return_data(&data);
+    debug_msg!("View returned: {:?}", view);
+    Ok(())
+}
@@ -1,25 +1,69 @@
 // Standard imports for Solana programs
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    entrypoint,
     entrypoint::ProgramResult,
     msg,
-    program_error::ProgramError,
+    program_error::{PrintProgramError, ProgramError},
     pubkey::Pubkey,
     sysvar::rent,
 };
 
-// Import the token and treasury modules
+// Diagnostic logging, compiled in only with the `debug-logs` feature (devnet builds).
+// Error messages and structured events are always logged.
+macro_rules! debug_msg {
+    ($($arg:tt)*) => {
+        if cfg!(feature = "debug-logs") {
+            solana_program::msg!($($arg)*);
+        }
+    };
+}
+
+// Import the CPI, error, event, governance, outflow limit, lock, pause, token program, account
+// state, token, ticket lot, timelock, draw, marketplace, treasury, account validation and view
+// modules
+pub mod cpi;
+pub mod haprdraw;
+pub mod haprerror;
+pub mod haprevent;
+pub mod haprgov;
+pub mod haprlimits;
+pub mod haprlock;
+pub mod haprmarket;
+pub mod haprpause;
+pub mod haprspl;
+pub mod haprstate;
+pub mod haprticketlot;
+pub mod haprtimelock;
 pub mod haprtoken;
 pub mod haprtreasury;
+pub mod haprvalidate;
+pub mod haprview;
 
-// Entrypoint macro to specify the program entry function
-entrypoint!(process_instruction);
+use borsh::BorshDeserialize;
+use haprerror::HaprError;
+
+// Entrypoint macro to specify the program entry function. Programs calling us
+// through CPI build with `no-entrypoint` so the symbol is not defined twice.
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
 
 pub fn process_instruction(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
+) -> ProgramResult {
+    // Log the message for any HaprError before handing the error back to the runtime
+    if let Err(error) = process(program_id, accounts, instruction_data) {
+        error.print::<HaprError>();
+        return Err(error);
+    }
+    Ok(())
+}
+
+fn process(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
 ) -> ProgramResult {
     // Define the seed for the PDA
     const SEED: &[u8] = b"mintthissuperhyperAPRtoken";
@@ -30,46 +74,57 @@ pub fn process_instruction(
 
     // Extract amount from instruction data if present (bytes 1-8)
     let amount = if instruction_data.len() > 1 {
-        msg!("The amount is greater than one, let's see if it triggers an error");
+        debug_msg!("The amount is greater than one, let's see if it triggers an error");
         u64::from_le_bytes(
-            instruction_data[1..9]
+            instruction_data
+                .get(1..9)
+                .ok_or(ProgramError::InvalidInstructionData)?
                 .try_into()
                 .map_err(|_| ProgramError::InvalidInstructionData)?,
         )
     } else {
         0
     };
+    let instruction = *instruction_data
+        .first()
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    // Refuse paused instructions before dispatching them
+    if let Some(flag) = pause_flag(instruction) {
+        haprpause::assert_not_paused(accounts, flag, program_id)?;
+    }
+
     // Determine the instruction based on the first byte in `instruction_data`
-    match instruction_data[0] {
+    match instruction {
         // Match case for instruction `0`: Mint Tokens
         0 => {
             let accounts_iter = &mut accounts.iter();
 
             // Explicitly assign each account to a variable with clear names
-            let payer = next_account_info(accounts_iter)?; // Payer for fees
+            let payer = next_account_info(accounts_iter)?; // Treasury admin, pays fees
             let mint_account = next_account_info(accounts_iter)?; // Mint account (SPL Token Mint)
             let to_account = next_account_info(accounts_iter)?; // Recipient's token account
             let mint_authority = next_account_info(accounts_iter)?; // PDA with mint authority (treasuryPDA)
 
-            msg!("Initial Payer: {:?}", payer.key);
+            debug_msg!("Initial Payer: {:?}", payer.key);
 
-            msg!("Initial Mint Account: {:?}", mint_account.key);
+            debug_msg!("Initial Mint Account: {:?}", mint_account.key);
 
-            msg!("Initial Recipient Token Account: {:?}", to_account.key);
+            debug_msg!("Initial Recipient Token Account: {:?}", to_account.key);
 
-            msg!("Initial Mint Authority (PDA): {:?}", mint_authority.key);
+            debug_msg!("Initial Mint Authority (PDA): {:?}", mint_authority.key);
 
             if *mint_authority.key != treasury_pda {
                 msg!("Error: Mint authority PDA does not match derived PDA.");
-                return Err(ProgramError::IncorrectProgramId);
+                return Err(HaprError::InvalidTreasuryPda.into());
             }
 
             // Added accounts
             let system_program = next_account_info(accounts_iter)?;
             let token_program = next_account_info(accounts_iter)?;
 
-            // Construct account array with all required accounts
-            let mint_tokens_accounts = [
+            // Construct account array with all required accounts; other treasury signers
+            // follow when the treasury is run by a signer set
+            let mut mint_tokens_accounts = vec![
                 payer.clone(),
                 mint_account.clone(),
                 to_account.clone(),
@@ -77,8 +132,9 @@ pub fn process_instruction(
                 system_program.clone(),
                 token_program.clone(),
             ];
+            mint_tokens_accounts.extend(accounts_iter.cloned());
 
-            msg!("No issues so far, initiating the minting");
+            debug_msg!("No issues so far, initiating the minting");
             // Call `mint_tokens` with the explicitly structured accounts and amount
             haprtoken::mint_tokens(&mint_tokens_accounts, amount, program_id)
         }
@@ -96,25 +152,25 @@ pub fn process_instruction(
             let token_program = next_account_info(accounts_iter)?;
             let sysvar_rent = next_account_info(accounts_iter)?;
 
-            msg!("Burn Account Owner: {:?}", burn_account.owner);
-            msg!("Payer: {:?}", payer.key);
+            debug_msg!("Burn Account Owner: {:?}", burn_account.owner);
+            debug_msg!("Payer: {:?}", payer.key);
 
-            if burn_account.owner != &spl_token::id() {
+            if !haprspl::is_token_program(burn_account.owner) {
                 msg!("Error: Burn account owner does not match the expected payer.");
-                return Err(ProgramError::IllegalOwner);
+                return Err(HaprError::IncorrectTokenProgram.into());
             }
 
             // Derive the PDA and verify it matches the burn_authority
             if *burn_authority.key != treasury_pda {
                 msg!("Error: Invalid burn authority PDA");
-                return Err(ProgramError::IncorrectProgramId);
+                return Err(HaprError::InvalidTreasuryPda.into());
             }
 
             if sysvar_rent.key != &rent::id() {
                 msg!("Error: Rent sysvar account missing or incorrect");
-                return Err(ProgramError::InvalidAccountData);
+                return Err(HaprError::InvalidSysvar.into());
             }
-            msg!("Rent sysvar account verified.");
+            debug_msg!("Rent sysvar account verified.");
             haprtoken::burn_tokens(
                 &[
                     payer.clone(),
@@ -144,21 +200,32 @@ pub fn process_instruction(
             let sysvar_rent = next_account_info(accounts_iter)?;
             //let treasury_token_account = next_account_info(accounts_iter)?; // Treasury Token Account
 
+            // Token-2022 mints only: the amount is the maximum transfer fee, followed by the
+            // transfer fee in basis points (none if omitted)
+            let transfer_fee_basis_points = match instruction_data.get(9..11) {
+                Some(bytes) => u16::from_le_bytes(
+                    bytes
+                        .try_into()
+                        .map_err(|_| ProgramError::InvalidInstructionData)?,
+                ),
+                None => 0,
+            };
+
             // Derive PDAs for both Treasury and Mint
 
             let (mint_pda, _mint_bump) = Pubkey::find_program_address(&[SEED], program_id);
-            msg!("Attempting to initialize the treasury now...");
+            debug_msg!("Attempting to initialize the treasury now...");
 
             // Verify that the provided treasury account matches the derived Treasury PDA
             if *treasury_account.key != treasury_pda {
                 msg!("Error: Treasury PDA does not match derived PDA.");
-                return Err(ProgramError::InvalidArgument);
+                return Err(HaprError::InvalidTreasuryPda.into());
             }
 
             // Verify that the provided mint account matches the derived Mint PDA
             if *mint_account.key != mint_pda {
                 msg!("Error: Mint PDA does not match derived PDA.");
-                return Err(ProgramError::InvalidArgument);
+                return Err(HaprError::InvalidMintPda.into());
             }
 
             // Call `initialize_treasury` with the required accounts
@@ -173,6 +240,8 @@ pub fn process_instruction(
                     //treasury_token_account.clone(),
                 ],
                 &admin_account.key,
+                transfer_fee_basis_points,
+                amount,
                 program_id,
             )
         }
@@ -189,7 +258,7 @@ pub fn process_instruction(
             let associated_token_account = next_account_info(accounts_iter)?; // associated Token Account
                                                                               // Call `initialize_treasury` with the required accounts
 
-            msg!("Treasury Account: {}", treasury_account.key);
+            debug_msg!("Treasury Account: {}", treasury_account.key);
             haprtreasury::create_treasury_ata(
                 &[
                     treasury_account.clone(),
@@ -216,31 +285,31 @@ pub fn process_instruction(
             let system_program = next_account_info(accounts_iter)?;
             let token_program = next_account_info(accounts_iter)?;
 
-            msg!("Sender Token Account: {:?}", sender_account.key);
-            msg!("Recipient Token Account: {:?}", recipient_account.key);
-            msg!("Mint Account: {:?}", mint_account.key);
-            msg!("Sender: {:?}", sender_owner.key);
+            debug_msg!("Sender Token Account: {:?}", sender_account.key);
+            debug_msg!("Recipient Token Account: {:?}", recipient_account.key);
+            debug_msg!("Mint Account: {:?}", mint_account.key);
+            debug_msg!("Sender: {:?}", sender_owner.key);
 
             // Validate sender's token account ownership
-            if sender_account.owner != &spl_token::id() {
-                msg!("Error: Sender token account is not owned by the SPL Token Program");
-                return Err(ProgramError::IncorrectProgramId);
+            if sender_account.owner != token_program.key {
+                msg!("Error: Sender token account is not owned by the token program");
+                return Err(HaprError::IncorrectTokenProgram.into());
             }
-            msg!("Sender token account ownership verified.");
+            debug_msg!("Sender token account ownership verified.");
 
             // Validate mint account ownership
-            if mint_account.owner != &spl_token::id() {
-                msg!("Error: Mint account is not owned by the SPL Token Program");
-                return Err(ProgramError::IncorrectProgramId);
+            if mint_account.owner != token_program.key {
+                msg!("Error: Mint account is not owned by the token program");
+                return Err(HaprError::IncorrectTokenProgram.into());
             }
-            msg!("Mint account ownership verified.");
+            debug_msg!("Mint account ownership verified.");
 
             // Validate recipient's token account ownership
-            if recipient_account.owner != &spl_token::id() {
-                msg!("Error: Recipient token account is not owned by the SPL Token Program");
-                return Err(ProgramError::IncorrectProgramId);
+            if recipient_account.owner != token_program.key {
+                msg!("Error: Recipient token account is not owned by the token program");
+                return Err(HaprError::IncorrectTokenProgram.into());
             }
-            msg!("Recipient token account ownership verified.");
+            debug_msg!("Recipient token account ownership verified.");
 
             haprtoken::transfer_tokens(
                 &[
@@ -265,24 +334,27 @@ pub fn process_instruction(
             let system_program = next_account_info(accounts_iter)?;
             let staker_account = next_account_info(accounts_iter)?; // Staker's PDA
                                                                     // Log information for debugging
-            msg!("Staking: User Token Account: {:?}", user_token_account.key);
-            msg!(
+            debug_msg!("Staking: User Token Account: {:?}", user_token_account.key);
+            debug_msg!(
                 "Staking: Treasury Token Account: {:?}",
                 treasury_token_account.key
             );
-            msg!("Staking: Treasury PDA: {:?}", treasury_pda.key);
-            msg!("Staking: User: {:?}", user.key);
+            debug_msg!("Staking: Treasury PDA: {:?}", treasury_pda.key);
+            debug_msg!("Staking: User: {:?}", user.key);
+
+            let mut stake_accounts = vec![
+                user_token_account.clone(),
+                treasury_token_account.clone(),
+                treasury_pda.clone(),
+                user.clone(),
+                token_program.clone(),
+                system_program.clone(),
+                staker_account.clone(),
+            ];
+            stake_accounts.extend(accounts_iter.cloned()); // HAPR mint
 
             haprtoken::stake_tokens(
-                &[
-                    user_token_account.clone(),
-                    treasury_token_account.clone(),
-                    treasury_pda.clone(),
-                    user.clone(),
-                    token_program.clone(),
-                    system_program.clone(),
-                    staker_account.clone(),
-                ],
+                &stake_accounts,
                 amount, // Amount passed from instruction data
                 program_id,
             )
@@ -298,26 +370,31 @@ pub fn process_instruction(
             let token_program = next_account_info(accounts_iter)?; // SPL Token program
 
             // Log information for debugging
-            msg!(
+            debug_msg!(
                 "Unstaking: Treasury Token Account: {:?}",
                 treasury_token_account.key
             );
-            msg!(
+            debug_msg!(
                 "Unstaking: User Token Account: {:?}",
                 user_token_account.key
             );
-            msg!("Unstaking: Treasury PDA: {:?}", treasury_pda);
-            msg!("Unstaking: User: {:?}", user.key);
+            debug_msg!("Unstaking: Treasury PDA: {:?}", treasury_pda);
+            debug_msg!("Unstaking: User: {:?}", user.key);
+
+            let mut unstake_accounts = vec![
+                user.clone(),
+                staker_account.clone(),
+                user_token_account.clone(),
+                treasury_token_account.clone(),
+                authority_account.clone(),
+                token_program.clone(),
+            ];
+            // The HAPR mint, and optionally the VeLock PDA (reward boost), wallet outflow PDA and
+            // system program
+            unstake_accounts.extend(accounts_iter.cloned());
 
             haprtoken::unstake_tokens(
-                &[
-                    user.clone(),
-                    staker_account.clone(),
-                    user_token_account.clone(),
-                    treasury_token_account.clone(),
-                    authority_account.clone(),
-                    token_program.clone(),
-                ],
+                &unstake_accounts,
                 amount, // Amount passed from instruction data
                 program_id,
             )
@@ -333,40 +410,43 @@ pub fn process_instruction(
             let ticket_account = next_account_info(accounts_iter)?; // Ticket PDA
             let token_program = next_account_info(accounts_iter)?;
             let system_program = next_account_info(accounts_iter)?;
+            // A trailing treasury PDA is only read by the pause check; the HAPR mint follows it
             // Decode the instruction data
             let amount = u64::from_le_bytes(
-                instruction_data[1..9]
+                instruction_data
+                    .get(1..9)
+                    .ok_or(ProgramError::InvalidInstructionData)?
                     .try_into()
                     .map_err(|_| ProgramError::InvalidInstructionData)?,
             );
 
             let vesting_period = i64::from_le_bytes(
-                instruction_data[9..17]
+                instruction_data
+                    .get(9..17)
+                    .ok_or(ProgramError::InvalidInstructionData)?
                     .try_into()
                     .map_err(|_| ProgramError::InvalidInstructionData)?,
             );
 
-            msg!(
+            debug_msg!(
                 "Purchasing tickets with amount: {}, vesting period: {} seconds",
                 amount,
                 vesting_period
             );
 
+            let mut purchase_accounts = vec![
+                buyer_account.clone(),
+                buyer_token_account.clone(),
+                treasury_token_account.clone(),
+                //authority_account.clone(),
+                ticket_account.clone(),
+                token_program.clone(),
+                system_program.clone(),
+            ];
+            purchase_accounts.extend(accounts_iter.cloned()); // Treasury PDA and HAPR mint
+
             // Call the `purchase_ticket` function
-            haprtoken::purchase_tickets(
-                &[
-                    buyer_account.clone(),
-                    buyer_token_account.clone(),
-                    treasury_token_account.clone(),
-                    //authority_account.clone(),
-                    ticket_account.clone(),
-                    token_program.clone(),
-                    system_program.clone(),
-                ],
-                amount,
-                vesting_period,
-                program_id,
-            )
+            haprtoken::purchase_tickets(&purchase_accounts, amount, vesting_period, program_id)
         }
 
         8 => {
@@ -377,37 +457,947 @@ pub fn process_instruction(
             let owner_account = next_account_info(accounts_iter)?; // User's main account
             let mint_account = next_account_info(accounts_iter)?; // Token mint account
             let owner_token_account = next_account_info(accounts_iter)?; // User's token account
+            let treasury_token_account = next_account_info(accounts_iter)?; // Treasury's token account
             let treasury_pda = next_account_info(accounts_iter)?; // Treasury PDA
             let token_program = next_account_info(accounts_iter)?; // Token program
+            let system_program = next_account_info(accounts_iter)?; // System program
 
             // Deserialize instruction data to retrieve the `amount`
             // Decode the instruction data
             let amount = u64::from_le_bytes(
-                instruction_data[1..9]
+                instruction_data
+                    .get(1..9)
+                    .ok_or(ProgramError::InvalidInstructionData)?
                     .try_into()
                     .map_err(|_| ProgramError::InvalidInstructionData)?,
             );
 
-            msg!("Instruction: Redeem Tickets");
-            msg!("Amount to redeem: {}", amount);
+            debug_msg!("Instruction: Redeem Tickets");
+            debug_msg!("Amount to redeem: {}", amount);
+
+            let mut redeem_accounts = vec![
+                ticket_account.clone(),
+                owner_account.clone(),
+                mint_account.clone(),
+                owner_token_account.clone(),
+                treasury_token_account.clone(),
+                treasury_pda.clone(),
+                token_program.clone(),
+                system_program.clone(),
+            ];
+            redeem_accounts.extend(accounts_iter.cloned()); // Optional: wallet outflow PDA
 
             // Call the redeem_ticket function
-            haprtoken::redeem_tickets(
+            haprtoken::redeem_tickets(&redeem_accounts, amount, program_id)
+        }
+
+        10 => {
+            // Transfer a ticket lot to another wallet
+            let accounts_iter = &mut accounts.iter();
+
+            let owner_account = next_account_info(accounts_iter)?; // Current ticket owner
+            let from_ticket_account = next_account_info(accounts_iter)?; // Owner's Ticket PDA
+            let recipient_account = next_account_info(accounts_iter)?; // Recipient's main account
+            let to_ticket_account = next_account_info(accounts_iter)?; // Recipient's Ticket PDA
+            let system_program = next_account_info(accounts_iter)?;
+            // A trailing treasury PDA is only read by the pause check
+
+            // Decode the instruction data: number of tickets (0 = whole lot) and lot index
+            let ticket_index = u32::from_le_bytes(
+                instruction_data
+                    .get(9..13)
+                    .ok_or(ProgramError::InvalidInstructionData)?
+                    .try_into()
+                    .map_err(|_| ProgramError::InvalidInstructionData)?,
+            );
+
+            debug_msg!(
+                "Transferring {} tickets from lot {} to {}",
+                amount,
+                ticket_index,
+                recipient_account.key
+            );
+
+            haprtoken::transfer_tickets(
                 &[
+                    owner_account.clone(),
+                    from_ticket_account.clone(),
+                    recipient_account.clone(),
+                    to_ticket_account.clone(),
+                    system_program.clone(),
+                ],
+                amount,
+                ticket_index as usize,
+                program_id,
+            )
+        }
+
+        11 => {
+            // Purchase tickets as a tokenized lot (NFT / SFT held by the buyer)
+            let accounts_iter = &mut accounts.iter();
+
+            let buyer_account = next_account_info(accounts_iter)?; // Buyer's main account
+            let buyer_token_account = next_account_info(accounts_iter)?; // Buyer's token account
+            let treasury_token_account = next_account_info(accounts_iter)?; // Treasury's token account
+            let treasury_pda = next_account_info(accounts_iter)?; // Treasury PDA
+            let lot_mint_account = next_account_info(accounts_iter)?; // Ticket lot mint PDA
+            let ticket_lot_account = next_account_info(accounts_iter)?; // Ticket lot record PDA
+            let buyer_lot_token_account = next_account_info(accounts_iter)?; // Buyer's lot ATA
+            let token_program = next_account_info(accounts_iter)?;
+            let associated_token_program = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+            let sysvar_rent = next_account_info(accounts_iter)?;
+
+            // Decode the instruction data
+            let vesting_period = i64::from_le_bytes(
+                instruction_data
+                    .get(9..17)
+                    .ok_or(ProgramError::InvalidInstructionData)?
+                    .try_into()
+                    .map_err(|_| ProgramError::InvalidInstructionData)?,
+            );
+            let nonce = u64::from_le_bytes(
+                instruction_data
+                    .get(17..25)
+                    .ok_or(ProgramError::InvalidInstructionData)?
+                    .try_into()
+                    .map_err(|_| ProgramError::InvalidInstructionData)?,
+            );
+
+            debug_msg!(
+                "Purchasing ticket lot with amount: {}, vesting period: {} seconds, nonce: {}",
+                amount,
+                vesting_period,
+                nonce
+            );
+
+            let mut purchase_accounts = vec![
+                buyer_account.clone(),
+                buyer_token_account.clone(),
+                treasury_token_account.clone(),
+                treasury_pda.clone(),
+                lot_mint_account.clone(),
+                ticket_lot_account.clone(),
+                buyer_lot_token_account.clone(),
+                token_program.clone(),
+                associated_token_program.clone(),
+                system_program.clone(),
+                sysvar_rent.clone(),
+            ];
+            purchase_accounts.extend(accounts_iter.cloned()); // HAPR mint
+
+            haprticketlot::purchase_ticket_lot(
+                &purchase_accounts,
+                amount,
+                vesting_period,
+                nonce,
+                program_id,
+            )
+        }
+
+        12 => {
+            // Redeem tokenized tickets by burning them
+            let accounts_iter = &mut accounts.iter();
+
+            let holder_account = next_account_info(accounts_iter)?; // Holder's main account
+            let holder_lot_token_account = next_account_info(accounts_iter)?; // Holder's lot token account
+            let lot_mint_account = next_account_info(accounts_iter)?; // Ticket lot mint
+            let ticket_lot_account = next_account_info(accounts_iter)?; // Ticket lot record PDA
+            let mint_account = next_account_info(accounts_iter)?; // Token mint account
+            let holder_token_account = next_account_info(accounts_iter)?; // Holder's token account
+            let treasury_token_account = next_account_info(accounts_iter)?; // Treasury's token account
+            let treasury_pda = next_account_info(accounts_iter)?; // Treasury PDA
+            let token_program = next_account_info(accounts_iter)?; // Token program
+
+            debug_msg!("Instruction: Redeem Ticket Lot");
+            debug_msg!("Amount to redeem: {}", amount);
+
+            let mut redeem_accounts = vec![
+                holder_account.clone(),
+                holder_lot_token_account.clone(),
+                lot_mint_account.clone(),
+                ticket_lot_account.clone(),
+                mint_account.clone(),
+                holder_token_account.clone(),
+                treasury_token_account.clone(),
+                treasury_pda.clone(),
+                token_program.clone(),
+            ];
+            // Optional: wallet outflow PDA and system program
+            redeem_accounts.extend(accounts_iter.cloned());
+
+            haprticketlot::redeem_ticket_lot(&redeem_accounts, amount, program_id)
+        }
+
+        13 => {
+            // Compact ticket lots
+            let accounts_iter = &mut accounts.iter();
+
+            let owner_account = next_account_info(accounts_iter)?; // Ticket owner
+            let ticket_account = next_account_info(accounts_iter)?; // Ticket PDA
+            let system_program = next_account_info(accounts_iter)?;
+
+            debug_msg!("Instruction: Compact Tickets");
+
+            haprtoken::compact_tickets(
+                &[
+                    owner_account.clone(),
                     ticket_account.clone(),
+                    system_program.clone(),
+                ],
+                program_id,
+            )
+        }
+
+        14 => {
+            // Enable (amount != 0) or disable the ticket receipt history
+            let accounts_iter = &mut accounts.iter();
+
+            let owner_account = next_account_info(accounts_iter)?; // Ticket owner
+            let ticket_account = next_account_info(accounts_iter)?; // Ticket PDA
+            let system_program = next_account_info(accounts_iter)?;
+
+            debug_msg!("Instruction: Set Ticket History");
+
+            haprtoken::set_ticket_history(
+                &[
                     owner_account.clone(),
-                    mint_account.clone(),
-                    owner_token_account.clone(),
-                    treasury_pda.clone(),
-                    token_program.clone(),
+                    ticket_account.clone(),
+                    system_program.clone(),
                 ],
+                amount != 0,
+                program_id,
+            )
+        }
+
+        15 => {
+            // Start a prize-linked savings draw (admin only), reserving the prize from the
+            // treasury's accrued yield
+            let accounts_iter = &mut accounts.iter();
+
+            let admin_account = next_account_info(accounts_iter)?; // Treasury admin
+            let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA
+            let draw_state_account = next_account_info(accounts_iter)?; // Draw PDA
+            let system_program = next_account_info(accounts_iter)?;
+
+            // Decode the instruction data: prize amount, draw id and entry window
+            let draw_id = u64::from_le_bytes(
+                instruction_data
+                    .get(9..17)
+                    .ok_or(ProgramError::InvalidInstructionData)?
+                    .try_into()
+                    .map_err(|_| ProgramError::InvalidInstructionData)?,
+            );
+            let entry_duration = i64::from_le_bytes(
+                instruction_data
+                    .get(17..25)
+                    .ok_or(ProgramError::InvalidInstructionData)?
+                    .try_into()
+                    .map_err(|_| ProgramError::InvalidInstructionData)?,
+            );
+
+            debug_msg!(
+                "Starting draw {} with prize {} and entry window of {} seconds",
+                draw_id,
+                amount,
+                entry_duration
+            );
+
+            // Other treasury signers follow when the treasury is run by a signer set
+            let mut draw_accounts = vec![
+                admin_account.clone(),
+                treasury_account.clone(),
+                draw_state_account.clone(),
+                system_program.clone(),
+            ];
+            draw_accounts.extend(accounts_iter.cloned());
+
+            haprdraw::start_draw(
+                &draw_accounts,
                 amount,
+                draw_id,
+                entry_duration,
+                program_id,
+            )
+        }
+
+        16 => {
+            // Enter a draw with the caller's tickets
+            let accounts_iter = &mut accounts.iter();
+
+            let owner_account = next_account_info(accounts_iter)?; // Ticket owner
+            let ticket_account = next_account_info(accounts_iter)?; // Ticket PDA
+            let draw_state_account = next_account_info(accounts_iter)?; // Draw PDA
+            let draw_entry_account = next_account_info(accounts_iter)?; // Draw entry PDA
+            let system_program = next_account_info(accounts_iter)?;
+
+            debug_msg!("Instruction: Enter Draw");
+
+            haprdraw::enter_draw(
+                &[
+                    owner_account.clone(),
+                    ticket_account.clone(),
+                    draw_state_account.clone(),
+                    draw_entry_account.clone(),
+                    system_program.clone(),
+                ],
                 program_id,
+            )
+        }
+
+        17 => {
+            // Settle a draw: commit to a randomness slot, then pay the prize to the winner from
+            // the treasury token account. The commit step takes a trailing treasury PDA for the
+            // pause check, writable when the draw had no entries.
+            debug_msg!("Instruction: Settle Draw");
+            haprdraw::settle_draw(accounts, program_id)
+        }
+
+        18 => {
+            // List a ticket lot for sale
+            let accounts_iter = &mut accounts.iter();
+
+            let seller_account = next_account_info(accounts_iter)?; // Seller's main account
+            let seller_ticket_account = next_account_info(accounts_iter)?; // Seller's Ticket PDA
+            let listing_account = next_account_info(accounts_iter)?; // Listing PDA
+            let system_program = next_account_info(accounts_iter)?;
+
+            // Decode the instruction data: tickets (0 = whole lot), lot index, price, listing id
+            let ticket_index = u32::from_le_bytes(
+                instruction_data
+                    .get(9..13)
+                    .ok_or(ProgramError::InvalidInstructionData)?
+                    .try_into()
+                    .map_err(|_| ProgramError::InvalidInstructionData)?,
+            );
+            let price = u64::from_le_bytes(
+                instruction_data
+                    .get(13..21)
+                    .ok_or(ProgramError::InvalidInstructionData)?
+                    .try_into()
+                    .map_err(|_| ProgramError::InvalidInstructionData)?,
             );
-            Ok(())
+            let listing_id = u64::from_le_bytes(
+                instruction_data
+                    .get(21..29)
+                    .ok_or(ProgramError::InvalidInstructionData)?
+                    .try_into()
+                    .map_err(|_| ProgramError::InvalidInstructionData)?,
+            );
+
+            debug_msg!(
+                "Listing {} tickets from lot {} for {} tokens (listing {})",
+                amount,
+                ticket_index,
+                price,
+                listing_id
+            );
+
+            haprmarket::list_ticket_lot(
+                &[
+                    seller_account.clone(),
+                    seller_ticket_account.clone(),
+                    listing_account.clone(),
+                    system_program.clone(),
+                ],
+                amount,
+                ticket_index as usize,
+                price,
+                listing_id,
+                program_id,
+            )
+        }
+
+        19 => {
+            // Cancel a ticket listing
+            let accounts_iter = &mut accounts.iter();
+
+            let seller_account = next_account_info(accounts_iter)?; // Seller's main account
+            let seller_ticket_account = next_account_info(accounts_iter)?; // Seller's Ticket PDA
+            let listing_account = next_account_info(accounts_iter)?; // Listing PDA
+            let system_program = next_account_info(accounts_iter)?;
+
+            debug_msg!("Instruction: Cancel Listing");
+
+            haprmarket::cancel_listing(
+                &[
+                    seller_account.clone(),
+                    seller_ticket_account.clone(),
+                    listing_account.clone(),
+                    system_program.clone(),
+                ],
+                program_id,
+            )
+        }
+
+        20 => {
+            // Buy a listed ticket lot (amount = maximum accepted price)
+            let accounts_iter = &mut accounts.iter();
+
+            let buyer_account = next_account_info(accounts_iter)?; // Buyer's main account
+            let buyer_token_account = next_account_info(accounts_iter)?; // Buyer's token account
+            let seller_account = next_account_info(accounts_iter)?; // Seller's main account
+            let seller_token_account = next_account_info(accounts_iter)?; // Seller's token account
+            let treasury_token_account = next_account_info(accounts_iter)?; // Treasury's token account
+            let buyer_ticket_account = next_account_info(accounts_iter)?; // Buyer's Ticket PDA
+            let listing_account = next_account_info(accounts_iter)?; // Listing PDA
+            let token_program = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+            // A trailing treasury PDA, writable to record the fee as accrued yield; the HAPR
+            // mint follows it
+
+            debug_msg!("Instruction: Buy Listed Lot (max price {})", amount);
+
+            let mut buy_accounts = vec![
+                buyer_account.clone(),
+                buyer_token_account.clone(),
+                seller_account.clone(),
+                seller_token_account.clone(),
+                treasury_token_account.clone(),
+                buyer_ticket_account.clone(),
+                listing_account.clone(),
+                token_program.clone(),
+                system_program.clone(),
+            ];
+            buy_accounts.extend(accounts_iter.cloned()); // Treasury PDA and HAPR mint
+
+            haprmarket::buy_listed_lot(&buy_accounts, amount, program_id)
+        }
+
+        21 => {
+            // View: pending staking rewards, returned via set_return_data
+            let accounts_iter = &mut accounts.iter();
+
+            let staker_account = next_account_info(accounts_iter)?; // Staker PDA
+            let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA
+            let ve_lock_account = accounts_iter.next(); // Optional: VeLock PDA (reward boost)
+
+            debug_msg!("Instruction: Get Staking Rewards");
+
+            let mut view_accounts = vec![staker_account.clone(), treasury_account.clone()];
+            view_accounts.extend(ve_lock_account.cloned());
+            haprview::get_staking_rewards(&view_accounts, program_id)
+        }
+
+        22 => {
+            // View: vested/unvested tickets and the redeemable amount now
+            let accounts_iter = &mut accounts.iter();
+
+            let ticket_account = next_account_info(accounts_iter)?; // Ticket PDA
+            let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA
+
+            debug_msg!("Instruction: Get Ticket Balances");
+
+            haprview::get_ticket_balances(
+                &[ticket_account.clone(), treasury_account.clone()],
+                program_id,
+            )
+        }
+
+        23 => {
+            // View: treasury solvency. Accounts: treasury token account, treasury PDA, then
+            // any staker and ticket accounts whose obligations should be counted
+            debug_msg!("Instruction: Get Treasury Solvency");
+
+            haprview::get_treasury_solvency(accounts, program_id)
+        }
+
+        24 => {
+            // View: current staking and ticket APR in basis points
+            let accounts_iter = &mut accounts.iter();
+
+            let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA
+
+            debug_msg!("Instruction: Get Current APR");
+
+            haprview::get_current_apr(std::slice::from_ref(treasury_account), program_id)
+        }
+
+        25 => {
+            // Stake on behalf of a beneficiary
+            let accounts_iter = &mut accounts.iter();
+
+            let funder = next_account_info(accounts_iter)?; // Funder (pays tokens and rent)
+            let funder_token_account = next_account_info(accounts_iter)?; // Funder's token account
+            let treasury_token_account = next_account_info(accounts_iter)?; // Treasury's token account
+            let beneficiary = next_account_info(accounts_iter)?; // Stake owner, signs to change its authority
+            let staker_account = next_account_info(accounts_iter)?; // Beneficiary's staker PDA
+            let token_program = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+            // A trailing treasury PDA is only read by the pause check; the HAPR mint follows it
+
+            // Decode the optional withdraw authority (defaults to the beneficiary)
+            let withdraw_authority = match instruction_data.get(9..41) {
+                Some(bytes) => Some(
+                    Pubkey::try_from(bytes).map_err(|_| ProgramError::InvalidInstructionData)?,
+                ),
+                None => None,
+            };
+
+            debug_msg!(
+                "Staking {} tokens for {} (withdraw authority: {:?})",
+                amount,
+                beneficiary.key,
+                withdraw_authority
+            );
+
+            let mut stake_accounts = vec![
+                funder.clone(),
+                funder_token_account.clone(),
+                treasury_token_account.clone(),
+                beneficiary.clone(),
+                staker_account.clone(),
+                token_program.clone(),
+                system_program.clone(),
+            ];
+            stake_accounts.extend(accounts_iter.cloned()); // Treasury PDA and HAPR mint
+
+            haprtoken::stake_for(&stake_accounts, amount, withdraw_authority, program_id)
+        }
+
+        26 => {
+            // Propose a change to a protocol parameter
+            let accounts_iter = &mut accounts.iter();
+
+            let proposer = next_account_info(accounts_iter)?; // Proposer (pays proposal rent)
+            let proposer_staker_account = next_account_info(accounts_iter)?; // Proposer's staker PDA
+            let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA
+            let proposal_account = next_account_info(accounts_iter)?; // Proposal PDA
+            let system_program = next_account_info(accounts_iter)?;
+            let ve_lock_account = accounts_iter.next(); // Optional: proposer's VeLock PDA
+
+            // Decode the instruction data: new value, proposal id and parameter
+            let proposal_id = u64::from_le_bytes(
+                instruction_data
+                    .get(9..17)
+                    .ok_or(ProgramError::InvalidInstructionData)?
+                    .try_into()
+                    .map_err(|_| ProgramError::InvalidInstructionData)?,
+            );
+            let param = instruction_data
+                .get(17)
+                .and_then(|param| haprgov::ConfigParam::from_u8(*param))
+                .ok_or(ProgramError::InvalidInstructionData)?;
+
+            debug_msg!(
+                "Creating proposal {} to set {:?} to {}",
+                proposal_id,
+                param,
+                amount
+            );
+
+            let mut proposal_accounts = vec![
+                proposer.clone(),
+                proposer_staker_account.clone(),
+                treasury_account.clone(),
+                proposal_account.clone(),
+                system_program.clone(),
+            ];
+            proposal_accounts.extend(ve_lock_account.cloned());
+
+            haprgov::create_proposal(
+                &proposal_accounts,
+                amount,
+                proposal_id,
+                param,
+                program_id,
+            )
+        }
+
+        27 => {
+            // Vote on a proposal (amount != 0 votes in favour)
+            let accounts_iter = &mut accounts.iter();
+
+            let voter = next_account_info(accounts_iter)?; // Voter (pays vote record rent)
+            let voter_staker_account = next_account_info(accounts_iter)?; // Voter's staker PDA
+            let proposal_account = next_account_info(accounts_iter)?; // Proposal PDA
+            let vote_record_account = next_account_info(accounts_iter)?; // Vote record PDA
+            let system_program = next_account_info(accounts_iter)?;
+            let ve_lock_account = accounts_iter.next(); // Optional: voter's VeLock PDA
+
+            let support = amount != 0;
+            debug_msg!("Voting {} on proposal {}", support, proposal_account.key);
+
+            let mut vote_accounts = vec![
+                voter.clone(),
+                voter_staker_account.clone(),
+                proposal_account.clone(),
+                vote_record_account.clone(),
+                system_program.clone(),
+            ];
+            vote_accounts.extend(ve_lock_account.cloned());
+
+            haprgov::cast_vote(
+                &vote_accounts,
+                support,
+                program_id,
+            )
+        }
+
+        28 => {
+            // Execute a passed proposal after its timelock
+            let accounts_iter = &mut accounts.iter();
+
+            let executor = next_account_info(accounts_iter)?; // Executor (pays treasury realloc)
+            let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA
+            let proposal_account = next_account_info(accounts_iter)?; // Proposal PDA
+            let system_program = next_account_info(accounts_iter)?;
+
+            debug_msg!("Executing proposal {}", proposal_account.key);
+
+            let mut execute_accounts = vec![
+                executor.clone(),
+                treasury_account.clone(),
+                proposal_account.clone(),
+                system_program.clone(),
+            ];
+            // StakingAprBps: the HAPR mint and, when the mint uses it, the Token-2022 program
+            execute_accounts.extend(accounts_iter.cloned());
+
+            haprgov::execute_proposal(&execute_accounts, program_id)
+        }
+
+        29 => {
+            // Lock tokens for veHAPR voting power, or extend an existing lock
+            let accounts_iter = &mut accounts.iter();
+
+            let owner = next_account_info(accounts_iter)?; // Owner (pays lock rent)
+            let owner_token_account = next_account_info(accounts_iter)?; // Owner's token account
+            let treasury_token_account = next_account_info(accounts_iter)?; // Treasury's token account
+            let ve_lock_account = next_account_info(accounts_iter)?; // VeLock PDA
+            let token_program = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+            // A trailing treasury PDA is only read by the pause check; the HAPR mint follows it
+
+            // Decode the instruction data: amount and unlock time (0 keeps the current one)
+            let unlock_time = i64::from_le_bytes(
+                instruction_data
+                    .get(9..17)
+                    .ok_or(ProgramError::InvalidInstructionData)?
+                    .try_into()
+                    .map_err(|_| ProgramError::InvalidInstructionData)?,
+            );
+
+            debug_msg!("Locking {} tokens until {}", amount, unlock_time);
+
+            let mut lock_accounts = vec![
+                owner.clone(),
+                owner_token_account.clone(),
+                treasury_token_account.clone(),
+                ve_lock_account.clone(),
+                token_program.clone(),
+                system_program.clone(),
+            ];
+            lock_accounts.extend(accounts_iter.cloned()); // Treasury PDA and HAPR mint
+
+            haprlock::lock_tokens(&lock_accounts, amount, unlock_time, program_id)
+        }
+
+        30 => {
+            // Withdraw tokens from an expired lock
+            let accounts_iter = &mut accounts.iter();
+
+            let owner = next_account_info(accounts_iter)?; // Owner
+            let ve_lock_account = next_account_info(accounts_iter)?; // VeLock PDA
+            let owner_token_account = next_account_info(accounts_iter)?; // Owner's token account
+            let treasury_token_account = next_account_info(accounts_iter)?; // Treasury's token account
+            let authority_account = next_account_info(accounts_iter)?; // Treasury PDA
+            let token_program = next_account_info(accounts_iter)?;
+
+            debug_msg!("Withdrawing lock {}", ve_lock_account.key);
+
+            let mut withdraw_accounts = vec![
+                owner.clone(),
+                ve_lock_account.clone(),
+                owner_token_account.clone(),
+                treasury_token_account.clone(),
+                authority_account.clone(),
+                token_program.clone(),
+            ];
+            // The HAPR mint, and optionally the wallet outflow PDA and system program
+            withdraw_accounts.extend(accounts_iter.cloned());
+
+            haprlock::withdraw_lock(&withdraw_accounts, program_id)
+        }
+
+        31 => {
+            // View: decaying veHAPR voting power, returned via set_return_data
+            let accounts_iter = &mut accounts.iter();
+
+            let ve_lock_account = next_account_info(accounts_iter)?; // VeLock PDA
+
+            debug_msg!("Instruction: Get Voting Power");
+
+            haprview::get_voting_power(std::slice::from_ref(ve_lock_account), program_id)
+        }
+
+        32 => {
+            // Queue an admin action behind the admin timelock
+            let accounts_iter = &mut accounts.iter();
+
+            let admin_account = next_account_info(accounts_iter)?; // Treasury admin
+            let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA
+            let action_account = next_account_info(accounts_iter)?; // Admin action PDA
+            let system_program = next_account_info(accounts_iter)?;
+
+            // Decode the instruction data: action id and Borsh-encoded action
+            let action = haprtimelock::AdminAction::try_from_slice(
+                instruction_data
+                    .get(9..)
+                    .ok_or(ProgramError::InvalidInstructionData)?,
+            )
+            .map_err(|_| ProgramError::InvalidInstructionData)?;
+
+            debug_msg!("Queueing admin action {}: {:?}", amount, action);
+
+            // Other treasury signers follow when the treasury is run by a signer set
+            let mut queue_accounts = vec![
+                admin_account.clone(),
+                treasury_account.clone(),
+                action_account.clone(),
+                system_program.clone(),
+            ];
+            queue_accounts.extend(accounts_iter.cloned());
+
+            haprtimelock::queue_action(
+                &queue_accounts,
+                amount,
+                action,
+                program_id,
+            )
+        }
+
+        33 => {
+            // Execute a queued admin action after its eta
+            let accounts_iter = &mut accounts.iter();
+
+            let admin_account = next_account_info(accounts_iter)?; // Treasury admin
+            let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA
+            let action_account = next_account_info(accounts_iter)?; // Admin action PDA
+            let system_program = next_account_info(accounts_iter)?;
+
+            debug_msg!("Executing admin action {}", action_account.key);
+
+            // The remaining accounts are specific to the queued action
+            let mut action_accounts = vec![
+                admin_account.clone(),
+                treasury_account.clone(),
+                action_account.clone(),
+                system_program.clone(),
+            ];
+            action_accounts.extend(accounts_iter.cloned());
+
+            haprtimelock::execute_action(&action_accounts, program_id)
+        }
+
+        34 => {
+            // Cancel a queued admin action
+            let accounts_iter = &mut accounts.iter();
+
+            let admin_account = next_account_info(accounts_iter)?; // Treasury admin
+            let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA
+            let action_account = next_account_info(accounts_iter)?; // Admin action PDA
+
+            debug_msg!("Cancelling admin action {}", action_account.key);
+
+            // Other treasury signers follow when the treasury is run by a signer set
+            let mut cancel_accounts = vec![
+                admin_account.clone(),
+                treasury_account.clone(),
+                action_account.clone(),
+            ];
+            cancel_accounts.extend(accounts_iter.cloned());
+
+            haprtimelock::cancel_action(&cancel_accounts, program_id)
+        }
+
+        35 => {
+            // Approve a queued admin action as a treasury signer
+            let accounts_iter = &mut accounts.iter();
+
+            let signer_account = next_account_info(accounts_iter)?; // Treasury signer
+            let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA
+            let action_account = next_account_info(accounts_iter)?; // Admin action PDA
+
+            debug_msg!(
+                "Approving admin action {} as {}",
+                action_account.key,
+                signer_account.key
+            );
+
+            haprtimelock::approve_action(
+                &[
+                    signer_account.clone(),
+                    treasury_account.clone(),
+                    action_account.clone(),
+                ],
+                program_id,
+            )
+        }
+
+        36 => {
+            // Trip circuit breakers (pause guardian or admin)
+            let accounts_iter = &mut accounts.iter();
+
+            let signer_account = next_account_info(accounts_iter)?; // Pause guardian or treasury admin
+            let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA
+            let system_program = next_account_info(accounts_iter)?;
+
+            debug_msg!("Pausing flags {:#04x}", amount);
+
+            // Other treasury signers follow when the admin pauses with a signer set
+            let mut pause_accounts = vec![
+                signer_account.clone(),
+                treasury_account.clone(),
+                system_program.clone(),
+            ];
+            pause_accounts.extend(accounts_iter.cloned());
+
+            let flags = u8::try_from(amount).map_err(|_| ProgramError::InvalidInstructionData)?;
+            haprpause::pause(&pause_accounts, flags, program_id)
+        }
+
+        37 => {
+            // Reset circuit breakers (admin only)
+            let accounts_iter = &mut accounts.iter();
+
+            let admin_account = next_account_info(accounts_iter)?; // Treasury admin
+            let treasury_account = next_account_info(accounts_iter)?; // Treasury PDA
+            let system_program = next_account_info(accounts_iter)?;
+
+            debug_msg!("Unpausing flags {:#04x}", amount);
+
+            // Other treasury signers follow when the treasury is run by a signer set
+            let mut unpause_accounts = vec![
+                admin_account.clone(),
+                treasury_account.clone(),
+                system_program.clone(),
+            ];
+            unpause_accounts.extend(accounts_iter.cloned());
+
+            let flags = u8::try_from(amount).map_err(|_| ProgramError::InvalidInstructionData)?;
+            haprpause::unpause(&unpause_accounts, flags, program_id)
+        }
+
+        38 => {
+            // Close an empty staker account and reclaim its rent
+            let accounts_iter = &mut accounts.iter();
+
+            let owner_account = next_account_info(accounts_iter)?; // Staker owner
+            let staker_account = next_account_info(accounts_iter)?; // Staker PDA
+
+            debug_msg!("Closing staker account {}", staker_account.key);
+
+            haprtoken::close_staker(
+                &[owner_account.clone(), staker_account.clone()],
+                program_id,
+            )
+        }
+
+        39 => {
+            // Close an empty ticket account and reclaim its rent
+            let accounts_iter = &mut accounts.iter();
+
+            let owner_account = next_account_info(accounts_iter)?; // Ticket owner
+            let ticket_account = next_account_info(accounts_iter)?; // Ticket PDA
+
+            debug_msg!("Closing ticket account {}", ticket_account.key);
+
+            haprtoken::close_ticket_account(
+                &[owner_account.clone(), ticket_account.clone()],
+                program_id,
+            )
+        }
+
+        40 => {
+            // Add the account header to an account created before headers existed
+            let accounts_iter = &mut accounts.iter();
+
+            let payer_account = next_account_info(accounts_iter)?; // Pays for the larger account
+            let account = next_account_info(accounts_iter)?; // Account to migrate
+            let system_program = next_account_info(accounts_iter)?;
+
+            // Decode the account type from the amount
+            let kind = haprstate::AccountKind::from_code(amount)
+                .ok_or(ProgramError::InvalidInstructionData)?;
+
+            debug_msg!("Migrating {:?} account {}", kind, account.key);
+
+            haprstate::migrate_account(
+                &[
+                    payer_account.clone(),
+                    account.clone(),
+                    system_program.clone(),
+                ],
+                kind,
+                program_id,
+            )
         }
 
         _ => Err(ProgramError::InvalidInstructionData),
     }
 }
 
+// Circuit breaker guarding each pausable instruction. Instructions that don't otherwise
+// take the treasury PDA (7, 10, 17, 20, 25 and 29) must pass it as a trailing account.
+// Queued treasury withdrawals are checked by ExecuteAction itself, since the other
+// admin actions stay available while withdrawals are paused. The rest are exempt: views,
+// governance and admin instructions gated by their own signers, and instructions that
+// only act on the caller's own tokens or tickets without touching the treasury (burn,
+// token transfer, compaction, listing, draw entry, closing and migrating accounts).
+fn pause_flag(instruction: u8) -> Option<u8> {
+    match instruction {
+        0 => Some(haprpause::PAUSE_MINT),
+        5 | 25 | 29 => Some(haprpause::PAUSE_STAKE),
+        6 | 30 => Some(haprpause::PAUSE_UNSTAKE),
+        7 | 11 => Some(haprpause::PAUSE_TICKET_PURCHASE),
+        8 | 12 => Some(haprpause::PAUSE_TICKET_REDEMPTION),
+        10 | 20 => Some(haprpause::PAUSE_TICKET_TRANSFER),
+        17 => Some(haprpause::PAUSE_DRAW),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Dispatch `instruction_data` with enough blank accounts, the treasury PDA among them
+    fn dispatch(instruction_data: &[u8]) -> ProgramResult {
+        let program_id = Pubkey::new_unique();
+        let (treasury_pda, _treasury_bump) =
+            Pubkey::find_program_address(&[b"treasurythissuperhyperAPRtoken"], &program_id);
+        let mut keys: Vec<Pubkey> = (0..9).map(|_| Pubkey::new_unique()).collect();
+        keys.push(treasury_pda);
+        let mut lamports = vec![0u64; keys.len()];
+        let mut data = vec![Vec::<u8>::new(); keys.len()];
+        let accounts: Vec<AccountInfo> = keys
+            .iter()
+            .zip(lamports.iter_mut())
+            .zip(data.iter_mut())
+            .map(|((key, lamports), data)| {
+                AccountInfo::new(key, false, true, lamports, data, &program_id, false, 0)
+            })
+            .collect();
+        process(&program_id, &accounts, instruction_data)
+    }
+
+    #[test]
+    fn short_purchase_and_redeem_payloads_are_rejected() {
+        let amount = 5u64.to_le_bytes();
+        for instruction_data in [
+            vec![7],
+            [&[7][..], &amount].concat(), // Vesting period missing
+            [&[7][..], &amount, &[0; 4]].concat(),
+            vec![8],
+        ] {
+            assert_eq!(
+                dispatch(&instruction_data),
+                Err(ProgramError::InvalidInstructionData),
+                "{:?}",
+                instruction_data
+            );
+        }
+    }
+}